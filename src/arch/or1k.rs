@@ -50,6 +50,13 @@ pub const STACK_ALIGNMENT: usize = 4;
 #[repr(transparent)]
 pub struct StackPointer(*mut usize);
 
+impl StackPointer {
+  #[inline(always)]
+  pub fn as_ptr(self) -> *mut u8 {
+    self.0 as *mut u8
+  }
+}
+
 pub unsafe fn init(stack: &Stack, f: unsafe extern "C" fn(usize, StackPointer) -> !) -> StackPointer {
   #[naked]
   unsafe extern "C" fn trampoline_1() {
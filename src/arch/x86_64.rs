@@ -55,6 +55,13 @@ pub const STACK_ALIGNMENT: usize = 16;
 #[repr(transparent)]
 pub struct StackPointer(*mut usize);
 
+impl StackPointer {
+  #[inline(always)]
+  pub fn as_ptr(self) -> *mut u8 {
+    self.0 as *mut u8
+  }
+}
+
 pub unsafe fn init(stack: &Stack, f: unsafe extern "C" fn(usize, StackPointer) -> !) -> StackPointer {
   #[cfg(not(target_vendor = "apple"))]
   #[naked]
@@ -234,3 +241,29 @@ pub unsafe fn swap(arg: usize, new_sp: StackPointer,
     : "volatile", "alignstack");
   (ret, StackPointer(ret_sp))
 }
+
+/// Returns the stack pointer and the program counter that make the last `swap`
+/// into the stack with the base address `base` return in the context that performed it,
+/// as if the context on that stack had switched back to it. The first argument
+/// register has to hold the value to be returned.
+///
+/// This is used to abandon a context that cannot continue, e.g. from a signal handler.
+pub unsafe fn abandon(base: *mut u8) -> (usize, usize) {
+  #[naked]
+  unsafe extern "C" fn trampoline() {
+    asm!(
+      r#"
+        # This is the second half of `swap`, with the stack pointer of
+        # the parent context already loaded.
+        popq    %rbp
+        popq    %rax
+        jmpq    *%rax
+      "#
+      : : : : "volatile")
+  }
+
+  // The CFA slot holds the stack pointer of the context that switched to
+  // this stack most recently; see `init` and `swap`.
+  let parent_sp = *(base as *mut usize).offset(-4);
+  (parent_sp, trampoline as usize)
+}
@@ -0,0 +1,205 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::fmt;
+use alloc::boxed::Box;
+use self::std::collections::VecDeque;
+use self::std::panic;
+use self::std::sync::{Arc, Mutex, Condvar, Once};
+use self::std::thread;
+use self::std::time::Duration;
+#[cfg(unix)]
+use rt;
+use sync::{Park, Unpark, DefaultPark};
+
+/// The largest number of threads of the pool used by `blocking()`.
+pub const DEFAULT_MAX_THREADS: usize = 64;
+
+/// How long a thread of a pool waits for a call before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<FnMut() + Send>;
+
+/// A pool of threads that make blocking calls on behalf of tasks.
+///
+/// Threads are started as calls arrive, up to a limit, and exit once they have
+/// been idle for a while. Once the pool is dropped, its threads finish the calls
+/// they have been given, and exit.
+pub struct BlockingPool {
+  shared: Arc<Shared>
+}
+
+struct Shared {
+  state:       Mutex<State>,
+  available:   Condvar,
+  max_threads: usize
+}
+
+struct State {
+  jobs:     VecDeque<Job>,
+  threads:  usize,
+  /// The number of threads waiting for a job.
+  idle:     usize,
+  shutdown: bool
+}
+
+/// Runs `f` on a thread of a pool shared by the whole process, and returns its result.
+/// This is the way to make calls that block the thread, e.g. file I/O or name resolution,
+/// without stopping the other tasks.
+///
+/// In a task of a runtime or an executor from [rt](rt/index.html), the task is suspended
+/// until `f` returns, and the runtime runs other tasks in the meantime. Elsewhere,
+/// including in a [Simulation](rt/sim/struct.Simulation.html), `f` runs on the current
+/// thread. If `f` panics, the panic propagates to the caller.
+///
+/// If the task is unwound while it waits, e.g. by `rt::timeout()`, `f` still runs
+/// to completion, and its result is discarded.
+///
+/// Other driver loops built around `Generator::resume` can suspend the calling fiber
+/// instead of blocking the thread using `BlockingPool::run_with_park()`.
+///
+/// # Example
+///
+/// ```
+/// use std::fs;
+/// use fringe::rt;
+///
+/// rt::run(|| {
+///   let metadata = fringe::blocking(|| fs::metadata("Cargo.toml"));
+///   assert!(metadata.unwrap().is_file());
+/// });
+/// ```
+pub fn blocking<F, T>(f: F) -> T
+    where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+  static INIT: Once = Once::new();
+  static mut POOL: *const BlockingPool = 0 as *const BlockingPool;
+  unsafe {
+    INIT.call_once(|| POOL = Box::into_raw(Box::new(BlockingPool::new(DEFAULT_MAX_THREADS))));
+    (*POOL).run(f)
+  }
+}
+
+impl BlockingPool {
+  /// Creates a pool that runs up to `max_threads` threads.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `max_threads` is 0.
+  pub fn new(max_threads: usize) -> BlockingPool {
+    assert!(max_threads > 0, "a pool needs at least one thread");
+    BlockingPool {
+      shared: Arc::new(Shared {
+        state:       Mutex::new(State { jobs: VecDeque::new(), threads: 0, idle: 0, shutdown: false }),
+        available:   Condvar::new(),
+        max_threads: max_threads
+      })
+    }
+  }
+
+  /// Runs `f` on a thread of the pool, and returns its result; see `blocking()`.
+  pub fn run<F, T>(&self, f: F) -> T
+      where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    if !in_task() { return f() }
+    self.run_with_park::<DefaultPark, F, T>(f)
+  }
+
+  /// Same as `run`, but always runs `f` on a thread of the pool, and suspends
+  /// the current fiber using `P` until `f` returns, so that a driver loop built
+  /// around `Generator::resume` can keep resuming other generators in the meantime;
+  /// see [Park](sync/trait.Park.html).
+  pub fn run_with_park<P, F, T>(&self, f: F) -> T
+      where P: Park, P::Unparker: Send + 'static,
+            F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    let slot = Arc::new(Mutex::new(None));
+    let unparker = P::unparker();
+    {
+      let slot = slot.clone();
+      let mut f = Some(f);
+      submit(&self.shared, Box::new(move || {
+        let f = f.take().unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+        *slot.lock().unwrap() = Some(result);
+        unparker.unpark()
+      }));
+    }
+
+    loop {
+      let result = slot.lock().unwrap().take();
+      match result {
+        Some(Ok(value)) => return value,
+        Some(Err(payload)) => panic::resume_unwind(payload),
+        None => P::park()
+      }
+    }
+  }
+}
+
+/// Returns whether the current thread is running a task of a runtime or an executor.
+#[inline]
+fn in_task() -> bool {
+  #[cfg(unix)]
+  { rt::try_current().is_some() || rt::mt::try_current().is_some() }
+  #[cfg(not(unix))]
+  { false }
+}
+
+impl Drop for BlockingPool {
+  fn drop(&mut self) {
+    self.shared.state.lock().unwrap().shutdown = true;
+    self.shared.available.notify_all()
+  }
+}
+
+impl fmt::Debug for BlockingPool {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let state = self.shared.state.lock().unwrap();
+    f.debug_struct("BlockingPool")
+      .field("threads", &state.threads)
+      .field("max_threads", &self.shared.max_threads)
+      .finish()
+  }
+}
+
+/// Queues `job`, and starts a thread for it unless there is one waiting for it.
+fn submit(shared: &Arc<Shared>, job: Job) {
+  let mut state = shared.state.lock().unwrap();
+  state.jobs.push_back(job);
+  if state.idle > 0 { shared.available.notify_one() }
+  if state.jobs.len() <= state.idle || state.threads == shared.max_threads { return }
+
+  let worker = shared.clone();
+  match thread::Builder::new().name("fringe-blocking".into()).spawn(move || worker.work()) {
+    Ok(_) => state.threads += 1,
+    // The threads that are running will get to the job eventually.
+    Err(_) if state.threads > 0 => (),
+    Err(err) => panic!("cannot spawn a thread for blocking calls: {}", err)
+  }
+}
+
+impl Shared {
+  /// Runs the jobs of the pool until it is dropped, or no job arrives for a while.
+  fn work(&self) {
+    let mut state = self.state.lock().unwrap();
+    loop {
+      if let Some(mut job) = state.jobs.pop_front() {
+        drop(state);
+        job();
+        state = self.state.lock().unwrap();
+        continue
+      }
+      if state.shutdown { break }
+
+      state.idle += 1;
+      let (next, timeout) = self.available.wait_timeout(state, KEEP_ALIVE).unwrap();
+      state = next;
+      state.idle -= 1;
+      if timeout.timed_out() && state.jobs.is_empty() { break }
+    }
+    state.threads -= 1
+  }
+}
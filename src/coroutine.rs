@@ -0,0 +1,369 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Symmetric coroutines.
+//!
+//! Unlike a generator, which always returns control to its resumer, a coroutine
+//! can transfer control directly to any other suspended coroutine.
+
+use core::marker::PhantomData;
+use core::{ptr, mem};
+use core::cell::Cell;
+use core::mem::ManuallyDrop;
+
+use stack;
+use debug;
+use unwind;
+#[cfg(feature = "std")]
+use current;
+use arch::{self, StackPointer};
+use generator::{GeneratorState, State};
+
+/// Coroutine wraps a function that can transfer control to other coroutines.
+///
+/// A thread enters a group of coroutines by calling `resume(value)` on one of them,
+/// which becomes the *root* of the group until control returns to it.
+/// The first time a coroutine is switched to, its function is called as
+/// `f(switcher, value)`. It can then call `switcher.transfer(&other, value)`, which
+/// suspends it and switches directly to `other`; `other` receives `value` as
+/// the result of its own pending `transfer()` call, or as the argument of its function.
+/// When some coroutine calls `switcher.suspend(value)`, control returns to the root,
+/// and `resume()` returns `Some(Yielded(value))`. When the function of some coroutine
+/// returns `value`, control returns to the root as well, and `resume()` returns
+/// `Some(Complete(value))`.
+///
+/// Coroutine stacks are linked together on every transfer, so a backtrace taken in
+/// a coroutine shows the chain of transfers that led to it, up to the root.
+///
+/// If the function of a coroutine panics, the panic is caught on its stack and
+/// propagated through the `resume()` call of the root. A coroutine that is dropped
+/// before its function returns is cancelled, as with the default
+/// [drop policy](../generator/enum.DropPolicy.html) of generators.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{OsStack, Coroutine};
+/// use fringe::generator::GeneratorState;
+///
+/// let pong = Coroutine::new(OsStack::new(1 << 16).unwrap(), |switcher, mut n: u32| {
+///   loop { n = switcher.suspend(n + 1) }
+/// });
+/// let ping = Coroutine::new(OsStack::new(1 << 16).unwrap(), |switcher, n: u32| {
+///   // Control goes straight from ping to pong, and from pong back to the root.
+///   switcher.transfer(&pong, n * 10)
+/// });
+/// assert_eq!(ping.resume(1), Some(GeneratorState::Yielded(11)));
+/// ```
+#[derive(Debug)]
+pub struct Coroutine<'a, T: 'a, Stack: stack::Stack> {
+  slot:     Slot,
+  stack:    ManuallyDrop<Stack>,
+  stack_id: ManuallyDrop<debug::StackId>,
+  phantom:  PhantomData<(&'a (), *mut T)>
+}
+
+/// The part of a coroutine, or of a root, that other contexts switch to.
+#[derive(Debug)]
+struct Slot {
+  state:     Cell<State>,
+  /// The stack pointer of the context while it is suspended, and `None` while
+  /// it is running or has finished.
+  stack_ptr: Cell<Option<StackPointer>>,
+  /// The limit address of the stack the context runs on, which becomes the current
+  /// one whenever the context is switched to.
+  #[cfg(feature = "std")]
+  limit:     usize
+}
+
+impl Slot {
+  /// Creates the slot of a coroutine running on `stack`, or of a root running
+  /// on the current stack if `stack` is `None`.
+  fn new(state: State, stack: Option<&stack::Stack>) -> Slot {
+    #[cfg(not(feature = "std"))]
+    let _ = stack;
+    Slot {
+      state:     Cell::new(state),
+      stack_ptr: Cell::new(None),
+      #[cfg(feature = "std")]
+      limit:     stack.map_or_else(current::stack_limit, |stack| stack.limit() as usize)
+    }
+  }
+}
+
+/// What is carried from one context to another on every switch.
+enum Payload<T> {
+  Value(T),
+  /// The coroutine is being dropped, and has to unwind its stack.
+  Cancel,
+  /// The function of a coroutine has returned, panicked, or has been cancelled.
+  /// This is only ever sent to the root.
+  Complete(Result<T, unwind::Unwound>)
+}
+
+/// The value passed, by pointer, to `arch::swap` on every switch.
+struct Envelope<T> {
+  payload: Payload<T>,
+  /// The context that is switching. It has to be suspended at the stack pointer
+  /// returned by `arch::swap`; it is null if the context has finished.
+  from:    *const Slot,
+  /// The context that is being switched to.
+  to:      *const Slot,
+  /// The root of the group of coroutines.
+  root:    *const Slot
+}
+
+impl<T> Envelope<T> {
+  /// Switches to `to`, which has to be suspended, passing this envelope, and returns
+  /// the envelope passed to the switching context once it is switched to again.
+  #[inline(always)]
+  unsafe fn send(self, stack: Option<&stack::Stack>) -> Envelope<T> {
+    let stack_ptr = (*self.to).stack_ptr.take().expect("switched to a Coroutine that is not suspended");
+    if let Some(stack) = stack { stack.check_overflow(stack_ptr.as_ptr()) }
+
+    #[cfg(feature = "std")]
+    current::replace_stack_limit((*self.to).limit);
+    let (data, stack_ptr) = arch::swap(&self as *const Envelope<T> as usize, stack_ptr, stack);
+    mem::forget(self);
+
+    let envelope = ptr::read(data as *const Envelope<T>);
+    if !envelope.from.is_null() { (*envelope.from).stack_ptr.set(Some(stack_ptr)) }
+    envelope
+  }
+}
+
+impl<'a, T, Stack> Coroutine<'a, T, Stack> where T: 'a, Stack: stack::Stack {
+  /// Creates a new coroutine.
+  ///
+  /// See also the [contract](../trait.GuardedStack.html) that needs to be fulfilled by `stack`.
+  pub fn new<F>(stack: Stack, f: F) -> Coroutine<'a, T, Stack>
+      where Stack: stack::GuardedStack + 'static,
+            F: FnOnce(&Switcher<T>, T) -> T + 'a {
+    unsafe { Coroutine::unsafe_new(stack, f) }
+  }
+
+  /// Same as `new`, but does not require `stack` to have a guard page.
+  ///
+  /// This function is unsafe because the coroutine function can easily violate
+  /// memory safety by overflowing the stack. It is useful in environments where
+  /// guarded stacks do not exist, e.g. in absence of an MMU.
+  ///
+  /// See also the [contract](../trait.Stack.html) that needs to be fulfilled by `stack`.
+  pub unsafe fn unsafe_new<F>(stack: Stack, f: F) -> Coroutine<'a, T, Stack>
+      where F: FnOnce(&Switcher<T>, T) -> T + 'a {
+    unsafe extern "C" fn coroutine_wrapper<T, F>(env: usize, stack_ptr: StackPointer) -> !
+        where F: FnOnce(&Switcher<T>, T) -> T {
+      // Retrieve our environment from the callee and return control to it.
+      let f = ptr::read(env as *const F);
+      let (data, stack_ptr) = arch::swap(0, stack_ptr, None);
+      let envelope = ptr::read(data as *const Envelope<T>);
+      (*envelope.from).stack_ptr.set(Some(stack_ptr));
+
+      let switcher = Switcher::new(&envelope);
+      let result = match envelope.payload {
+        Payload::Value(value) => unwind::catch(|| f(&switcher, value)),
+        Payload::Cancel => {
+          // The coroutine was cancelled before it was ever switched to.
+          drop(f);
+          Err(unwind::Unwound::Cancelled)
+        }
+        Payload::Complete(_) => unreachable!()
+      };
+      switcher.complete(result)
+    }
+
+    let stack_id  = debug::StackId::register(&stack);
+    let stack_ptr = arch::init(&stack, coroutine_wrapper::<T, F>);
+
+    // Transfer environment to the callee.
+    let stack_ptr = arch::swap(&f as *const F as usize, stack_ptr, Some(&stack)).1;
+    mem::forget(f);
+
+    let slot = Slot::new(State::Runnable, Some(&stack));
+    slot.stack_ptr.set(Some(stack_ptr));
+    Coroutine {
+      slot:     slot,
+      stack:    ManuallyDrop::new(stack),
+      stack_id: ManuallyDrop::new(stack_id),
+      phantom:  PhantomData
+    }
+  }
+
+  /// Enters the group of coroutines this coroutine belongs to, switching to it
+  /// and passing it `value`, and returns once some coroutine suspends itself
+  /// or its function returns. If this coroutine has already finished, returns `None`.
+  /// If the function of some coroutine panics, propagates the panic.
+  ///
+  /// This function panics if the coroutine is not suspended, i.e. if it is
+  /// called from within the group of coroutines that it belongs to.
+  pub fn resume(&self, value: T) -> Option<GeneratorState<T, T>> {
+    if self.slot.state.get() != State::Runnable { return None }
+
+    let root = Slot::new(State::Runnable, None);
+    let envelope = Envelope {
+      payload: Payload::Value(value),
+      from:    &root,
+      to:      &self.slot,
+      root:    &root
+    };
+    match unsafe { envelope.send(Some(&*self.stack)).payload } {
+      Payload::Value(value) => Some(GeneratorState::Yielded(value)),
+      Payload::Complete(Ok(value)) => Some(GeneratorState::Complete(value)),
+      Payload::Complete(Err(unwind::Unwound::Panicked(payload))) => unwind::resume(payload),
+      Payload::Complete(Err(_)) | Payload::Cancel => unreachable!()
+    }
+  }
+
+  /// Returns the state of the coroutine.
+  #[inline]
+  pub fn state(&self) -> State { self.slot.state.get() }
+
+  /// Cancels the coroutine, unwinding its stack, if it has not finished.
+  #[cfg(feature = "std")]
+  fn cancel(&mut self) {
+    if self.slot.state.get() != State::Runnable { return }
+
+    let root = Slot::new(State::Runnable, None);
+    let envelope = Envelope {
+      payload: Payload::Cancel::<T>,
+      from:    &root,
+      to:      &self.slot,
+      root:    &root
+    };
+    match unsafe { envelope.send(Some(&*self.stack)).payload } {
+      Payload::Complete(Err(unwind::Unwound::Cancelled)) => (),
+      // The coroutine function caught the unwinding and returned normally.
+      Payload::Complete(Ok(value)) => drop(value),
+      // The coroutine function caught the unwinding and panicked afterwards.
+      Payload::Complete(Err(unwind::Unwound::Panicked(payload))) => unwind::resume(payload),
+      // The coroutine function caught the unwinding and suspended itself, or
+      // transferred control to a coroutine that did.
+      Payload::Value(_) => panic!("cancelled Coroutine suspended itself"),
+      Payload::Complete(Err(unwind::Unwound::Overflowed)) | Payload::Cancel => unreachable!()
+    }
+  }
+
+  /// Extracts the stack from a coroutine when its function has returned.
+  /// If the function has not returned (i.e. `self.state() == State::Runnable`), panics.
+  pub fn unwrap(self) -> Stack {
+    match self.slot.state.get() {
+      State::Unavailable => unsafe {
+        let mut this = ManuallyDrop::new(self);
+        ManuallyDrop::drop(&mut this.stack_id);
+        ptr::read(&*this.stack)
+      },
+      _ => {
+        mem::forget(self);
+        panic!("Argh! Bastard! Don't touch that!")
+      }
+    }
+  }
+}
+
+impl<'a, T, Stack> Drop for Coroutine<'a, T, Stack> where T: 'a, Stack: stack::Stack {
+  fn drop(&mut self) {
+    if self.slot.state.get() == State::Runnable {
+      #[cfg(feature = "std")]
+      self.cancel();
+      #[cfg(not(feature = "std"))]
+      unsafe {
+        ManuallyDrop::drop(&mut self.stack_id);
+        panic!("dropped unfinished Coroutine")
+      }
+    }
+
+    unsafe {
+      ManuallyDrop::drop(&mut self.stack_id);
+      ManuallyDrop::drop(&mut self.stack)
+    }
+  }
+}
+
+/// Switcher is an interface provided to every coroutine through which it
+/// transfers control to other coroutines.
+#[derive(Debug)]
+pub struct Switcher<T> {
+  /// The slot of the coroutine, which is updated every time the coroutine is
+  /// switched to, since the coroutine may have been moved while it was suspended.
+  own:       Cell<*const Slot>,
+  root:      Cell<*const Slot>,
+  cancelled: Cell<bool>,
+  phantom:   PhantomData<*mut T>
+}
+
+impl<T> Switcher<T> {
+  fn new(envelope: &Envelope<T>) -> Switcher<T> {
+    Switcher {
+      own:       Cell::new(envelope.to),
+      root:      Cell::new(envelope.root),
+      cancelled: Cell::new(false),
+      phantom:   PhantomData
+    }
+  }
+
+  #[inline(always)]
+  fn switch(&self, payload: Payload<T>, to: *const Slot, stack: Option<&stack::Stack>) -> T {
+    if self.cancelled.get() {
+      // The coroutine function caught the unwinding; don't let it switch.
+      drop(payload);
+      unwind::cancel()
+    }
+
+    let envelope = Envelope {
+      payload: payload,
+      from:    self.own.get(),
+      to:      to,
+      root:    self.root.get()
+    };
+    let envelope = unsafe { envelope.send(stack) };
+    self.own.set(envelope.to);
+    self.root.set(envelope.root);
+    match envelope.payload {
+      Payload::Value(value) => value,
+      Payload::Cancel => {
+        self.cancelled.set(true);
+        unwind::cancel()
+      }
+      Payload::Complete(_) => unreachable!()
+    }
+  }
+
+  /// Suspends the coroutine and transfers control to `to`, passing it `value`.
+  /// Returns the value passed by the context that switches back to this coroutine.
+  ///
+  /// This function panics if `to` is not suspended, e.g. if it is the current
+  /// coroutine, or if its function has returned.
+  pub fn transfer<S: stack::Stack>(&self, to: &Coroutine<T, S>, value: T) -> T {
+    if to.slot.state.get() != State::Runnable {
+      panic!("transferred control to a finished Coroutine")
+    }
+    self.switch(Payload::Value(value), &to.slot, Some(&*to.stack))
+  }
+
+  /// Suspends the coroutine and returns control to the root, whose `resume()`
+  /// call returns `Some(Yielded(value))`. Returns the value passed by the context
+  /// that switches back to this coroutine.
+  pub fn suspend(&self, value: T) -> T {
+    self.switch(Payload::Value(value), self.root.get(), None)
+  }
+
+  /// Passes the result of the coroutine function to the root. The coroutine
+  /// is never switched to again.
+  fn complete(&self, result: Result<T, unwind::Unwound>) -> ! {
+    unsafe {
+      (*self.own.get()).state.set(State::Unavailable);
+      let envelope = Envelope {
+        payload: Payload::Complete(result),
+        from:    ptr::null(),
+        to:      self.root.get(),
+        root:    self.root.get()
+      };
+      envelope.send(None);
+    }
+    unreachable!("switched to a finished Coroutine")
+  }
+}
@@ -0,0 +1,80 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Bookkeeping of the stack the current thread is executing on.
+//!
+//! `Generator::resume` records the limit of the generator stack, and the fiber-local
+//! values of the generator, before switching to it, and restores the previous values
+//! once the generator suspends. The address of the first record also identifies
+//! the current thread.
+
+use core::ptr;
+use core::cell::Cell;
+use local::Locals;
+
+#[thread_local]
+static STACK_LIMIT: Cell<usize> = Cell::new(0);
+
+/// The limit address of the stack the current thread was created with, once known.
+#[thread_local]
+static THREAD_STACK_LIMIT: Cell<usize> = Cell::new(0);
+
+#[thread_local]
+static LOCALS: Cell<*const Locals> = Cell::new(ptr::null());
+
+/// Returns the limit address of the generator stack the current thread is executing on,
+/// or 0 if it is executing on the stack it was created with.
+#[inline(always)]
+pub fn stack_limit() -> usize {
+  STACK_LIMIT.get()
+}
+
+/// Sets the limit address of the stack the current thread is executing on,
+/// and returns the previous one.
+#[inline(always)]
+pub fn replace_stack_limit(limit: usize) -> usize {
+  STACK_LIMIT.replace(limit)
+}
+
+/// Returns the limit address of the stack the current thread was created with,
+/// or 0 if it has not been recorded yet.
+#[inline(always)]
+pub fn thread_stack_limit() -> usize {
+  THREAD_STACK_LIMIT.get()
+}
+
+/// Records the limit address of the stack the current thread was created with.
+#[inline(always)]
+pub fn set_thread_stack_limit(limit: usize) {
+  THREAD_STACK_LIMIT.set(limit)
+}
+
+// A generator created by Generator::new_send() may be resumed on another thread, so
+// the address of LOCALS must not be kept across a suspension in a caller; LocalKey::with,
+// which is generic and inlined into its callers, reads it only through these.
+
+/// Returns the fiber-local values of the generator the current thread is executing,
+/// or null if it is not executing a generator.
+#[inline(never)]
+pub fn locals() -> *const Locals {
+  LOCALS.get()
+}
+
+/// Sets the fiber-local values of the generator the current thread is executing,
+/// and returns the previous ones.
+#[inline(never)]
+pub fn replace_locals(locals: *const Locals) -> *const Locals {
+  LOCALS.replace(locals)
+}
+
+/// Returns a value that identifies the current thread among the threads that are alive.
+/// It is never 0.
+#[cfg(debug_assertions)]
+#[inline(always)]
+pub fn thread_id() -> usize {
+  &STACK_LIMIT as *const Cell<usize> as usize
+}
@@ -12,34 +12,110 @@
 //! afterwards.
 
 use core::marker::PhantomData;
+use core::ops::Deref;
 use core::{ptr, mem};
 use core::cell::Cell;
 use core::mem::ManuallyDrop;
+#[cfg(feature = "std")]
+use core::any::Any;
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
 
 use stack;
 use debug;
+use unwind;
+#[cfg(feature = "std")]
+use current;
+#[cfg(feature = "std")]
+use local::{self, Locals};
 use arch::{self, StackPointer};
 
+/// The result of resuming a generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorState<Yield, Return> {
+  /// The generator suspended itself through `yielder.suspend(item)`.
+  Yielded(Yield),
+  /// The generator function has returned.
+  Complete(Return)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
   /// Generator can be resumed. This is the initial state.
   Runnable,
   /// Generator cannot be resumed. This is the state of the generator after
   /// the generator function has returned or panicked.
-  Unavailable
+  Unavailable,
+  /// Generator cannot be resumed, and its stack will never be reused. This is the state
+  /// of the generator after it has overflowed its stack with the overflow policy
+  /// `OverflowPolicy::Poison`, or after its stack has detected an overflow through
+  /// [Stack::check_overflow](../trait.Stack.html#method.check_overflow).
+  Poisoned
+}
+
+/// What happens to a generator that is dropped while its state is `State::Runnable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+  /// The generator is cancelled, unwinding its stack and running destructors
+  /// of all values held by the generator function, as if by `cancel()`.
+  /// This is the default when the `std` feature is enabled.
+  #[cfg(feature = "std")]
+  Unwind,
+  /// The generator stack is leaked, and no destructors are run.
+  Leak,
+  /// The process is aborted.
+  Abort,
+  /// The stack is leaked, and the thread panics.
+  /// This is the default when the `std` feature is disabled.
+  Panic
+}
+
+impl Default for DropPolicy {
+  #[cfg(feature = "std")]
+  fn default() -> DropPolicy { DropPolicy::Unwind }
+
+  #[cfg(not(feature = "std"))]
+  fn default() -> DropPolicy { DropPolicy::Panic }
 }
 
+/// What happens when a generator overflows its stack, provided that the stack is
+/// an [OsStack](../struct.OsStack.html) and the [overflow handler](../fn.install_overflow_handler.html)
+/// is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+  /// The overflow is reported and the process is aborted. This is the default.
+  Abort,
+  /// The generator is abandoned without running any destructors, and the pending
+  /// `resume()` call fails with `StackOverflow`. The state of the generator
+  /// becomes `State::Poisoned`, and its stack is leaked when it is dropped.
+  ///
+  /// This is only supported on x86_64 Linux; elsewhere, it is the same as `Abort`.
+  Poison
+}
+
+impl Default for OverflowPolicy {
+  fn default() -> OverflowPolicy { OverflowPolicy::Abort }
+}
+
+/// The panic payload with which `resume()` fails if the generator has overflowed
+/// its stack and has been abandoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackOverflow;
+
 /// Generator wraps a function and allows suspending its execution more than once, returning
 /// a value each time.
 ///
 /// The first time `resume(input0)` is called, the function is called as `f(yielder, input0)`.
 /// It runs until it suspends its execution through `yielder.suspend(output0)`, after which
-/// `resume(input0)` returns `output0`. The function can be resumed again using `resume(input1)`,
-/// after which `yielder.suspend(output0)` returns `input1`, and so on. Once the function returns,
-/// the `resume()` call will return `None`, and it will return `None` every time it is called
-/// after that.
+/// `resume(input0)` returns `Some(Yielded(output0))`. The function can be resumed again using
+/// `resume(input1)`, after which `yielder.suspend(output0)` returns `input1`, and so on.
+/// Once the function returns a value, the `resume()` call will return `Some(Complete(value))`,
+/// and it will return `None` every time it is called after that.
 ///
 /// If the generator function panics, the panic is propagated through the `resume()` call as usual.
+/// When the `std` feature is enabled, the panic is caught on the generator stack and resumed
+/// on the stack of the caller, so the unwinder never has to cross the boundary between them;
+/// `try_resume()` returns the panic payload instead of propagating the panic.
 ///
 /// After the generator function returns or panics, it is safe to reclaim the generator stack
 /// using `unwrap()`.
@@ -48,8 +124,20 @@ pub enum State {
 /// the state is `State::Runnable` after creation and suspension, and `State::Unavailable`
 /// once the generator function returns or panics.
 ///
+/// A generator that has not finished can be cancelled using `cancel()`, which makes
+/// the pending `yielder.suspend()` call unwind the generator stack. What happens when such
+/// a generator is dropped is determined by its [drop policy](enum.DropPolicy.html).
+/// What happens when it overflows its stack is determined by its
+/// [overflow policy](enum.OverflowPolicy.html).
+///
 /// When the input type is `()`, a generator implements the Iterator trait.
 ///
+/// A generator cannot be sent to another thread, since the generator function may
+/// hold references to thread-local values while it is suspended. When the `std` feature
+/// is enabled and debug assertions are on, resuming a generator on a thread other than
+/// the one that created it panics. Generators that can be sent to another thread are
+/// created using [new_send](#method.new_send), which is unsafe.
+///
 /// # Example
 ///
 /// ```
@@ -57,13 +145,17 @@ pub enum State {
 ///
 /// let stack = OsStack::new(0).unwrap();
 /// let mut add_one = Generator::new(stack, move |yielder, mut input| {
+///   let mut count = 0;
 ///   loop {
 ///     if input == 0 { break }
-///     input = yielder.suspend(input + 1)
+///     input = yielder.suspend(input + 1);
+///     count += 1
 ///   }
+///   count
 /// });
-/// println!("{:?}", add_one.resume(2)); // prints Some(3)
-/// println!("{:?}", add_one.resume(3)); // prints Some(4)
+/// println!("{:?}", add_one.resume(2)); // prints Some(Yielded(3))
+/// println!("{:?}", add_one.resume(3)); // prints Some(Yielded(4))
+/// println!("{:?}", add_one.resume(0)); // prints Some(Complete(2))
 /// println!("{:?}", add_one.resume(0)); // prints None
 /// ```
 ///
@@ -71,37 +163,103 @@ pub enum State {
 ///
 /// ```
 /// use fringe::{OsStack, Generator};
-/// use std::mem;
 ///
-/// let stack = OsStack::new(0).unwrap();
+/// let stack = OsStack::new(1 << 16).unwrap();
 /// let mut nat = Generator::new(stack, move |yielder, ()| {
 ///   for i in 1.. { yielder.suspend(i) }
 /// });
 /// println!("{:?}", nat.next()); // prints Some(0)
 /// println!("{:?}", nat.next()); // prints Some(1)
 /// println!("{:?}", nat.next()); // prints Some(2)
-/// mem::forget(nat); // we can't drop a running Generator, so we leak it
+/// drop(nat); // unwinds the generator stack
 /// ```
 #[derive(Debug)]
-pub struct Generator<'a, Input: 'a, Output: 'a, Stack: stack::Stack> {
+pub struct Generator<'a, Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a = ()> {
   state:     State,
+  policy:    DropPolicy,
+  name:      Option<&'static str>,
+  overflow:  OverflowPolicy,
   stack:     ManuallyDrop<Stack>,
   stack_id:  ManuallyDrop<debug::StackId>,
   stack_ptr: arch::StackPointer,
-  phantom:   PhantomData<(&'a (), *mut Input, *const Output)>
+  /// The thread the generator is bound to, or 0 if it can be resumed on any thread.
+  #[cfg(all(feature = "std", debug_assertions))]
+  thread:    usize,
+  #[cfg(feature = "std")]
+  locals:    Locals,
+  phantom:   PhantomData<(&'a (), *mut Input, *const Output, *const Return)>
 }
 
-impl<'a, Input, Output, Stack> Generator<'a, Input, Output, Stack>
-    where Input: 'a, Output: 'a, Stack: stack::Stack {
+/// The value passed to the resumer once the generator function returns, panics,
+/// or is cancelled.
+///
+/// It starts with an `Option<Output>` that is always `None`, so that the resumer
+/// can tell it apart from a value passed by `Yielder::suspend_bare`.
+#[repr(C)]
+struct Complete<Output, Return> {
+  yielded: Option<Output>,
+  result:  Result<Return, unwind::Unwound>
+}
+
+impl<'a, Input, Output, Stack, Return> Generator<'a, Input, Output, Stack, Return>
+    where Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a {
   /// Creates a new generator.
   ///
   /// See also the [contract](../trait.GuardedStack.html) that needs to be fulfilled by `stack`.
-  pub fn new<F>(stack: Stack, f: F) -> Generator<'a, Input, Output, Stack>
+  pub fn new<F>(stack: Stack, f: F) -> Generator<'a, Input, Output, Stack, Return>
       where Stack: stack::GuardedStack + 'static,
-            F: FnOnce(&Yielder<Input, Output>, Input) + 'a {
+            F: FnOnce(&Yielder<Input, Output>, Input) -> Return + 'a {
     unsafe { Generator::unsafe_new(stack, f) }
   }
 
+  /// Same as `new`, but the returned generator can be sent to another thread while
+  /// it is suspended, and resumed there.
+  ///
+  /// In debug builds, resuming a generator created using `new` on a thread other than
+  /// the one it was created on panics; this check is skipped for generators created
+  /// using `new_send`, which is why the caller has to uphold the contract below.
+  ///
+  /// # Safety
+  ///
+  /// Whatever the generator function holds across a call to `yielder.suspend()` must be
+  /// `Send`, since the generator may be resumed on another thread afterwards. In particular,
+  /// it must not hold across such a call:
+  ///
+  ///   * references to thread-local values, such as the one passed to the closure of
+  ///     `LocalKey::with`, which refer to the values of the thread that has created them,
+  ///     and dangle once that thread exits;
+  ///   * values that are not `Send`, such as an `Rc`, a `std::sync::MutexGuard`,
+  ///     or a reference to a `Cell`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use std::thread;
+  /// use fringe::{OsStack, Generator};
+  ///
+  /// let stack = OsStack::new(1 << 16).unwrap();
+  /// // The generator function holds nothing but an integer across suspensions.
+  /// let mut gen = unsafe {
+  ///   Generator::new_send(stack, move |yielder, ()| {
+  ///     for i in 1.. { yielder.suspend(i) }
+  ///   })
+  /// };
+  /// assert_eq!(gen.next(), Some(1));
+  /// let mut gen = thread::spawn(move || {
+  ///   assert_eq!(gen.next(), Some(2));
+  ///   gen
+  /// }).join().unwrap();
+  /// assert_eq!(gen.next(), Some(3));
+  /// ```
+  pub unsafe fn new_send<F>(stack: Stack, f: F) -> SendGenerator<'a, Input, Output, Stack, Return>
+      where Input: Send, Output: Send, Return: Send,
+            Stack: stack::GuardedStack + Send + 'static,
+            F: FnOnce(&Yielder<Input, Output>, Input) -> Return + Send + 'a {
+    let mut generator = Generator::unsafe_new(stack, f);
+    generator.unbind_thread();
+    SendGenerator(generator)
+  }
+
   /// Same as `new`, but does not require `stack` to have a guard page.
   ///
   /// This function is unsafe because the generator function can easily violate
@@ -109,24 +267,50 @@ impl<'a, Input, Output, Stack> Generator<'a, Input, Output, Stack>
   /// guarded stacks do not exist, e.g. in absence of an MMU.
   ///
   /// See also the [contract](../trait.Stack.html) that needs to be fulfilled by `stack`.
-  pub unsafe fn unsafe_new<F>(stack: Stack, f: F) -> Generator<'a, Input, Output, Stack>
-      where F: FnOnce(&Yielder<Input, Output>, Input) + 'a {
-    unsafe extern "C" fn generator_wrapper<Input, Output, Stack, F>(env: usize, stack_ptr: StackPointer) -> !
-        where Stack: stack::Stack, F: FnOnce(&Yielder<Input, Output>, Input) {
+  pub unsafe fn unsafe_new<F>(stack: Stack, f: F) -> Generator<'a, Input, Output, Stack, Return>
+      where F: FnOnce(&Yielder<Input, Output>, Input) -> Return + 'a {
+    unsafe extern "C" fn generator_wrapper<Input, Output, Stack, Return, F>(env: usize, stack_ptr: StackPointer) -> !
+        where Stack: stack::Stack, F: FnOnce(&Yielder<Input, Output>, Input) -> Return {
+      // The frame of generator_body holds the generator function, its input and its result,
+      // which can be large enough to jump over the guard area entirely. On x86 and x86_64,
+      // the compiler probes every frame larger than a page already. Elsewhere, touch
+      // the pages the frame will occupy first, so that it faults in the guard area
+      // if it does not fit.
+      #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+      probe_stack(mem::size_of::<F>() + mem::size_of::<Input>() +
+                  mem::size_of::<Complete<Output, Return>>());
+      generator_body::<Input, Output, Stack, Return, F>(env, stack_ptr)
+    }
+
+    #[inline(never)]
+    unsafe fn generator_body<Input, Output, Stack, Return, F>(env: usize, stack_ptr: StackPointer) -> !
+        where Stack: stack::Stack, F: FnOnce(&Yielder<Input, Output>, Input) -> Return {
       // Retrieve our environment from the callee and return control to it.
       let f = ptr::read(env as *const F);
       let (data, stack_ptr) = arch::swap(0, stack_ptr, None);
+      let yielder = Yielder::new(stack_ptr);
+      if data == 0 {
+        // The generator was cancelled before it was ever resumed.
+        drop(f);
+        yielder.complete::<Return>(Err(unwind::Unwound::Cancelled))
+      }
       // See the second half of Yielder::suspend_bare.
       let input = ptr::read(data as *const Input);
-      // Run the body of the generator.
-      let yielder = Yielder::new(stack_ptr);
-      f(&yielder, input);
-      // Past this point, the generator has dropped everything it has held.
-      loop { yielder.suspend_bare(None); }
+      // Run the body of the generator, catching any panics.
+      let result = unwind::catch(|| f(&yielder, input));
+      // Destroy the fiber-local values while they can still refer to each other.
+      #[cfg(feature = "std")]
+      let result = {
+        let cleared = unwind::catch(local::clear_current);
+        result.and_then(|value| cleared.map(|()| value))
+      };
+      // Past this point, the generator has dropped everything it has held,
+      // except for the return value or panic payload, which is moved out by the resumer.
+      yielder.complete::<Return>(result)
     }
 
     let stack_id  = debug::StackId::register(&stack);
-    let stack_ptr = arch::init(&stack, generator_wrapper::<Input, Output, Stack, F>);
+    let stack_ptr = arch::init(&stack, generator_wrapper::<Input, Output, Stack, Return, F>);
 
     // Transfer environment to the callee.
     let stack_ptr = arch::swap(&f as *const F as usize, stack_ptr, Some(&stack)).1;
@@ -134,55 +318,247 @@ impl<'a, Input, Output, Stack> Generator<'a, Input, Output, Stack>
 
     Generator {
       state:     State::Runnable,
+      policy:    DropPolicy::default(),
+      name:      None,
+      overflow:  OverflowPolicy::default(),
       stack:     ManuallyDrop::new(stack),
       stack_id:  ManuallyDrop::new(stack_id),
       stack_ptr: stack_ptr,
+      #[cfg(all(feature = "std", debug_assertions))]
+      thread:    current::thread_id(),
+      #[cfg(feature = "std")]
+      locals:    Locals::new(),
       phantom:   PhantomData
     }
   }
 
-  /// Resumes the generator and return the next value it yields.
-  /// If the generator function has returned, returns `None`.
+  /// Resumes the generator and returns the next value it yields, or the value
+  /// the generator function returns.
+  /// If the generator function has already returned, returns `None`.
+  /// If the generator function panics, propagates the panic.
   #[inline]
-  pub fn resume(&mut self, input: Input) -> Option<Output> {
+  pub fn resume(&mut self, input: Input) -> Option<GeneratorState<Output, Return>> {
+    match self.resume_bare(input) {
+      None => None,
+      Some(Ok(val)) => Some(val),
+      Some(Err(unwind::Unwound::Panicked(payload))) => unwind::resume(payload),
+      Some(Err(unwind::Unwound::Overflowed)) => unwind::overflow(),
+      Some(Err(unwind::Unwound::Cancelled)) => unreachable!()
+    }
+  }
+
+  /// Same as `resume`, but if the generator function panics, returns the panic
+  /// payload instead of propagating the panic. If the generator has overflowed
+  /// its stack and has been abandoned, the payload is `StackOverflow`.
+  #[cfg(feature = "std")]
+  #[inline]
+  pub fn try_resume(&mut self, input: Input)
+      -> Result<Option<GeneratorState<Output, Return>>, Box<Any + Send>> {
+    match self.resume_bare(input) {
+      None => Ok(None),
+      Some(Ok(val)) => Ok(Some(val)),
+      Some(Err(unwind::Unwound::Panicked(payload))) => Err(payload),
+      Some(Err(unwind::Unwound::Overflowed)) => Err(Box::new(StackOverflow)),
+      Some(Err(unwind::Unwound::Cancelled)) => unreachable!()
+    }
+  }
+
+  #[inline(always)]
+  fn resume_bare(&mut self, input: Input)
+      -> Option<Result<GeneratorState<Output, Return>, unwind::Unwound>> {
     match self.state {
       State::Runnable => {
+        self.check_thread();
+
         // Set the state to Unavailable. Since we have exclusive access to the generator,
         // the only case where this matters is the generator function panics, after which
         // it must not be invocable again.
         self.state = State::Unavailable;
 
-        // Switch to the generator function, and retrieve the yielded value.
+        // Switch to the generator function, and retrieve the yielded or returned value.
         let val = unsafe {
-          let (data_out, stack_ptr) = arch::swap(&input as *const Input as usize, self.stack_ptr, Some(&*self.stack));
-          self.stack_ptr = stack_ptr;
+          let val = self.switch(&input as *const Input as usize);
           mem::forget(input);
-          ptr::read(data_out as *const Option<Output>)
+          val
         };
 
         // Unless the generator function has returned, it can be switched to again, so
         // set the state to Runnable.
-        if val.is_some() { self.state = State::Runnable }
+        match val {
+          Ok(GeneratorState::Yielded(_)) => self.state = State::Runnable,
+          Err(unwind::Unwound::Overflowed) => self.state = State::Poisoned,
+          _ => ()
+        }
+
+        Some(val)
+      }
+      State::Unavailable | State::Poisoned => None
+    }
+  }
+
+  /// Cancels the generator. If the generator function has not returned, the pending
+  /// `yielder.suspend()` call unwinds the generator stack, running the destructors
+  /// of every value held by the generator function, and the state becomes
+  /// `State::Unavailable`. If the generator was never resumed, the generator
+  /// function is dropped without being called.
+  ///
+  /// Cancellation cannot be prevented: if the generator function catches the unwinding
+  /// and calls `yielder.suspend()` again, that call unwinds as well.
+  #[cfg(feature = "std")]
+  pub fn cancel(&mut self) {
+    if let State::Runnable = self.state {
+      self.check_thread();
+      self.state = State::Unavailable;
 
-        val
+      match unsafe { self.switch(0) } {
+        Err(unwind::Unwound::Cancelled) => (),
+        // The generator function caught the unwinding and returned normally.
+        Ok(val) => drop(val),
+        // The generator function caught the unwinding and panicked afterwards.
+        Err(unwind::Unwound::Panicked(payload)) => unwind::resume(payload),
+        // The generator overflowed its stack while unwinding.
+        Err(unwind::Unwound::Overflowed) => self.state = State::Poisoned
       }
-      State::Unavailable => None
     }
   }
 
+  /// Switches to the generator function, passing it `data`, and retrieves
+  /// the yielded or returned value.
+  #[inline(always)]
+  unsafe fn switch(&mut self, data: usize) -> Result<GeneratorState<Output, Return>, unwind::Unwound> {
+    self.check_overflow();
+    #[cfg(feature = "std")]
+    let stack_limit = current::replace_stack_limit(self.stack.limit() as usize);
+    #[cfg(feature = "std")]
+    let locals = current::replace_locals(&self.locals);
+    let (data_out, stack_ptr) = arch::swap(data, self.stack_ptr, Some(&*self.stack));
+    self.stack_ptr = stack_ptr;
+    #[cfg(feature = "std")]
+    current::replace_stack_limit(stack_limit);
+    #[cfg(feature = "std")]
+    current::replace_locals(locals);
+    #[cfg(unix)]
+    {
+      // The generator has been abandoned; see stack::os::overflow.
+      if data_out == stack::os::overflow::OVERFLOWED { return Err(unwind::Unwound::Overflowed) }
+    }
+    self.check_overflow();
+    match ptr::read(data_out as *const Option<Output>) {
+      Some(item) => Ok(GeneratorState::Yielded(item)),
+      None => {
+        let complete = data_out as *const Complete<Output, Return>;
+        ptr::read(&(*complete).result).map(GeneratorState::Complete)
+      }
+    }
+  }
+
+  /// Returns whether the generator is bound to a thread other than the current one.
+  #[inline(always)]
+  fn on_foreign_thread(&self) -> bool {
+    #[cfg(all(feature = "std", debug_assertions))]
+    { self.thread != 0 && self.thread != current::thread_id() }
+    #[cfg(not(all(feature = "std", debug_assertions)))]
+    { false }
+  }
+
+  /// Lets the generator be resumed on any thread.
+  #[inline(always)]
+  fn unbind_thread(&mut self) {
+    #[cfg(all(feature = "std", debug_assertions))]
+    { self.thread = 0 }
+  }
+
+  /// Panics if the generator is bound to a thread other than the current one.
+  #[inline(always)]
+  fn check_thread(&self) {
+    if self.on_foreign_thread() {
+      panic!("resumed a Generator on a thread other than the one that created it")
+    }
+  }
+
+  /// Lets the stack check whether the generator has overflowed it. If it has,
+  /// the stack panics, and the generator is left poisoned.
+  #[inline(always)]
+  fn check_overflow(&mut self) {
+    let state = mem::replace(&mut self.state, State::Poisoned);
+    self.stack.check_overflow(self.stack_ptr.as_ptr());
+    self.state = state
+  }
+
   /// Returns the state of the generator.
   #[inline]
   pub fn state(&self) -> State { self.state }
 
+  /// Returns the amount of memory used by the generator stack.
+  /// To measure the high-water mark of the stack, wrap it in
+  /// a [PaintedStack](../struct.PaintedStack.html).
+  pub fn stack_usage(&self) -> stack::StackUsage {
+    stack::StackUsage::of(&*self.stack)
+  }
+
+  /// Returns the name of the generator, if one was set.
+  #[inline]
+  pub fn name(&self) -> Option<&'static str> { self.name }
+
+  /// Sets the name of the generator. If the generator stack is an `OsStack` and
+  /// the [overflow handler](../fn.install_overflow_handler.html) is installed,
+  /// the name is reported if the generator overflows its stack.
+  pub fn set_name(&mut self, name: &'static str) {
+    self.name = Some(name);
+    self.describe_stack()
+  }
+
+  /// Returns the overflow policy of the generator.
+  #[inline]
+  pub fn overflow_policy(&self) -> OverflowPolicy { self.overflow }
+
+  /// Sets the overflow policy of the generator, which determines what happens
+  /// if the generator overflows its stack.
+  pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+    self.overflow = policy;
+    self.describe_stack()
+  }
+
+  /// Tells the overflow handler about the name and the overflow policy of the generator.
+  fn describe_stack(&self) {
+    #[cfg(unix)]
+    stack::os::overflow::describe(self.stack.limit(), self.name,
+                                  self.overflow == OverflowPolicy::Poison);
+  }
+
+  /// Makes the overflow handler forget about the generator, since its stack
+  /// is about to be reused or freed.
+  fn forget_stack(&mut self) {
+    if self.name.is_some() || self.overflow != OverflowPolicy::default() {
+      self.name = None;
+      self.overflow = OverflowPolicy::default();
+      self.describe_stack()
+    }
+  }
+
+  /// Returns the drop policy of the generator.
+  #[inline]
+  pub fn drop_policy(&self) -> DropPolicy { self.policy }
+
+  /// Sets the drop policy of the generator, which determines what happens
+  /// if the generator is dropped before the generator function returns.
+  #[inline]
+  pub fn set_drop_policy(&mut self, policy: DropPolicy) { self.policy = policy }
+
   /// Extracts the stack from a generator when the generator function has returned.
   /// If the generator function has not returned
-  /// (i.e. `self.state() == State::Runnable`), panics.
+  /// (i.e. `self.state() == State::Runnable`), or the generator has been poisoned
+  /// (i.e. `self.state() == State::Poisoned`), panics.
   pub fn unwrap(self) -> Stack {
     match self.state {
       State::Runnable => {
         mem::forget(self);
         panic!("Argh! Bastard! Don't touch that!")
       }
+      State::Poisoned => {
+        mem::forget(self);
+        panic!("cannot reuse the stack of a poisoned Generator")
+      }
       State::Unavailable => unsafe { self.unsafe_unwrap() }
     }
   }
@@ -190,31 +566,139 @@ impl<'a, Input, Output, Stack> Generator<'a, Input, Output, Stack>
   /// Extracts the stack from a generator without checking if the generator function has returned.
   /// This will leave any pointers into the generator stack dangling, and won't run destructors.
   pub unsafe fn unsafe_unwrap(mut self) -> Stack {
+    self.forget_stack();
     ManuallyDrop::drop(&mut self.stack_id);
+    #[cfg(feature = "std")]
+    ptr::drop_in_place(&mut self.locals);
     let stack = ptr::read(&mut *self.stack);
     mem::forget(self);
     stack
   }
 }
 
-impl<'a, Input, Output, Stack> Drop for Generator<'a, Input, Output, Stack>
-    where Input: 'a, Output: 'a, Stack: stack::Stack {
+impl<'a, Input, Output, Stack, Return> Drop for Generator<'a, Input, Output, Stack, Return>
+    where Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a {
   fn drop(&mut self) {
+    if let State::Runnable = self.state {
+      match self.policy {
+        // Unwinding the generator stack on another thread would run destructors
+        // that may refer to thread-local values; leak it instead.
+        #[cfg(feature = "std")]
+        DropPolicy::Unwind if self.on_foreign_thread() => {
+          unsafe { ManuallyDrop::drop(&mut self.stack_id) }
+          return
+        }
+        #[cfg(feature = "std")]
+        DropPolicy::Unwind => self.cancel(),
+        DropPolicy::Leak   => {
+          unsafe { ManuallyDrop::drop(&mut self.stack_id) }
+          return
+        }
+        DropPolicy::Abort  => unwind::abort(),
+        DropPolicy::Panic  => unsafe {
+          ManuallyDrop::drop(&mut self.stack_id);
+          panic!("dropped unfinished Generator")
+        }
+      }
+    }
+
     unsafe {
       ManuallyDrop::drop(&mut self.stack_id);
-      match self.state {
-        State::Runnable    => panic!("dropped unfinished Generator"),
-        State::Unavailable => ManuallyDrop::drop(&mut self.stack)
-      }
+      // The stack of a poisoned generator still holds values whose destructors
+      // have never run, so it is leaked.
+      if let State::Poisoned = self.state { return }
+      self.forget_stack();
+      ManuallyDrop::drop(&mut self.stack)
     }
   }
 }
 
+/// SendGenerator is a generator that can be sent to another thread while it is
+/// suspended. It is created using [Generator::new_send](struct.Generator.html#method.new_send),
+/// and dereferences to the generator it wraps.
+///
+/// It does not implement `DerefMut`, since that would allow swapping it with
+/// a generator that cannot be sent to another thread; instead, it provides its own
+/// versions of the methods of `Generator` that take `&mut self`.
+#[derive(Debug)]
+pub struct SendGenerator<'a, Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a = ()>(
+  Generator<'a, Input, Output, Stack, Return>);
+
+unsafe impl<'a, Input, Output, Stack, Return> Send for SendGenerator<'a, Input, Output, Stack, Return>
+    where Input: Send + 'a, Output: Send + 'a, Stack: stack::Stack + Send, Return: Send + 'a {}
+
+impl<'a, Input, Output, Stack, Return> SendGenerator<'a, Input, Output, Stack, Return>
+    where Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a {
+  /// See [Generator::resume](struct.Generator.html#method.resume).
+  #[inline]
+  pub fn resume(&mut self, input: Input) -> Option<GeneratorState<Output, Return>> {
+    self.0.resume(input)
+  }
+
+  /// See [Generator::try_resume](struct.Generator.html#method.try_resume).
+  #[cfg(feature = "std")]
+  #[inline]
+  pub fn try_resume(&mut self, input: Input)
+      -> Result<Option<GeneratorState<Output, Return>>, Box<Any + Send>> {
+    self.0.try_resume(input)
+  }
+
+  /// See [Generator::cancel](struct.Generator.html#method.cancel).
+  #[cfg(feature = "std")]
+  pub fn cancel(&mut self) { self.0.cancel() }
+
+  /// See [Generator::set_name](struct.Generator.html#method.set_name).
+  pub fn set_name(&mut self, name: &'static str) { self.0.set_name(name) }
+
+  /// See [Generator::set_overflow_policy](struct.Generator.html#method.set_overflow_policy).
+  pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) { self.0.set_overflow_policy(policy) }
+
+  /// See [Generator::set_drop_policy](struct.Generator.html#method.set_drop_policy).
+  #[inline]
+  pub fn set_drop_policy(&mut self, policy: DropPolicy) { self.0.set_drop_policy(policy) }
+
+  /// See [Generator::unwrap](struct.Generator.html#method.unwrap).
+  pub fn unwrap(self) -> Stack { self.0.unwrap() }
+
+  /// See [Generator::unsafe_unwrap](struct.Generator.html#method.unsafe_unwrap).
+  pub unsafe fn unsafe_unwrap(self) -> Stack { self.0.unsafe_unwrap() }
+}
+
+impl<'a, Input, Output, Stack, Return> Deref for SendGenerator<'a, Input, Output, Stack, Return>
+    where Input: 'a, Output: 'a, Stack: stack::Stack, Return: 'a {
+  type Target = Generator<'a, Input, Output, Stack, Return>;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl<'a, Output, Stack, Return> Iterator for SendGenerator<'a, (), Output, Stack, Return>
+    where Output: 'a, Stack: stack::Stack, Return: 'a {
+  type Item = Output;
+
+  fn next(&mut self) -> Option<Self::Item> { self.0.next() }
+}
+
+/// Reads a byte every 4096 bytes, the smallest guard area allowed by `GuardedStack`,
+/// from the top of the frame of this function down to `size` bytes below it.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+#[inline(never)]
+unsafe fn probe_stack(size: usize) {
+  const STEP: usize = 4096;
+  let top = &size as *const usize as usize;
+  let mut offset = STEP;
+  while offset <= size {
+    ptr::read_volatile((top - offset) as *const u8);
+    offset += STEP
+  }
+}
+
 /// Yielder is an interface provided to every generator through which it
 /// returns a value.
 #[derive(Debug)]
 pub struct Yielder<Input, Output> {
   stack_ptr: Cell<StackPointer>,
+  cancelled: Cell<bool>,
   phantom: PhantomData<(*const Input, *mut Output)>
 }
 
@@ -222,31 +706,60 @@ impl<Input, Output> Yielder<Input, Output> {
   fn new(stack_ptr: StackPointer) -> Yielder<Input, Output> {
     Yielder {
       stack_ptr: Cell::new(stack_ptr),
+      cancelled: Cell::new(false),
       phantom: PhantomData
     }
   }
 
   #[inline(always)]
   fn suspend_bare(&self, val: Option<Output>) -> Input {
+    if self.cancelled.get() {
+      // The generator function caught the unwinding; don't let it suspend.
+      drop(val);
+      unwind::cancel()
+    }
+
     unsafe {
       let (data, stack_ptr) = arch::swap(&val as *const Option<Output> as usize, self.stack_ptr.get(), None);
       self.stack_ptr.set(stack_ptr);
       mem::forget(val);
+      if data == 0 {
+        // The resumer has no input to pass; it is cancelling the generator.
+        self.cancelled.set(true);
+        unwind::cancel()
+      }
       ptr::read(data as *const Input)
     }
   }
 
-  /// Suspends the generator and returns `Some(item)` from the `resume()`
+  /// Suspends the generator and returns `Some(Yielded(item))` from the `resume()`
   /// invocation that resumed the generator.
   #[inline(always)]
   pub fn suspend(&self, item: Output) -> Input {
     self.suspend_bare(Some(item))
   }
+
+  /// Passes the result of the generator function to the `resume()` invocation
+  /// that resumed the generator. The generator is never switched to again.
+  #[inline(always)]
+  fn complete<Return>(&self, result: Result<Return, unwind::Unwound>) -> ! {
+    let complete = Complete { yielded: None::<Output>, result: result };
+    unsafe {
+      arch::swap(&complete as *const Complete<Output, Return> as usize, self.stack_ptr.get(), None);
+    }
+    mem::forget(complete);
+    unreachable!("resumed a completed Generator")
+  }
 }
 
-impl<'a, Output, Stack> Iterator for Generator<'a, (), Output, Stack>
-    where Output: 'a, Stack: stack::Stack {
+impl<'a, Output, Stack, Return> Iterator for Generator<'a, (), Output, Stack, Return>
+    where Output: 'a, Stack: stack::Stack, Return: 'a {
   type Item = Output;
 
-  fn next(&mut self) -> Option<Self::Item> { self.resume(()) }
+  fn next(&mut self) -> Option<Self::Item> {
+    match self.resume(()) {
+      Some(GeneratorState::Yielded(item)) => Some(item),
+      Some(GeneratorState::Complete(_)) | None => None
+    }
+  }
 }
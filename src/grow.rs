@@ -0,0 +1,103 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+use stack::{Stack, GuardedStack};
+use generator::{Generator, GeneratorState};
+
+/// Runs `f` on `stack` and returns its result.
+/// If `f` panics, the panic is propagated to the caller.
+///
+/// # Example
+///
+/// ```
+/// use fringe::OsStack;
+///
+/// fn depth(n: u32) -> u32 { if n == 0 { 0 } else { 1 + depth(n - 1) } }
+///
+/// let mut stack = OsStack::new(16 << 20).unwrap();
+/// assert_eq!(fringe::on_stack(&mut stack, || depth(100_000)), 100_000);
+/// ```
+pub fn on_stack<S, F, R>(stack: &mut S, f: F) -> R
+    where S: Stack + GuardedStack, F: FnOnce() -> R {
+  // This is safe because `stack` is guarded, and the generator finishes
+  // before the borrow of `stack` ends.
+  let mut generator: Generator<(), (), &mut S, R> =
+    unsafe { Generator::unsafe_new(stack, move |_, ()| f()) };
+  match generator.resume(()) {
+    Some(GeneratorState::Complete(value)) => value,
+    _ => unreachable!()
+  }
+}
+
+#[cfg(all(unix, feature = "std"))]
+pub use self::os::*;
+
+#[cfg(all(unix, feature = "std"))]
+mod os {
+  use current;
+  use stack::OsStack;
+  use stack::os::sys;
+  use super::on_stack;
+
+  /// Returns the amount of stack space available to the caller, in bytes,
+  /// or `None` if it cannot be determined on this platform.
+  ///
+  /// The current stack is known on threads, in generators and coroutines, and in
+  /// [raw](raw/index.html) contexts that have been switched to with stack linking
+  /// enabled; elsewhere, the result is meaningless.
+  #[inline(never)]
+  pub fn remaining_stack() -> Option<usize> {
+    let marker = 0u8;
+    let sp = &marker as *const u8 as usize;
+    let limit = match current::stack_limit() {
+      0 => thread_stack_limit()?,
+      limit => limit
+    };
+    Some(sp.saturating_sub(limit))
+  }
+
+  /// Returns the limit address of the stack the current thread was created with.
+  /// Determining it can be expensive, so it is only done once per thread.
+  #[inline]
+  fn thread_stack_limit() -> Option<usize> {
+    match current::thread_stack_limit() {
+      0 => {
+        let limit = sys::thread_stack_limit()?;
+        current::set_thread_stack_limit(limit);
+        Some(limit)
+      }
+      limit => Some(limit)
+    }
+  }
+
+  /// Runs `f` and returns its result. If less than `red_zone` bytes of stack space
+  /// remain, or it is not possible to tell, `f` runs on a newly allocated `OsStack`
+  /// of `stack_size` bytes instead.
+  ///
+  /// This is useful for deeply recursive code, which can call `maybe_grow` at
+  /// every level of recursion.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// fn depth(n: u32) -> u32 {
+  ///   fringe::maybe_grow(32 << 10, 1 << 20, || if n == 0 { 0 } else { 1 + depth(n - 1) })
+  /// }
+  ///
+  /// assert_eq!(depth(1_000_000), 1_000_000);
+  /// ```
+  #[inline]
+  pub fn maybe_grow<F, R>(red_zone: usize, stack_size: usize, f: F) -> R
+      where F: FnOnce() -> R {
+    match remaining_stack() {
+      Some(remaining) if remaining >= red_zone => f(),
+      _ => {
+        let mut stack = OsStack::new(stack_size).expect("cannot allocate stack");
+        on_stack(&mut stack, f)
+      }
+    }
+  }
+}
@@ -6,6 +6,8 @@
 // copied, modified, or distributed except according to those terms.
 #![feature(asm, naked_functions, untagged_unions)]
 #![cfg_attr(feature = "alloc", feature(alloc, allocator_api))]
+#![cfg_attr(feature = "std", feature(thread_local))]
+#![cfg_attr(not(feature = "std"), feature(core_intrinsics))]
 #![cfg_attr(test, feature(test))]
 #![no_std]
 
@@ -16,10 +18,29 @@
 //! It provides the following safe abstractions:
 //!
 //!   * an implementation of generators,
-//!     [Generator](generator/struct.Generator.html).
+//!     [Generator](generator/struct.Generator.html);
+//!   * an implementation of symmetric coroutines, which transfer control
+//!     directly to each other, [Coroutine](coroutine/struct.Coroutine.html);
+//!   * a single-threaded green thread runtime built on generators, with timers and
+//!     fiber-aware sockets on Linux,
+//!     [rt](rt/index.html), and a multi-threaded work-stealing one,
+//!     [Executor](rt/mt/struct.Executor.html), and a deterministic scheduler for testing,
+//!     [Simulation](rt/sim/struct.Simulation.html);
+//!   * fiber-local storage, which every generator has its own copy of,
+//!     [fiber_local!](macro.fiber_local.html);
+//!   * synchronization primitives that suspend the current fiber instead of
+//!     blocking the thread, [sync](sync/index.html), including channels with
+//!     [select!](macro.select.html);
+//!   * a thread pool that makes blocking calls while the task making them
+//!     is suspended, [blocking](fn.blocking.html);
+//!   * a way to run a function on a different stack,
+//!     [on_stack](fn.on_stack.html), and to do so only when the current
+//!     stack is close to exhaustion, [maybe_grow](fn.maybe_grow.html).
 //!
 //! It also provides the necessary low-level building blocks:
 //!
+//!   * raw context switching, for building other abstractions,
+//!     [raw](raw/index.html);
 //!   * a trait that can be implemented by stack allocators,
 //!     [Stack](struct.Stack.html);
 //!   * a wrapper for using slice references as stacks,
@@ -27,7 +48,19 @@
 //!   * a stack allocator based on `Box<[u8]>`,
 //!     [OwnedStack](struct.OwnedStack.html);
 //!   * a stack allocator based on anonymous memory mappings with guard pages,
-//!     [OsStack](struct.OsStack.html).
+//!     [OsStack](struct.OsStack.html);
+//!   * guarded counterparts of the above, which protect the lowest pages of the memory
+//!     they are given, [GuardedSliceStack](struct.GuardedSliceStack.html) and
+//!     [GuardedOwnedStack](struct.GuardedOwnedStack.html);
+//!   * a pool that recycles such stacks, [StackPool](struct.StackPool.html);
+//!   * an arena that carves many such stacks out of a single mapping,
+//!     [StackArena](struct.StackArena.html);
+//!   * a wrapper for measuring the high-water mark of a stack,
+//!     [PaintedStack](struct.PaintedStack.html);
+//!   * a wrapper for detecting overflows of stacks without a guard page,
+//!     [CheckedStack](struct.CheckedStack.html);
+//!   * a signal handler that reports overflows of such stacks,
+//!     [install_overflow_handler](fn.install_overflow_handler.html).
 
 #[cfg(test)]
 #[macro_use]
@@ -38,6 +71,10 @@ extern crate alloc;
 
 pub use stack::*;
 pub use generator::Generator;
+pub use coroutine::Coroutine;
+pub use grow::*;
+#[cfg(feature = "std")]
+pub use blocking::{blocking, BlockingPool, DEFAULT_MAX_THREADS};
 
 mod arch;
 
@@ -46,6 +83,29 @@ pub const STACK_ALIGNMENT: usize = arch::STACK_ALIGNMENT;
 
 mod debug;
 
+mod unwind;
+
+#[cfg(feature = "std")]
+mod current;
+
+#[cfg(feature = "std")]
+pub mod local;
+
 pub mod generator;
 
+pub mod coroutine;
+
+pub mod raw;
+
+#[cfg(all(unix, feature = "std"))]
+pub mod rt;
+
+#[cfg(feature = "std")]
+pub mod sync;
+
+#[cfg(feature = "std")]
+mod blocking;
+
+mod grow;
+
 mod stack;
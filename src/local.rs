@@ -0,0 +1,165 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Fiber-local storage.
+//!
+//! A value declared using [fiber_local!](../macro.fiber_local.html) is like one declared
+//! using `thread_local!`, except that every generator has its own copy of it. While
+//! a generator runs, `LocalKey::with` refers to the copy of that generator, which is
+//! created the first time it is used, and destroyed once the generator function returns,
+//! or when the generator is dropped. Outside of any generator, it refers to the copy
+//! of the current thread.
+//!
+//! Generators created using `Generator::new_send` take their fiber-local values along
+//! when they are sent to another thread, which is why the values have to be `Send`.
+//!
+//! # Example
+//!
+//! ```
+//! #[macro_use]
+//! extern crate fringe;
+//!
+//! use std::cell::Cell;
+//! use fringe::{OsStack, Generator};
+//!
+//! fiber_local!(static REQUEST: Cell<u32> = Cell::new(0));
+//!
+//! # fn main() {
+//! let mut generators = (1..3).map(|id| {
+//!   Generator::new(OsStack::new(1 << 16).unwrap(), move |yielder, ()| {
+//!     REQUEST.with(|request| request.set(id));
+//!     loop { yielder.suspend(REQUEST.with(|request| request.get())) }
+//!   })
+//! }).collect::<Vec<_>>();
+//! assert_eq!(generators[0].next(), Some(1));
+//! assert_eq!(generators[1].next(), Some(2));
+//! assert_eq!(generators[0].next(), Some(1));
+//! assert_eq!(REQUEST.with(|request| request.get()), 0);
+//! # }
+//! ```
+extern crate std;
+
+use core::{fmt, mem};
+use core::any::Any;
+use core::cell::UnsafeCell;
+use alloc::boxed::Box;
+use self::std::vec::Vec;
+use current;
+
+/// A key for a fiber-local value, declared using [fiber_local!](../macro.fiber_local.html).
+pub struct LocalKey<T: 'static> {
+  #[doc(hidden)]
+  pub __init: fn() -> T
+}
+
+impl<T: Send + 'static> LocalKey<T> {
+  /// Calls `f` with a reference to the value of the current generator, or of the current
+  /// thread outside of any generator, initializing it first if it has not been used yet.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of any generator while the thread-local values of
+  /// the current thread are being destroyed.
+  pub fn with<F, R>(&'static self, f: F) -> R
+      where F: FnOnce(&T) -> R {
+    let locals = current::locals();
+    if locals.is_null() {
+      THREAD.with(|locals| f(self.get(locals)))
+    } else {
+      f(self.get(unsafe { &*locals }))
+    }
+  }
+
+  fn get<'a>(&'static self, locals: &'a Locals) -> &'a T {
+    let key = self as *const LocalKey<T> as usize;
+    let value = match locals.find(key) {
+      Some(value) => value,
+      None => {
+        // The initializer may use other fiber-local values, so no references
+        // into `locals` are held while it runs.
+        let value = Box::new((self.__init)());
+        match locals.find(key) {
+          Some(value) => value,
+          None => locals.insert(key, value)
+        }
+      }
+    };
+    value.downcast_ref::<T>().unwrap()
+  }
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("LocalKey { .. }")
+  }
+}
+
+/// Declares fiber-local values, each of which has the type [LocalKey](local/struct.LocalKey.html).
+/// The syntax is the same as that of `thread_local!`.
+#[macro_export]
+macro_rules! fiber_local {
+  () => {};
+  ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+    fiber_local!($(#[$attr])* $vis static $name: $t = $init);
+    fiber_local!($($rest)*);
+  };
+  ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+    $(#[$attr])*
+    $vis static $name: $crate::local::LocalKey<$t> = {
+      fn __init() -> $t { $init }
+      $crate::local::LocalKey { __init: __init }
+    };
+  };
+}
+
+/// The fiber-local values of a generator or a thread.
+pub(crate) struct Locals {
+  /// The values, with the addresses of their keys. The values are boxed, so that
+  /// references to them stay valid while others are inserted.
+  values: UnsafeCell<Vec<(usize, Box<Any>)>>
+}
+
+self::std::thread_local! {
+  static THREAD: Locals = Locals::new();
+}
+
+impl Locals {
+  pub fn new() -> Locals {
+    Locals { values: UnsafeCell::new(Vec::new()) }
+  }
+
+  fn find(&self, key: usize) -> Option<&Any> {
+    let values = unsafe { &*self.values.get() };
+    values.iter().find(|&&(other, _)| other == key).map(|&(_, ref value)| &**value)
+  }
+
+  fn insert(&self, key: usize, value: Box<Any>) -> &Any {
+    let values = unsafe { &mut *self.values.get() };
+    values.push((key, value));
+    &*values.last().unwrap().1
+  }
+
+  /// Destroys the values. Values used by their destructors are created again,
+  /// and destroyed along with the `Locals`.
+  pub fn clear(&self) {
+    let values = mem::replace(unsafe { &mut *self.values.get() }, Vec::new());
+    drop(values)
+  }
+}
+
+impl fmt::Debug for Locals {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let count = unsafe { (*self.values.get()).len() };
+    f.debug_struct("Locals").field("values", &count).finish()
+  }
+}
+
+/// Destroys the fiber-local values of the generator the current thread is executing.
+pub(crate) fn clear_current() {
+  let locals = current::locals();
+  if !locals.is_null() { unsafe { (*locals).clear() } }
+}
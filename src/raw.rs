@@ -0,0 +1,118 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Raw context switching.
+//!
+//! This module exposes the context switching primitives that generators and
+//! coroutines are built on, for implementing other abstractions, such as schedulers,
+//! without their overhead. Nothing here is safe: it is entirely up to the caller
+//! to make sure that every context is switched to at most once per suspension,
+//! that its stack outlives it, and that no context is switched to after its
+//! function has stopped running.
+//!
+//! A context is suspended by switching away from it, and resumed by switching to
+//! the `Context` that the switch has produced. Every switch carries a word-sized
+//! payload, which is typically a pointer to a value on the stack of the context
+//! that is switching, to be read by the context that is switched to.
+//!
+//! # Example
+//!
+//! ```
+//! use fringe::OsStack;
+//! use fringe::raw::{self, Context};
+//!
+//! unsafe extern "C" fn adder(arg: usize, parent: Context) -> ! {
+//!   let (arg, parent) = raw::switch(arg + 1, parent, None);
+//!   raw::switch(arg + 1, parent, None);
+//!   unreachable!()
+//! }
+//!
+//! let stack = OsStack::new(1 << 16).unwrap();
+//! unsafe {
+//!   let context = raw::init(&stack, adder);
+//!   let (result, context) = raw::switch(10, context, Some(&stack));
+//!   assert_eq!(result, 11);
+//!   let (result, _) = raw::switch(20, context, Some(&stack));
+//!   assert_eq!(result, 21);
+//! }
+//! ```
+
+use core::mem;
+use stack::Stack;
+#[cfg(feature = "std")]
+use current;
+use arch::{self, StackPointer};
+
+/// A suspended execution context, represented by its saved stack pointer.
+///
+/// A `Context` can be switched to only once; the switch produces a new `Context`
+/// once the context is suspended again.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct Context(StackPointer);
+
+impl Context {
+  /// Returns the stack pointer of the context.
+  #[inline(always)]
+  pub fn stack_ptr(&self) -> *mut u8 {
+    self.0.as_ptr()
+  }
+}
+
+/// Prepares a new context on `stack`. When switched to for the first time,
+/// the context calls `f(arg, parent)`, where `arg` is the payload of the switch,
+/// and `parent` is the context that has switched to it. `f` must never return;
+/// instead, it has to switch to another context, and never be switched to again.
+///
+/// If `f` panics, the panic unwinds into the context that has last switched to
+/// `stack` with stack linking enabled (see `switch`), as if `f` has been called by it.
+///
+/// `maybe_grow` and `remaining_stack` only know that `f` runs on `stack` if the context
+/// is first switched to with stack linking enabled; otherwise, they consider `f`
+/// to run on the stack of the context that has switched to it.
+///
+/// # Safety
+///
+/// `stack` must fulfill the [contract](../trait.Stack.html) of `Stack`,
+/// and must outlive the context. `f` must not unwind unless the context has been
+/// switched to with stack linking enabled.
+#[inline]
+pub unsafe fn init(stack: &Stack, f: unsafe extern "C" fn(usize, Context) -> !) -> Context {
+  // Context is a transparent wrapper around StackPointer, so both function types
+  // have the same ABI.
+  let f: unsafe extern "C" fn(usize, StackPointer) -> ! = mem::transmute(f);
+  Context(arch::init(stack, f))
+}
+
+/// Suspends the current context, switches to `to` passing it `arg`, and returns
+/// the payload and the suspended context of whoever switches back to the current
+/// context.
+///
+/// If `link` is `Some(stack)`, where `stack` is the stack the context `to` runs on,
+/// the current stack is linked to it: the debugger and the unwinder then see
+/// the frames of the current context as the callers of the frames on `stack`,
+/// which produces complete backtraces and lets panics propagate from `stack`
+/// into the current context. It also makes `stack` the current stack for `maybe_grow`
+/// and `remaining_stack`, until the current context is switched back to.
+///
+/// # Safety
+///
+/// `to` must be a context that is suspended, and its stack must still be alive.
+/// If `link` is `Some(stack)`, `stack` must be the stack of `to`, and must have been
+/// prepared using `init`.
+#[inline(always)]
+pub unsafe fn switch(arg: usize, to: Context, link: Option<&Stack>) -> (usize, Context) {
+  #[cfg(feature = "std")]
+  let stack_limit = match link {
+    Some(stack) => current::replace_stack_limit(stack.limit() as usize),
+    None => current::stack_limit()
+  };
+  let (arg, stack_ptr) = arch::swap(arg, to.0, link);
+  #[cfg(feature = "std")]
+  current::replace_stack_limit(stack_limit);
+  (arg, Context(stack_ptr))
+}
@@ -0,0 +1,169 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Fiber-aware I/O on file descriptors.
+//!
+//! The functions in this module operate on non-blocking file descriptors.
+//! When an operation would block, the current task waits for the file descriptor
+//! to become ready, and other tasks run in the meantime. Outside of a task of
+//! the single-threaded runtime, the thread blocks in `poll(2)` instead. This includes
+//! the tasks of an [Executor](../mt/struct.Executor.html), which have no reactor:
+//! there, waiting for I/O stalls the worker, and every task queued on it.
+//! [blocking](../../fn.blocking.html) can be used to wait on another thread instead.
+//!
+//! # Example
+//!
+//! ```
+//! extern crate libc;
+//! extern crate fringe;
+//!
+//! use fringe::rt;
+//!
+//! # fn main() {
+//! rt::run(|| {
+//!   let mut fds = [0; 2];
+//!   assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) }, 0);
+//!   let writer = rt::spawn(rt::stack(), move || {
+//!     rt::yield_now();
+//!     rt::io::write(fds[1], b"hello").unwrap()
+//!   });
+//!   let mut buf = [0; 5];
+//!   assert_eq!(rt::io::read(fds[0], &mut buf).unwrap(), 5);
+//!   assert_eq!(&buf, b"hello");
+//!   writer.join().unwrap();
+//!   unsafe { libc::close(fds[0]); libc::close(fds[1]) };
+//! });
+//! # }
+//! ```
+extern crate std;
+extern crate libc;
+
+use self::std::io::{self, Error as IoError};
+use self::std::os::unix::io::RawFd;
+use core::mem;
+use self::libc::{c_int, c_void, sockaddr, socklen_t};
+use super::{LOCAL, park};
+
+pub use super::reactor::Interest;
+
+/// Waits for `fd` to become ready for `interest`.
+///
+/// The task may also be woken spuriously, so the operation that would have blocked
+/// should be retried in a loop, as `retry()` does.
+///
+/// Outside of a task of the single-threaded runtime, including in a task of
+/// an `rt::mt` executor, blocks the thread in `poll(2)` until `fd` is ready.
+pub fn wait(fd: RawFd, interest: Interest) -> Result<(), IoError> {
+  let local = LOCAL.with(|cell| cell.borrow().clone());
+  match local {
+    Some(ref local) if local.current.borrow().is_some() => {
+      try!(local.shared.reactor.wait(fd, interest, local.current_task()));
+      park();
+      Ok(())
+    }
+    _ => poll(fd, interest, -1).map(|_| ())
+  }
+}
+
+/// Polls `fd` for `interest` for up to `timeout` milliseconds, or forever if `timeout`
+/// is negative, and returns whether it is ready.
+fn poll(fd: RawFd, interest: Interest, timeout: c_int) -> Result<bool, IoError> {
+  let events = match interest {
+    Interest::Read  => libc::POLLIN,
+    Interest::Write => libc::POLLOUT
+  };
+  let mut pollfd = libc::pollfd { fd: fd, events: events, revents: 0 };
+  match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+    -1 => Err(IoError::last_os_error()),
+    _ => Ok(pollfd.revents != 0)
+  }
+}
+
+/// Calls `f` until it does not fail with `WouldBlock` or `Interrupted`, waiting for `fd`
+/// to become ready for `interest` every time it fails with `WouldBlock`.
+pub fn retry<F, R>(fd: RawFd, interest: Interest, mut f: F) -> Result<R, IoError>
+    where F: FnMut() -> Result<R, IoError> {
+  loop {
+    match f() {
+      Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => try!(wait(fd, interest)),
+      Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+      result => return result
+    }
+  }
+}
+
+/// Forgets about the tasks waiting for `fd`. This must be called before closing
+/// a file descriptor that tasks have waited for, since another file descriptor
+/// may be created with the same number.
+pub fn forget(fd: RawFd) {
+  LOCAL.with(|cell| {
+    if let Some(ref local) = *cell.borrow() { local.shared.reactor.forget(fd) }
+  })
+}
+
+fn cvt(ret: isize) -> Result<usize, IoError> {
+  if ret < 0 { Err(IoError::last_os_error()) } else { Ok(ret as usize) }
+}
+
+/// Reads from `fd` into `buf`, waiting for `fd` to become readable.
+pub fn read(fd: RawFd, buf: &mut [u8]) -> Result<usize, IoError> {
+  retry(fd, Interest::Read, || cvt(unsafe {
+    libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) as isize
+  }))
+}
+
+/// Writes `buf` to `fd`, waiting for `fd` to become writable.
+pub fn write(fd: RawFd, buf: &[u8]) -> Result<usize, IoError> {
+  retry(fd, Interest::Write, || cvt(unsafe {
+    libc::write(fd, buf.as_ptr() as *const c_void, buf.len()) as isize
+  }))
+}
+
+/// Accepts a connection on the listening socket `fd`, waiting for `fd` to become readable.
+/// The new socket is non-blocking and close-on-exec.
+pub fn accept(fd: RawFd) -> Result<RawFd, IoError> {
+  retry(fd, Interest::Read, || cvt(unsafe {
+    libc::accept4(fd, 0 as *mut sockaddr, 0 as *mut socklen_t,
+                  libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC) as isize
+  })).map(|fd| fd as RawFd)
+}
+
+/// Connects the socket `fd` to `addr`, waiting for the connection to be established.
+///
+/// # Safety
+///
+/// `addr` must point to a valid socket address of `len` bytes.
+pub unsafe fn connect(fd: RawFd, addr: *const sockaddr, len: socklen_t) -> Result<(), IoError> {
+  if libc::connect(fd, addr, len) == 0 { return Ok(()) }
+  let err = IoError::last_os_error();
+  match err.raw_os_error() {
+    // An interrupted connection attempt goes on in the background, like a non-blocking one.
+    Some(libc::EINPROGRESS) | Some(libc::EINTR) => (),
+    _ => return Err(err)
+  }
+
+  // The attempt is over once the socket is writable, and its outcome is in SO_ERROR.
+  loop {
+    try!(wait(fd, Interest::Write));
+    match poll(fd, Interest::Write, 0) {
+      Ok(true) => break,
+      Ok(false) => continue,
+      Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+      Err(err) => return Err(err)
+    }
+  }
+  let mut error: c_int = 0;
+  let mut error_len = mem::size_of::<c_int>() as socklen_t;
+  if libc::getsockopt(fd, libc::SOL_SOCKET, libc::SO_ERROR,
+                      &mut error as *mut c_int as *mut c_void, &mut error_len) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  match error {
+    0 => Ok(()),
+    error => Err(IoError::from_raw_os_error(error))
+  }
+}
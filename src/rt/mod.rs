@@ -0,0 +1,538 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A single-threaded green thread runtime.
+//!
+//! `run(f)` turns the current thread into a scheduler: it spawns `f` as the first
+//! task, and runs tasks from its ready queue until every task has finished. Tasks are
+//! generators; they run until they finish, yield using `yield_now()`, or wait using
+//! `park()` or `JoinHandle::join()`, after which the next task in the ready queue runs.
+//! Tasks never run concurrently, and are never preempted.
+//!
+//! On Linux, tasks can wait for file descriptors without blocking the thread,
+//! using the wrappers in [io](io/index.html) and the sockets in [net](net/index.html).
+//! While no task is ready, the runtime waits for the file descriptors using `epoll`.
+//!
+//! Tasks can also wait for a duration using `sleep()` and `sleep_until()`, and give
+//! up on a computation that takes too long using `timeout()`. The deadlines are kept
+//! in a hierarchical timer wheel, and the runtime sleeps until the nearest one while
+//! no task is ready.
+//!
+//! Tasks spawned in a `scope()` can borrow data from the task that created the scope,
+//! which waits for them to finish before returning. The tasks of a scope can be cancelled
+//! together using its [CancellationToken](struct.CancellationToken.html).
+//!
+//! Every task runs on its own stack. The runtime keeps a [StackPool](../struct.StackPool.html),
+//! from which `stack()` takes a stack; any other guarded stack can be used as well.
+//!
+//! To run tasks on several threads, use the work-stealing [Executor](mt/struct.Executor.html)
+//! in [mt](mt/index.html) instead. To test tasks under reproducible interleavings,
+//! use the deterministic scheduler in [sim](sim/index.html).
+//!
+//! # Example
+//!
+//! ```
+//! use fringe::rt;
+//!
+//! let sum = rt::run(|| {
+//!   let handles = (0..10).map(|i| {
+//!     rt::spawn(rt::stack(), move || {
+//!       rt::yield_now();
+//!       i * 2
+//!     })
+//!   }).collect::<Vec<_>>();
+//!   handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u32>()
+//! });
+//! assert_eq!(sum, 90);
+//! ```
+extern crate std;
+
+use core::{fmt, mem, ptr};
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use self::std::collections::{HashMap, VecDeque};
+use self::std::panic;
+use self::std::time::{Duration, Instant};
+use self::std::vec::Vec;
+use self::std::sync::{Arc, Mutex};
+#[cfg(not(target_os = "linux"))]
+use self::std::sync::Condvar;
+use stack::{Stack, GuardedStack, StackPool, PooledStack};
+use generator::{Generator, GeneratorState, Yielder};
+
+pub use self::task::Task;
+pub use self::timer::{sleep, sleep_until, timeout, Elapsed};
+pub use self::scope::{scope, Scope, ScopedJoinHandle, CancellationToken, Cancelled};
+
+mod task;
+mod timer;
+mod scope;
+pub mod mt;
+pub mod sim;
+#[cfg(target_os = "linux")]
+mod reactor;
+#[cfg(target_os = "linux")]
+pub mod io;
+#[cfg(target_os = "linux")]
+pub mod net;
+
+#[cfg(target_os = "linux")]
+use self::reactor::Reactor;
+use self::timer::{Wheel, Timer};
+
+/// The number of tasks the runtime runs between checks for expired timers and
+/// readiness of file descriptors while other tasks are ready.
+const EVENT_INTERVAL: usize = 61;
+
+/// The size of the stacks in the pool of a runtime created using `Runtime::new()`.
+pub const DEFAULT_STACK_SIZE: usize = 256 << 10;
+
+/// A single-threaded runtime, which runs tasks on the thread that calls `run()`.
+#[derive(Debug, Clone)]
+pub struct Runtime {
+  pool: StackPool
+}
+
+/// The part of a runtime that can be accessed from other threads.
+#[derive(Debug)]
+pub(crate) struct Shared {
+  ready:     Mutex<Ready>,
+  #[cfg(not(target_os = "linux"))]
+  available: Condvar,
+  #[cfg(target_os = "linux")]
+  reactor:   Reactor
+}
+
+#[derive(Debug)]
+struct Ready {
+  tasks:    VecDeque<Task>,
+  /// Whether the runtime is waiting for a task to become ready.
+  sleeping: bool
+}
+
+/// The part of a runtime that lives on the thread running it.
+struct Local {
+  shared:  Arc<Shared>,
+  pool:    StackPool,
+  fibers:  RefCell<HashMap<usize, Box<Fiber>>>,
+  next_id: Cell<usize>,
+  ticks:   Cell<usize>,
+  current: RefCell<Option<(Task, Rc<Cell<*const Yielder<(), ()>>>)>>,
+  wheel:   RefCell<Wheel>,
+  /// The timers of the pending `timeout()` calls of every task, innermost last.
+  timeouts: RefCell<HashMap<usize, Vec<Rc<Timer>>>>,
+  /// The cancellation tokens of the tasks spawned in a scope.
+  tokens:  RefCell<HashMap<usize, CancellationToken>>
+}
+
+self::std::thread_local! {
+  static LOCAL: RefCell<Option<Rc<Local>>> = RefCell::new(None);
+}
+
+impl Runtime {
+  /// Creates a runtime that allocates stacks of `DEFAULT_STACK_SIZE` bytes.
+  pub fn new() -> Runtime {
+    Runtime::with_pool(StackPool::new(DEFAULT_STACK_SIZE))
+  }
+
+  /// Creates a runtime that takes stacks from `pool`.
+  pub fn with_pool(pool: StackPool) -> Runtime {
+    Runtime { pool: pool }
+  }
+
+  /// Spawns `f` as a task on a stack from the pool, and runs tasks until every task
+  /// has finished. Returns the result of `f`, or propagates its panic.
+  ///
+  /// If tasks are parked, and nothing ever unparks them, `run()` never returns.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called from a task of another runtime, or if the reactor
+  /// cannot be created.
+  pub fn run<F, T>(&self, f: F) -> T
+      where F: FnOnce() -> T + 'static, T: 'static {
+    let local = Rc::new(Local {
+      shared:  Arc::new(Shared::new()),
+      pool:    self.pool.clone(),
+      fibers:  RefCell::new(HashMap::new()),
+      next_id: Cell::new(0),
+      ticks:   Cell::new(0),
+      current: RefCell::new(None),
+      wheel:   RefCell::new(Wheel::new()),
+      timeouts: RefCell::new(HashMap::new()),
+      tokens:  RefCell::new(HashMap::new())
+    });
+
+    LOCAL.with(|cell| {
+      let mut cell = cell.borrow_mut();
+      assert!(cell.is_none(), "cannot run a runtime inside another one");
+      *cell = Some(local.clone())
+    });
+
+    struct Exit;
+    impl Drop for Exit {
+      fn drop(&mut self) {
+        LOCAL.with(|cell| *cell.borrow_mut() = None)
+      }
+    }
+
+    let _exit = Exit;
+    let stack = local.pool.get().expect("cannot allocate a stack");
+    let handle = local.spawn(stack, f);
+    local.drive();
+    let result = handle.packet.result.borrow_mut().take();
+    match result {
+      Some(Ok(value)) => value,
+      Some(Err(payload)) => panic::resume_unwind(payload),
+      None => unreachable!()
+    }
+  }
+}
+
+/// Same as `Runtime::new().run(f)`.
+pub fn run<F, T>(f: F) -> T
+    where F: FnOnce() -> T + 'static, T: 'static {
+  Runtime::new().run(f)
+}
+
+/// Spawns `f` as a task on `stack`. The task is added to the end of the ready queue.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn spawn<S, F, T>(stack: S, f: F) -> JoinHandle<T>
+    where S: Stack + GuardedStack + 'static, F: FnOnce() -> T + 'static, T: 'static {
+  local().spawn(stack, f)
+}
+
+/// Takes a stack from the pool of the runtime.
+///
+/// # Panics
+///
+/// Panics if called outside of a task, or if a stack cannot be allocated.
+pub fn stack() -> PooledStack {
+  local().pool.get().expect("cannot allocate a stack")
+}
+
+/// Returns a handle to the task that calls it.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn current() -> Task {
+  local().current_task()
+}
+
+/// Suspends the current task, and adds it to the end of the ready queue.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn yield_now() {
+  let local = local();
+  local.check_interrupts();
+  local.shared.schedule(local.current_task());
+  local.suspend()
+}
+
+/// Suspends the current task until `unpark()` is called on its handle. If `unpark()`
+/// has been called since the task last parked, returns immediately.
+///
+/// The task may also be woken spuriously, so `park()` should be called in a loop
+/// that checks whether the condition the task is waiting for holds.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn park() {
+  let local = local();
+  local.check_interrupts();
+  if local.current_task().prepare_park() { local.suspend() }
+}
+
+/// Like `park()`, but never unwinds the task because of a timeout or a cancellation.
+fn park_uninterruptibly() {
+  let local = local();
+  if local.current_task().prepare_park() { local.switch() }
+}
+
+/// Returns a handle to the current task, if the thread is running a task of a runtime.
+pub(crate) fn try_current() -> Option<Task> {
+  LOCAL.with(|cell| {
+    cell.borrow().as_ref().and_then(|local| local.current.borrow().as_ref().map(|&(ref task, _)| task.clone()))
+  })
+}
+
+/// Returns the runtime of the current thread.
+fn local() -> Rc<Local> {
+  LOCAL.with(|cell| cell.borrow().clone())
+    .expect("not running inside of fringe::rt::run()")
+}
+
+impl Local {
+  fn spawn<S, F, T>(&self, stack: S, f: F) -> JoinHandle<T>
+      where S: Stack + GuardedStack + 'static, F: FnOnce() -> T + 'static, T: 'static {
+    unsafe { self.spawn_unchecked(stack, f) }
+  }
+
+  /// Like `spawn()`, but lets `f` and its result borrow data, which the caller
+  /// has to keep alive until the task has finished.
+  unsafe fn spawn_unchecked<'a, S, F, T>(&self, stack: S, f: F) -> JoinHandle<T>
+      where S: Stack + GuardedStack + 'static, F: FnOnce() -> T + 'a, T: 'a {
+    let id = self.next_id.get();
+    self.next_id.set(id + 1);
+
+    let task = Task::new(id, &self.shared);
+    let packet = Rc::new(Packet { result: RefCell::new(None), waiter: Cell::new(None) });
+    let yielder = Rc::new(Cell::new(ptr::null()));
+    let generator = {
+      let packet = packet.clone();
+      let yielder = yielder.clone();
+      Generator::new(stack, move |this: &Yielder<(), ()>, ()| {
+        yielder.set(this);
+        let value = f();
+        *packet.result.borrow_mut() = Some(Ok(value))
+      })
+    };
+
+    let fiber: Box<Fiber + 'a> = Box::new(TaskFiber { generator: generator, yielder: yielder, packet: packet.clone() });
+    self.fibers.borrow_mut().insert(id, mem::transmute::<Box<Fiber + 'a>, Box<Fiber>>(fiber));
+    self.shared.schedule(task.clone());
+    JoinHandle { task: task, packet: packet }
+  }
+
+  fn current_task(&self) -> Task {
+    match *self.current.borrow() {
+      Some((ref task, _)) => task.clone(),
+      None => panic!("not running inside of a task")
+    }
+  }
+
+  /// Switches from the current task back to the runtime, and checks
+  /// for interrupts once the task is resumed.
+  fn suspend(&self) {
+    self.switch();
+    self.check_interrupts()
+  }
+
+  /// Switches from the current task back to the runtime.
+  fn switch(&self) {
+    let yielder = match *self.current.borrow() {
+      Some((_, ref yielder)) => yielder.get(),
+      None => panic!("not running inside of a task")
+    };
+    unsafe { (*yielder).suspend(()) }
+  }
+
+  /// Unwinds the current task if the deadline of a pending `timeout()` call has passed,
+  /// or if the scope it has been spawned in has been cancelled.
+  fn check_interrupts(&self) {
+    let id = self.current_task().id();
+    timer::check_timeouts(self.timeouts.borrow().get(&id));
+    let cancelled = self.tokens.borrow().get(&id).map_or(false, |token| token.is_cancelled());
+    if cancelled { panic::resume_unwind(Box::new(Cancelled)) }
+  }
+
+  /// Returns whether `check_interrupts()` would unwind the current task.
+  fn is_interrupted(&self) -> bool {
+    let id = self.current_task().id();
+    timer::expired(self.timeouts.borrow().get(&id)).is_some() ||
+      self.tokens.borrow().get(&id).map_or(false, |token| token.is_cancelled())
+  }
+
+  /// Fires the expired timers, and returns the time until the wheel has to be advanced
+  /// again, if it holds any timers.
+  fn fire_timers(&self) -> Option<Duration> {
+    let now = Instant::now();
+    let (ready, next) = {
+      let mut wheel = self.wheel.borrow_mut();
+      let ready = wheel.advance(now);
+      (ready, wheel.next_deadline())
+    };
+    for task in ready { task.unpark() }
+    next.map(|next| if next > now { next - now } else { Duration::from_millis(0) })
+  }
+
+  /// Runs tasks from the ready queue until every task has finished.
+  fn drive(&self) {
+    loop {
+      let task = match self.next_task() {
+        Some(task) => task,
+        None => return
+      };
+
+      // Take the fiber out while it runs, so that it can spawn other tasks.
+      let mut fiber = match self.fibers.borrow_mut().remove(&task.id()) {
+        Some(fiber) => fiber,
+        None => continue
+      };
+      *self.current.borrow_mut() = Some((task.clone(), fiber.yielder()));
+      let finished = fiber.resume();
+      *self.current.borrow_mut() = None;
+
+      if finished {
+        self.tokens.borrow_mut().remove(&task.id());
+        task.finish()
+      } else {
+        self.fibers.borrow_mut().insert(task.id(), fiber);
+      }
+    }
+  }
+
+  /// Takes the next task from the ready queue, waiting until there is one,
+  /// or returns `None` if every task has finished.
+  fn next_task(&self) -> Option<Task> {
+    // Don't let tasks that are always ready starve the ones waiting for timers or I/O.
+    let ticks = self.ticks.get().wrapping_add(1);
+    self.ticks.set(ticks);
+    if ticks % EVENT_INTERVAL == 0 {
+      self.fire_timers();
+      #[cfg(target_os = "linux")]
+      self.shared.reactor.poll(Some(Duration::from_millis(0))).expect("cannot poll the reactor")
+    }
+
+    let mut ready = self.shared.ready.lock().unwrap();
+    loop {
+      if let Some(task) = ready.tasks.pop_front() { return Some(task) }
+      if self.fibers.borrow().is_empty() { return None }
+
+      // Firing timers makes tasks ready, which needs the lock.
+      drop(ready);
+      let timeout = self.fire_timers();
+      ready = self.shared.ready.lock().unwrap();
+      if !ready.tasks.is_empty() { continue }
+      ready.sleeping = true;
+
+      #[cfg(target_os = "linux")]
+      {
+        drop(ready);
+        self.shared.reactor.poll(timeout).expect("cannot poll the reactor");
+        ready = self.shared.ready.lock().unwrap();
+        ready.sleeping = false
+      }
+
+      #[cfg(not(target_os = "linux"))]
+      {
+        ready = match timeout {
+          Some(timeout) => self.shared.available.wait_timeout(ready, timeout).unwrap().0,
+          None => self.shared.available.wait(ready).unwrap()
+        };
+        ready.sleeping = false
+      }
+    }
+  }
+}
+
+impl Shared {
+  #[cfg(target_os = "linux")]
+  fn new() -> Shared {
+    Shared {
+      ready:   Mutex::new(Ready { tasks: VecDeque::new(), sleeping: false }),
+      reactor: Reactor::new().expect("cannot create the reactor")
+    }
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  fn new() -> Shared {
+    Shared {
+      ready:     Mutex::new(Ready { tasks: VecDeque::new(), sleeping: false }),
+      available: Condvar::new()
+    }
+  }
+
+  /// Adds `task` to the end of the ready queue, and wakes the runtime
+  /// if it is waiting for a task to become ready.
+  pub(crate) fn schedule(&self, task: Task) {
+    let mut ready = self.ready.lock().unwrap();
+    ready.tasks.push_back(task);
+    if mem::replace(&mut ready.sleeping, false) {
+      drop(ready);
+      #[cfg(target_os = "linux")]
+      self.reactor.notify();
+      #[cfg(not(target_os = "linux"))]
+      self.available.notify_one()
+    }
+  }
+}
+
+/// A task, with the type of its stack and its result erased.
+trait Fiber {
+  /// Resumes the task, and returns whether it has finished.
+  fn resume(&mut self) -> bool;
+  fn yielder(&self) -> Rc<Cell<*const Yielder<(), ()>>>;
+}
+
+struct TaskFiber<'a, S: Stack, T: 'a> {
+  generator: Generator<'a, (), (), S>,
+  yielder:   Rc<Cell<*const Yielder<(), ()>>>,
+  packet:    Rc<Packet<T>>
+}
+
+impl<'a, S: Stack, T> Fiber for TaskFiber<'a, S, T> {
+  fn resume(&mut self) -> bool {
+    match self.generator.try_resume(()) {
+      Ok(Some(GeneratorState::Yielded(()))) => return false,
+      Ok(_) => (),
+      Err(payload) => *self.packet.result.borrow_mut() = Some(Err(payload))
+    }
+    if let Some(waiter) = self.packet.waiter.take() { waiter.unpark() }
+    true
+  }
+
+  fn yielder(&self) -> Rc<Cell<*const Yielder<(), ()>>> {
+    self.yielder.clone()
+  }
+}
+
+/// The result of a task, and the task waiting for it.
+struct Packet<T> {
+  result: RefCell<Option<Result<T, Box<Any + Send>>>>,
+  waiter: Cell<Option<Task>>
+}
+
+/// An owned permission to join a task, that is, to wait for it to finish.
+/// If the handle is dropped, the task is detached, and its result is discarded.
+pub struct JoinHandle<T> {
+  task:   Task,
+  packet: Rc<Packet<T>>
+}
+
+impl<T> JoinHandle<T> {
+  /// Returns a handle to the task.
+  #[inline]
+  pub fn task(&self) -> &Task { &self.task }
+
+  /// Returns whether the task has finished.
+  pub fn is_finished(&self) -> bool {
+    self.packet.result.borrow().is_some()
+  }
+
+  /// Waits for the task to finish, and returns its result. If the task panics,
+  /// returns the panic payload.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the task has not finished and `join()` is called outside of a task,
+  /// or by the task itself.
+  pub fn join(self) -> Result<T, Box<Any + Send>> {
+    loop {
+      if let Some(result) = self.packet.result.borrow_mut().take() { return result }
+      let current = current();
+      assert!(current != self.task, "a task cannot join itself");
+      self.packet.waiter.set(Some(current));
+      park()
+    }
+  }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("JoinHandle").field("task", &self.task).finish()
+  }
+}
@@ -0,0 +1,587 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A multi-threaded green thread runtime.
+//!
+//! An [Executor](struct.Executor.html) runs tasks on a fixed number of worker threads.
+//! Every worker has its own queue of ready tasks; a worker whose queue is empty steals
+//! half of the tasks of another one. A suspended task can be resumed by any worker,
+//! so tasks are generators created using [Generator::new_send](../../generator/struct.Generator.html#method.new_send),
+//! and everything they hold while suspended has to be `Send`. The compiler cannot check
+//! this, so spawning a task is unsafe, and the caller has to uphold the contract
+//! described by `Executor::spawn`.
+//!
+//! # Example
+//!
+//! ```
+//! use fringe::rt::mt::{self, Executor};
+//!
+//! let executor = Executor::new(4);
+//! // The tasks hold nothing but integers and join handles while suspended.
+//! let handle = unsafe {
+//!   executor.spawn(|| {
+//!     let handles = (0..100u64).map(|i| {
+//!       mt::spawn(move || {
+//!         mt::yield_now();
+//!         i * i
+//!       })
+//!     }).collect::<Vec<_>>();
+//!     handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u64>()
+//!   })
+//! };
+//! assert_eq!(handle.join().unwrap(), 328350);
+//! executor.shutdown();
+//! ```
+extern crate std;
+
+use core::fmt;
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Weak, Mutex, Condvar};
+use self::std::thread;
+use self::std::vec::Vec;
+use stack::{StackPool, PooledStack};
+use generator::{Generator, GeneratorState, SendGenerator, Yielder};
+use super::DEFAULT_STACK_SIZE;
+
+/// The task is running, or is in a ready queue.
+const RUNNING:  usize = 0;
+/// The task is running, and `unpark()` has been called since it last parked.
+const NOTIFIED: usize = 1;
+/// The task is suspended in `park()`.
+const PARKED:   usize = 2;
+/// The task has finished.
+const DONE:     usize = 3;
+
+/// A multi-threaded runtime with work-stealing workers.
+///
+/// Dropping the executor, or calling `shutdown()`, waits for every task to finish,
+/// and then stops the workers.
+pub struct Executor {
+  shared:  Arc<Shared>,
+  workers: Vec<thread::JoinHandle<()>>
+}
+
+struct Shared {
+  pool:     StackPool,
+  /// Tasks spawned or unparked from outside of the workers.
+  injector: Mutex<VecDeque<Task>>,
+  queues:   Vec<Mutex<VecDeque<Task>>>,
+  sleep:    Mutex<Sleep>,
+  wakeup:   Condvar,
+  /// The number of tasks that have not finished.
+  live:     Mutex<usize>,
+  finished: Condvar,
+  next_id:  AtomicUsize
+}
+
+struct Sleep {
+  sleepers: usize,
+  shutdown: bool
+}
+
+/// What the current task wants once it suspends itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+  Yield,
+  Park
+}
+
+/// The worker the current thread is, and the task it is running.
+struct Worker {
+  shared:  Arc<Shared>,
+  index:   usize,
+  current: Option<(Task, Arc<AtomicUsize>)>,
+  reason:  Cell<Reason>
+}
+
+self::std::thread_local! {
+  static WORKER: RefCell<Option<Worker>> = RefCell::new(None);
+}
+
+impl Executor {
+  /// Starts an executor with `workers` worker threads, which allocates stacks
+  /// of `DEFAULT_STACK_SIZE` bytes.
+  pub fn new(workers: usize) -> Executor {
+    Executor::with_pool(workers, StackPool::new(DEFAULT_STACK_SIZE))
+  }
+
+  /// Starts an executor with `workers` worker threads, which takes stacks from `pool`.
+  pub fn with_pool(workers: usize, pool: StackPool) -> Executor {
+    assert!(workers > 0, "an executor needs at least one worker");
+    let shared = Arc::new(Shared {
+      pool:     pool,
+      injector: Mutex::new(VecDeque::new()),
+      queues:   (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+      sleep:    Mutex::new(Sleep { sleepers: 0, shutdown: false }),
+      wakeup:   Condvar::new(),
+      live:     Mutex::new(0),
+      finished: Condvar::new(),
+      next_id:  AtomicUsize::new(0)
+    });
+
+    let workers = (0..workers).map(|index| {
+      let shared = shared.clone();
+      thread::Builder::new()
+        .name(self::std::format!("fringe-worker-{}", index))
+        .spawn(move || run_worker(shared, index))
+        .expect("cannot spawn a worker thread")
+    }).collect();
+
+    Executor { shared: shared, workers: workers }
+  }
+
+  /// Spawns `f` as a task on a stack from the pool of the executor.
+  ///
+  /// # Safety
+  ///
+  /// The task may be suspended on one worker and resumed on another, so whatever `f`
+  /// holds across a suspension point, i.e. a call to `yield_now()` or `park()`, or to
+  /// anything that may call them, such as joining a task or waiting for a primitive
+  /// from [sync](../../sync/index.html), must be `Send`. In particular, `f` must not hold
+  /// an `Rc`, a `std::sync::MutexGuard`, or a reference to a thread-local value
+  /// across one. See also [Generator::new_send](../../generator/struct.Generator.html#method.new_send).
+  pub unsafe fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+      where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    Shared::spawn(&self.shared, None, f)
+  }
+
+  /// Waits for every task to finish, and stops the workers.
+  pub fn shutdown(self) {}
+}
+
+impl Drop for Executor {
+  fn drop(&mut self) {
+    {
+      let mut live = self.shared.live.lock().unwrap();
+      while *live > 0 { live = self.shared.finished.wait(live).unwrap() }
+    }
+    self.shared.sleep.lock().unwrap().shutdown = true;
+    self.shared.wakeup.notify_all();
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
+
+impl fmt::Debug for Executor {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Executor").field("workers", &self.workers.len()).finish()
+  }
+}
+
+/// Spawns `f` as a task on the executor running the current task.
+///
+/// # Safety
+///
+/// The same as for [Executor::spawn](struct.Executor.html#method.spawn).
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub unsafe fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+  let (shared, index) = worker(|worker| (worker.shared.clone(), worker.index));
+  Shared::spawn(&shared, Some(index), f)
+}
+
+/// Returns a handle to the current task.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn current() -> Task {
+  worker(|worker| worker.task().clone())
+}
+
+/// Suspends the current task, and adds it to the end of the ready queue.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn yield_now() {
+  suspend(Reason::Yield)
+}
+
+/// Suspends the current task until `unpark()` is called on its handle. If `unpark()`
+/// has been called since the task last parked, returns immediately.
+///
+/// The task may also be woken spuriously, so `park()` should be called in a loop
+/// that checks whether the condition the task is waiting for holds.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn park() {
+  let notified = worker(|worker| {
+    worker.task().inner.state.compare_exchange(NOTIFIED, RUNNING, Ordering::AcqRel, Ordering::Acquire).is_ok()
+  });
+  if !notified { suspend(Reason::Park) }
+}
+
+/// Returns whether the current thread is running a task of an executor.
+pub fn in_task() -> bool {
+  try_current().is_some()
+}
+
+/// Returns a handle to the current task, if the thread is running a task of an executor.
+#[inline(never)]
+pub(crate) fn try_current() -> Option<Task> {
+  WORKER.with(|cell| {
+    cell.borrow().as_ref().and_then(|worker| worker.current.as_ref().map(|&(ref task, _)| task.clone()))
+  })
+}
+
+// The task may be resumed on another thread, so the addresses of thread-local values
+// must not be kept across the switch; these are never inlined into their callers.
+
+#[inline(never)]
+fn worker<F: FnOnce(&Worker) -> R, R>(f: F) -> R {
+  WORKER.with(|cell| {
+    match *cell.borrow() {
+      Some(ref worker) if worker.current.is_some() => f(worker),
+      _ => panic!("not running inside of a task of an Executor")
+    }
+  })
+}
+
+#[inline(never)]
+fn suspend(reason: Reason) {
+  let yielder = worker(|worker| {
+    worker.reason.set(reason);
+    worker.current.as_ref().unwrap().1.load(Ordering::Relaxed)
+  });
+  unsafe { (*(yielder as *const Yielder<(), ()>)).suspend(()) }
+}
+
+impl Worker {
+  fn task(&self) -> &Task {
+    &self.current.as_ref().unwrap().0
+  }
+}
+
+fn run_worker(shared: Arc<Shared>, index: usize) {
+  WORKER.with(|cell| {
+    *cell.borrow_mut() = Some(Worker {
+      shared:  shared.clone(),
+      index:   index,
+      current: None,
+      reason:  Cell::new(Reason::Yield)
+    })
+  });
+
+  let mut rng = index as u32 + 1;
+  loop {
+    let task = match shared.find_task(index, &mut rng) {
+      Some(task) => task,
+      None => if shared.sleep_until_work() { continue } else { break }
+    };
+    shared.run(index, task)
+  }
+
+  WORKER.with(|cell| *cell.borrow_mut() = None)
+}
+
+impl Shared {
+  fn spawn<F, T>(shared: &Arc<Shared>, index: Option<usize>, f: F) -> JoinHandle<T>
+      where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    let stack = shared.pool.get().expect("cannot allocate a stack");
+    let packet = Arc::new(Packet { state: Mutex::new((None, None)) });
+    let yielder = Arc::new(AtomicUsize::new(0));
+    let generator = {
+      let packet = packet.clone();
+      let yielder = yielder.clone();
+      // The callers of spawn() uphold the contract of new_send().
+      unsafe {
+        Generator::new_send(stack, move |this: &Yielder<(), ()>, ()| {
+          yielder.store(this as *const Yielder<(), ()> as usize, Ordering::Relaxed);
+          let value = f();
+          packet.complete(Ok(value))
+        })
+      }
+    };
+
+    *shared.live.lock().unwrap() += 1;
+    let task = Task {
+      inner: Arc::new(TaskInner {
+        id:     shared.next_id.fetch_add(1, Ordering::Relaxed),
+        state:  AtomicUsize::new(RUNNING),
+        fiber:  Mutex::new(Some(Box::new(TaskFiber {
+          generator: generator,
+          yielder:   yielder,
+          packet:    packet.clone()
+        }))),
+        shared: Arc::downgrade(shared)
+      })
+    };
+    shared.push(index, task.clone());
+    JoinHandle { task: task, packet: packet }
+  }
+
+  /// Adds `task` to the queue of the worker `index`, or to the injector queue.
+  fn push(&self, index: Option<usize>, task: Task) {
+    match index {
+      Some(index) => self.queues[index].lock().unwrap().push_back(task),
+      None => self.injector.lock().unwrap().push_back(task)
+    }
+    // Waking a worker requires the lock, so that a worker that is about to sleep
+    // either sees the task, or is already waiting.
+    if self.sleep.lock().unwrap().sleepers > 0 { self.wakeup.notify_one() }
+  }
+
+  fn find_task(&self, index: usize, rng: &mut u32) -> Option<Task> {
+    if let Some(task) = self.queues[index].lock().unwrap().pop_front() { return Some(task) }
+    if let Some(task) = self.injector.lock().unwrap().pop_front() { return Some(task) }
+
+    // Steal half of the tasks of another worker, starting at a random one.
+    *rng ^= *rng << 13;
+    *rng ^= *rng >> 17;
+    *rng ^= *rng << 5;
+    let count = self.queues.len();
+    let start = *rng as usize % count;
+    for offset in 0..count {
+      let victim = (start + offset) % count;
+      if victim == index { continue }
+      let mut stolen = {
+        let mut queue = self.queues[victim].lock().unwrap();
+        let len = queue.len();
+        queue.split_off(len / 2)
+      };
+      if let Some(task) = stolen.pop_front() {
+        self.queues[index].lock().unwrap().extend(stolen);
+        return Some(task)
+      }
+    }
+    None
+  }
+
+  /// Waits until there might be a task to run, and returns `true`, or returns `false`
+  /// if the executor is shutting down.
+  fn sleep_until_work(&self) -> bool {
+    let mut sleep = self.sleep.lock().unwrap();
+    if sleep.shutdown { return false }
+    let has_work = !self.injector.lock().unwrap().is_empty() ||
+                   self.queues.iter().any(|queue| !queue.lock().unwrap().is_empty());
+    if !has_work {
+      sleep.sleepers += 1;
+      sleep = self.wakeup.wait(sleep).unwrap();
+      sleep.sleepers -= 1;
+    }
+    true
+  }
+
+  fn run(&self, index: usize, task: Task) {
+    let mut fiber = task.inner.fiber.lock().unwrap().take().expect("task resumed twice");
+    WORKER.with(|cell| {
+      cell.borrow_mut().as_mut().unwrap().current = Some((task.clone(), fiber.yielder()))
+    });
+    let finished = fiber.resume();
+    let reason = WORKER.with(|cell| {
+      let mut cell = cell.borrow_mut();
+      let worker = cell.as_mut().unwrap();
+      worker.current = None;
+      worker.reason.get()
+    });
+
+    if finished {
+      task.inner.state.store(DONE, Ordering::Release);
+      drop(fiber);
+      let mut live = self.live.lock().unwrap();
+      *live -= 1;
+      if *live == 0 { self.finished.notify_all() }
+      return
+    }
+
+    // The fiber has to be back in place before any other worker can pick up the task.
+    *task.inner.fiber.lock().unwrap() = Some(fiber);
+    match reason {
+      Reason::Yield => self.push(Some(index), task),
+      Reason::Park => {
+        // If the task has been unparked while it was still running, it is not
+        // parked at all, and has to run again.
+        if task.inner.state.compare_exchange(RUNNING, PARKED, Ordering::AcqRel, Ordering::Acquire).is_err() {
+          task.inner.state.store(RUNNING, Ordering::Release);
+          self.push(Some(index), task)
+        }
+      }
+    }
+  }
+}
+
+/// A handle to a task spawned on an executor.
+///
+/// Task handles are cheap to clone, and can be sent to other threads, which can
+/// then wake the task using `unpark()`.
+#[derive(Clone)]
+pub struct Task {
+  inner: Arc<TaskInner>
+}
+
+struct TaskInner {
+  id:     usize,
+  state:  AtomicUsize,
+  fiber:  Mutex<Option<Box<Fiber + Send>>>,
+  shared: Weak<Shared>
+}
+
+impl Task {
+  /// Returns the identifier of the task, which is unique within its executor.
+  #[inline]
+  pub fn id(&self) -> usize { self.inner.id }
+
+  /// Wakes the task if it is suspended in `park()`. Otherwise, the next call to `park()`
+  /// made by the task returns immediately.
+  pub fn unpark(&self) {
+    let mut state = self.inner.state.load(Ordering::Acquire);
+    loop {
+      let next = match state {
+        RUNNING => NOTIFIED,
+        PARKED  => RUNNING,
+        _ => return
+      };
+      match self.inner.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => break,
+        Err(actual) => state = actual
+      }
+    }
+
+    if state == PARKED {
+      if let Some(shared) = self.inner.shared.upgrade() {
+        // Prefer the queue of the current worker, if it belongs to the same executor.
+        let index = WORKER.with(|cell| {
+          cell.borrow().as_ref().and_then(|worker| {
+            if Arc::ptr_eq(&worker.shared, &shared) { Some(worker.index) } else { None }
+          })
+        });
+        shared.push(index, self.clone())
+      }
+    }
+  }
+}
+
+impl PartialEq for Task {
+  fn eq(&self, other: &Task) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+}
+
+impl Eq for Task {}
+
+impl fmt::Debug for Task {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Task").field("id", &self.inner.id).finish()
+  }
+}
+
+/// A task, with the type of its result erased.
+trait Fiber {
+  /// Resumes the task, and returns whether it has finished.
+  fn resume(&mut self) -> bool;
+  fn yielder(&self) -> Arc<AtomicUsize>;
+}
+
+struct TaskFiber<T> {
+  generator: SendGenerator<'static, (), (), PooledStack>,
+  yielder:   Arc<AtomicUsize>,
+  packet:    Arc<Packet<T>>
+}
+
+impl<T> Fiber for TaskFiber<T> {
+  fn resume(&mut self) -> bool {
+    match self.generator.try_resume(()) {
+      Ok(Some(GeneratorState::Yielded(()))) => false,
+      Ok(_) => true,
+      Err(payload) => {
+        self.packet.complete(Err(payload));
+        true
+      }
+    }
+  }
+
+  fn yielder(&self) -> Arc<AtomicUsize> {
+    self.yielder.clone()
+  }
+}
+
+/// What is waiting for a task to finish.
+enum Waiter {
+  Task(Task),
+  Thread(thread::Thread)
+}
+
+/// The result of a task, and what is waiting for it.
+struct Packet<T> {
+  state: Mutex<(Option<Result<T, Box<Any + Send>>>, Option<Waiter>)>
+}
+
+impl<T> Packet<T> {
+  fn complete(&self, result: Result<T, Box<Any + Send>>) {
+    let waiter = {
+      let mut state = self.state.lock().unwrap();
+      state.0 = Some(result);
+      state.1.take()
+    };
+    match waiter {
+      Some(Waiter::Task(task)) => task.unpark(),
+      Some(Waiter::Thread(thread)) => thread.unpark(),
+      None => ()
+    }
+  }
+}
+
+/// An owned permission to join a task, that is, to wait for it to finish.
+/// If the handle is dropped, the task is detached, and its result is discarded.
+pub struct JoinHandle<T> {
+  task:   Task,
+  packet: Arc<Packet<T>>
+}
+
+impl<T> JoinHandle<T> {
+  /// Returns a handle to the task.
+  #[inline]
+  pub fn task(&self) -> &Task { &self.task }
+
+  /// Returns whether the task has finished.
+  pub fn is_finished(&self) -> bool {
+    self.packet.state.lock().unwrap().0.is_some()
+  }
+
+  /// Waits for the task to finish, and returns its result. If the task panics,
+  /// returns the panic payload.
+  ///
+  /// Inside of a task, the current task is suspended; otherwise, the thread is blocked.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called by the task itself.
+  pub fn join(self) -> Result<T, Box<Any + Send>> {
+    let in_task = in_task();
+    loop {
+      {
+        let mut state = self.packet.state.lock().unwrap();
+        if let Some(result) = state.0.take() { return result }
+        state.1 = Some(if in_task {
+          let current = current();
+          assert!(current != self.task, "a task cannot join itself");
+          Waiter::Task(current)
+        } else {
+          Waiter::Thread(thread::current())
+        });
+      }
+      if in_task { park() } else { thread::park() }
+    }
+  }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("JoinHandle").field("task", &self.task).finish()
+  }
+}
@@ -0,0 +1,340 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Fiber-aware sockets.
+//!
+//! These are counterparts of the sockets in `std::net` and `std::os::unix::net`
+//! whose operations make the current task wait instead of blocking the thread.
+//! See also [io](../io/index.html).
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use fringe::rt;
+//! use fringe::rt::net::{TcpListener, TcpStream};
+//!
+//! rt::run(|| {
+//!   let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+//!   let addr = listener.local_addr().unwrap();
+//!   let client = rt::spawn(rt::stack(), move || {
+//!     let mut stream = TcpStream::connect(addr).unwrap();
+//!     stream.write_all(b"ping").unwrap();
+//!   });
+//!   let (mut stream, _) = listener.accept().unwrap();
+//!   let mut buf = Vec::new();
+//!   stream.read_to_end(&mut buf).unwrap();
+//!   assert_eq!(buf, b"ping");
+//!   client.join().unwrap();
+//! });
+//! ```
+extern crate std;
+extern crate libc;
+
+use core::mem;
+use self::std::io::{self, Read, Write, Error as IoError};
+use self::std::net::{self, SocketAddr, ToSocketAddrs, Shutdown};
+use self::std::os::unix::ffi::OsStrExt;
+use self::std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use self::std::os::unix::net as unix;
+use self::std::path::Path;
+use self::libc::{c_int, sockaddr, socklen_t};
+use super::io::{self as rt_io, Interest};
+
+/// Creates a non-blocking, close-on-exec stream socket.
+fn socket(family: c_int) -> Result<RawFd, IoError> {
+  match unsafe { libc::socket(family, libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0) } {
+    -1 => Err(IoError::last_os_error()),
+    fd => Ok(fd)
+  }
+}
+
+/// A TCP socket server, listening for connections.
+#[derive(Debug)]
+pub struct TcpListener {
+  inner: net::TcpListener
+}
+
+impl TcpListener {
+  /// Creates a listener bound to `addr`. See `std::net::TcpListener::bind`.
+  pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<TcpListener, IoError> {
+    TcpListener::from_std(try!(net::TcpListener::bind(addr)))
+  }
+
+  /// Converts a listener from `std`, making it non-blocking.
+  pub fn from_std(listener: net::TcpListener) -> Result<TcpListener, IoError> {
+    try!(listener.set_nonblocking(true));
+    Ok(TcpListener { inner: listener })
+  }
+
+  /// Accepts a new connection, waiting until there is one.
+  pub fn accept(&self) -> Result<(TcpStream, SocketAddr), IoError> {
+    let (stream, addr) = try!(rt_io::retry(self.as_raw_fd(), Interest::Read, || self.inner.accept()));
+    Ok((try!(TcpStream::from_std(stream)), addr))
+  }
+
+  /// Returns the local address of the listener.
+  pub fn local_addr(&self) -> Result<SocketAddr, IoError> {
+    self.inner.local_addr()
+  }
+
+  /// Returns the underlying `std` listener.
+  pub fn get_ref(&self) -> &net::TcpListener { &self.inner }
+}
+
+impl AsRawFd for TcpListener {
+  fn as_raw_fd(&self) -> RawFd { self.inner.as_raw_fd() }
+}
+
+impl Drop for TcpListener {
+  fn drop(&mut self) {
+    rt_io::forget(self.as_raw_fd())
+  }
+}
+
+/// A TCP stream between a local and a remote socket.
+#[derive(Debug)]
+pub struct TcpStream {
+  inner: net::TcpStream
+}
+
+impl TcpStream {
+  /// Opens a connection to `addr`, waiting until it is established. If `addr` yields
+  /// several addresses, each of them is tried in turn.
+  pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpStream, IoError> {
+    let mut last_err = None;
+    for addr in try!(addr.to_socket_addrs()) {
+      match TcpStream::connect_addr(&addr) {
+        Ok(stream) => return Ok(stream),
+        Err(err) => last_err = Some(err)
+      }
+    }
+    Err(last_err.unwrap_or_else(|| {
+      IoError::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+    }))
+  }
+
+  fn connect_addr(addr: &SocketAddr) -> Result<TcpStream, IoError> {
+    unsafe {
+      let (family, storage, len) = match *addr {
+        SocketAddr::V4(ref addr) => {
+          let mut sin: libc::sockaddr_in = mem::zeroed();
+          sin.sin_family = libc::AF_INET as libc::sa_family_t;
+          sin.sin_port = addr.port().to_be();
+          sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+          let mut storage: libc::sockaddr_storage = mem::zeroed();
+          *(&mut storage as *mut _ as *mut libc::sockaddr_in) = sin;
+          (libc::AF_INET, storage, mem::size_of::<libc::sockaddr_in>())
+        }
+        SocketAddr::V6(ref addr) => {
+          let mut sin6: libc::sockaddr_in6 = mem::zeroed();
+          sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+          sin6.sin6_port = addr.port().to_be();
+          sin6.sin6_flowinfo = addr.flowinfo();
+          sin6.sin6_addr.s6_addr = addr.ip().octets();
+          sin6.sin6_scope_id = addr.scope_id();
+          let mut storage: libc::sockaddr_storage = mem::zeroed();
+          *(&mut storage as *mut _ as *mut libc::sockaddr_in6) = sin6;
+          (libc::AF_INET6, storage, mem::size_of::<libc::sockaddr_in6>())
+        }
+      };
+
+      // Let the stream own the socket right away, so that it is closed on failure.
+      let stream = TcpStream { inner: net::TcpStream::from_raw_fd(try!(socket(family))) };
+      try!(rt_io::connect(stream.as_raw_fd(), &storage as *const _ as *const sockaddr,
+                          len as socklen_t));
+      Ok(stream)
+    }
+  }
+
+  /// Converts a stream from `std`, making it non-blocking.
+  pub fn from_std(stream: net::TcpStream) -> Result<TcpStream, IoError> {
+    try!(stream.set_nonblocking(true));
+    Ok(TcpStream { inner: stream })
+  }
+
+  /// Returns the local address of the stream.
+  pub fn local_addr(&self) -> Result<SocketAddr, IoError> {
+    self.inner.local_addr()
+  }
+
+  /// Returns the remote address of the stream.
+  pub fn peer_addr(&self) -> Result<SocketAddr, IoError> {
+    self.inner.peer_addr()
+  }
+
+  /// Shuts down the read half, the write half, or both halves of the stream.
+  pub fn shutdown(&self, how: Shutdown) -> Result<(), IoError> {
+    self.inner.shutdown(how)
+  }
+
+  /// Sets the value of the `TCP_NODELAY` option of the stream.
+  pub fn set_nodelay(&self, nodelay: bool) -> Result<(), IoError> {
+    self.inner.set_nodelay(nodelay)
+  }
+
+  /// Returns the underlying `std` stream.
+  pub fn get_ref(&self) -> &net::TcpStream { &self.inner }
+}
+
+impl<'a> Read for &'a TcpStream {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    rt_io::retry(self.as_raw_fd(), Interest::Read, || (&self.inner).read(buf))
+  }
+}
+
+impl<'a> Write for &'a TcpStream {
+  fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+    rt_io::retry(self.as_raw_fd(), Interest::Write, || (&self.inner).write(buf))
+  }
+
+  fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl Read for TcpStream {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> { (&*self).read(buf) }
+}
+
+impl Write for TcpStream {
+  fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> { (&*self).write(buf) }
+  fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl AsRawFd for TcpStream {
+  fn as_raw_fd(&self) -> RawFd { self.inner.as_raw_fd() }
+}
+
+impl Drop for TcpStream {
+  fn drop(&mut self) {
+    rt_io::forget(self.as_raw_fd())
+  }
+}
+
+/// A Unix domain socket server, listening for connections.
+#[derive(Debug)]
+pub struct UnixListener {
+  inner: unix::UnixListener
+}
+
+impl UnixListener {
+  /// Creates a listener bound to `path`. See `std::os::unix::net::UnixListener::bind`.
+  pub fn bind<P: AsRef<Path>>(path: P) -> Result<UnixListener, IoError> {
+    UnixListener::from_std(try!(unix::UnixListener::bind(path)))
+  }
+
+  /// Converts a listener from `std`, making it non-blocking.
+  pub fn from_std(listener: unix::UnixListener) -> Result<UnixListener, IoError> {
+    try!(listener.set_nonblocking(true));
+    Ok(UnixListener { inner: listener })
+  }
+
+  /// Accepts a new connection, waiting until there is one.
+  pub fn accept(&self) -> Result<(UnixStream, unix::SocketAddr), IoError> {
+    let (stream, addr) = try!(rt_io::retry(self.as_raw_fd(), Interest::Read, || self.inner.accept()));
+    Ok((try!(UnixStream::from_std(stream)), addr))
+  }
+
+  /// Returns the local address of the listener.
+  pub fn local_addr(&self) -> Result<unix::SocketAddr, IoError> {
+    self.inner.local_addr()
+  }
+
+  /// Returns the underlying `std` listener.
+  pub fn get_ref(&self) -> &unix::UnixListener { &self.inner }
+}
+
+impl AsRawFd for UnixListener {
+  fn as_raw_fd(&self) -> RawFd { self.inner.as_raw_fd() }
+}
+
+impl Drop for UnixListener {
+  fn drop(&mut self) {
+    rt_io::forget(self.as_raw_fd())
+  }
+}
+
+/// A Unix domain stream socket.
+#[derive(Debug)]
+pub struct UnixStream {
+  inner: unix::UnixStream
+}
+
+impl UnixStream {
+  /// Opens a connection to the socket at `path`, waiting until it is established.
+  pub fn connect<P: AsRef<Path>>(path: P) -> Result<UnixStream, IoError> {
+    unsafe {
+      let mut addr: libc::sockaddr_un = mem::zeroed();
+      addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+      let path = path.as_ref().as_os_str().as_bytes();
+      // Leave room for the terminating NUL.
+      if path.len() >= addr.sun_path.len() {
+        return Err(IoError::new(io::ErrorKind::InvalidInput, "path must be shorter than SUN_LEN"))
+      }
+      for (dst, &src) in addr.sun_path.iter_mut().zip(path) { *dst = src as libc::c_char }
+      let len = mem::size_of::<libc::sa_family_t>() + path.len() + 1;
+
+      // Let the stream own the socket right away, so that it is closed on failure.
+      let stream = UnixStream { inner: unix::UnixStream::from_raw_fd(try!(socket(libc::AF_UNIX))) };
+      try!(rt_io::connect(stream.as_raw_fd(), &addr as *const _ as *const sockaddr,
+                          len as socklen_t));
+      Ok(stream)
+    }
+  }
+
+  /// Creates an unnamed pair of connected sockets.
+  pub fn pair() -> Result<(UnixStream, UnixStream), IoError> {
+    let (a, b) = try!(unix::UnixStream::pair());
+    Ok((try!(UnixStream::from_std(a)), try!(UnixStream::from_std(b))))
+  }
+
+  /// Converts a stream from `std`, making it non-blocking.
+  pub fn from_std(stream: unix::UnixStream) -> Result<UnixStream, IoError> {
+    try!(stream.set_nonblocking(true));
+    Ok(UnixStream { inner: stream })
+  }
+
+  /// Shuts down the read half, the write half, or both halves of the stream.
+  pub fn shutdown(&self, how: Shutdown) -> Result<(), IoError> {
+    self.inner.shutdown(how)
+  }
+
+  /// Returns the underlying `std` stream.
+  pub fn get_ref(&self) -> &unix::UnixStream { &self.inner }
+}
+
+impl<'a> Read for &'a UnixStream {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    rt_io::retry(self.as_raw_fd(), Interest::Read, || (&self.inner).read(buf))
+  }
+}
+
+impl<'a> Write for &'a UnixStream {
+  fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+    rt_io::retry(self.as_raw_fd(), Interest::Write, || (&self.inner).write(buf))
+  }
+
+  fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl Read for UnixStream {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> { (&*self).read(buf) }
+}
+
+impl Write for UnixStream {
+  fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> { (&*self).write(buf) }
+  fn flush(&mut self) -> Result<(), IoError> { Ok(()) }
+}
+
+impl AsRawFd for UnixStream {
+  fn as_raw_fd(&self) -> RawFd { self.inner.as_raw_fd() }
+}
+
+impl Drop for UnixStream {
+  fn drop(&mut self) {
+    rt_io::forget(self.as_raw_fd())
+  }
+}
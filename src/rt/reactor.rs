@@ -0,0 +1,195 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+extern crate libc;
+
+use core::mem;
+use self::std::collections::HashMap;
+use self::std::io::{self, Error as IoError};
+use self::std::os::unix::io::RawFd;
+use self::std::sync::Mutex;
+use self::std::time::Duration;
+use self::std::vec::Vec;
+use self::libc::c_int;
+use super::Task;
+
+/// The epoll data of the eventfd used by `notify()`.
+const NOTIFY: u64 = !0;
+
+/// The readiness a task waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+  /// The file descriptor is readable, or has been closed by the peer.
+  Read,
+  /// The file descriptor is writable.
+  Write
+}
+
+/// Reactor waits for readiness of file descriptors using `epoll`, and wakes
+/// the tasks waiting for it.
+///
+/// Every file descriptor that tasks wait for is registered in one-shot mode,
+/// so that it is reported once per call to `wait()`, and never after it is closed.
+#[derive(Debug)]
+pub struct Reactor {
+  epoll:   RawFd,
+  event:   RawFd,
+  waiters: Mutex<HashMap<RawFd, Waiters>>
+}
+
+#[derive(Debug, Default)]
+struct Waiters {
+  read:  Vec<Task>,
+  write: Vec<Task>
+}
+
+fn cvt(ret: c_int) -> Result<c_int, IoError> {
+  if ret < 0 { Err(IoError::last_os_error()) } else { Ok(ret) }
+}
+
+impl Reactor {
+  pub fn new() -> Result<Reactor, IoError> {
+    unsafe {
+      let epoll = try!(cvt(libc::epoll_create1(libc::EPOLL_CLOEXEC)));
+      let event = match cvt(libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK)) {
+        Ok(event) => event,
+        Err(err) => {
+          libc::close(epoll);
+          return Err(err)
+        }
+      };
+      // Construct the reactor first, so that both descriptors are closed on failure.
+      let reactor = Reactor { epoll: epoll, event: event, waiters: Mutex::new(HashMap::new()) };
+      let mut ev = libc::epoll_event { events: libc::EPOLLIN as u32, u64: NOTIFY };
+      try!(cvt(libc::epoll_ctl(epoll, libc::EPOLL_CTL_ADD, event, &mut ev)));
+      Ok(reactor)
+    }
+  }
+
+  /// Makes a pending or the next call to `poll()` return.
+  pub fn notify(&self) {
+    let one = 1u64;
+    unsafe { libc::write(self.event, &one as *const u64 as *const _, mem::size_of::<u64>()) };
+  }
+
+  /// Makes `task` wait for `fd` to become ready for `interest`. Once it does,
+  /// or an error or hangup is reported, `poll()` unparks the task.
+  pub fn wait(&self, fd: RawFd, interest: Interest, task: Task) -> Result<(), IoError> {
+    let mut waiters = self.waiters.lock().unwrap();
+    {
+      let entry = waiters.entry(fd).or_insert_with(Waiters::default);
+      let list = match interest {
+        Interest::Read  => &mut entry.read,
+        Interest::Write => &mut entry.write
+      };
+      if !list.contains(&task) { list.push(task) }
+    }
+    match self.arm(fd, &waiters[&fd]) {
+      Ok(()) => Ok(()),
+      Err(err) => {
+        waiters.remove(&fd);
+        Err(err)
+      }
+    }
+  }
+
+  /// Forgets about the tasks waiting for `fd`, which is about to be closed.
+  pub fn forget(&self, fd: RawFd) {
+    let mut waiters = self.waiters.lock().unwrap();
+    waiters.remove(&fd);
+    // The descriptor may have been duplicated, in which case closing it
+    // does not remove it from the epoll set.
+    unsafe { libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_DEL, fd, 0 as *mut _) };
+  }
+
+  /// Enables reporting of the readiness the tasks waiting for `fd` are interested in.
+  fn arm(&self, fd: RawFd, waiters: &Waiters) -> Result<(), IoError> {
+    let mut events = libc::EPOLLONESHOT;
+    if !waiters.read.is_empty()  { events |= libc::EPOLLIN | libc::EPOLLRDHUP }
+    if !waiters.write.is_empty() { events |= libc::EPOLLOUT }
+    let mut ev = libc::epoll_event { events: events as u32, u64: fd as u64 };
+    unsafe {
+      match cvt(libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_MOD, fd, &mut ev)) {
+        Err(ref err) if err.raw_os_error() == Some(libc::ENOENT) =>
+          cvt(libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_ADD, fd, &mut ev)).map(|_| ()),
+        result => result.map(|_| ())
+      }
+    }
+  }
+
+  /// Waits for readiness for at most `timeout`, or indefinitely if it is `None`,
+  /// and unparks the tasks waiting for it.
+  pub fn poll(&self, timeout: Option<Duration>) -> Result<(), IoError> {
+    let timeout = match timeout {
+      None => -1,
+      // Round up, so that the caller does not wake up before its deadline.
+      Some(timeout) => {
+        let millis = timeout.as_secs().saturating_mul(1000)
+                            .saturating_add((timeout.subsec_nanos() as u64 + 999_999) / 1_000_000);
+        if millis > c_int::max_value() as u64 { c_int::max_value() } else { millis as c_int }
+      }
+    };
+
+    let mut events: [libc::epoll_event; 64] = unsafe { mem::zeroed() };
+    let count = match cvt(unsafe {
+      libc::epoll_wait(self.epoll, events.as_mut_ptr(), events.len() as c_int, timeout)
+    }) {
+      Ok(count) => count as usize,
+      Err(ref err) if err.kind() == io::ErrorKind::Interrupted => 0,
+      Err(err) => return Err(err)
+    };
+
+    let mut ready = Vec::new();
+    {
+      let mut waiters = self.waiters.lock().unwrap();
+      for event in &events[..count] {
+        let (flags, data) = (event.events as c_int, event.u64);
+        if data == NOTIFY {
+          let mut value = 0u64;
+          unsafe { libc::read(self.event, &mut value as *mut u64 as *mut _, mem::size_of::<u64>()) };
+          continue
+        }
+
+        let fd = data as RawFd;
+        let rearm = match waiters.get_mut(&fd) {
+          Some(entry) => {
+            let failed = flags & (libc::EPOLLERR | libc::EPOLLHUP) != 0;
+            if failed || flags & (libc::EPOLLIN | libc::EPOLLRDHUP) != 0 {
+              ready.extend(entry.read.drain(..))
+            }
+            if failed || flags & libc::EPOLLOUT != 0 {
+              ready.extend(entry.write.drain(..))
+            }
+            !entry.read.is_empty() || !entry.write.is_empty()
+          }
+          None => false
+        };
+        if rearm {
+          // If this fails, the remaining tasks are woken, and find out themselves.
+          if self.arm(fd, &waiters[&fd]).is_err() {
+            let entry = waiters.remove(&fd).unwrap();
+            ready.extend(entry.read.into_iter().chain(entry.write.into_iter()))
+          }
+        } else {
+          waiters.remove(&fd);
+        }
+      }
+    }
+
+    for task in ready { task.unpark() }
+    Ok(())
+  }
+}
+
+impl Drop for Reactor {
+  fn drop(&mut self) {
+    unsafe {
+      libc::close(self.event);
+      libc::close(self.epoll);
+    }
+  }
+}
@@ -0,0 +1,295 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::marker::PhantomData;
+use alloc::boxed::Box;
+use alloc::rc::{Rc, Weak};
+use self::std::error::Error;
+use self::std::panic;
+use self::std::vec::Vec;
+use stack::{Stack, GuardedStack};
+use super::{Task, JoinHandle, Packet, local, park_uninterruptibly};
+
+/// A token that tells the tasks of a scope to stop.
+///
+/// Once the token is cancelled, every task spawned in the scope, or in a scope nested
+/// in one of those tasks, is unwound the next time it suspends itself, e.g. by calling
+/// `yield_now()`, `park()`, or waiting for I/O. Tasks that have not started yet never
+/// start. The token of a scope is cancelled when the scope, or one of its tasks, panics.
+#[derive(Clone)]
+pub struct CancellationToken {
+  inner: Rc<TokenInner>
+}
+
+struct TokenInner {
+  cancelled: Cell<bool>,
+  /// The tasks to wake once the token is cancelled.
+  tasks:     RefCell<Vec<Task>>,
+  /// The tokens of the nested scopes.
+  children:  RefCell<Vec<Weak<TokenInner>>>
+}
+
+impl CancellationToken {
+  fn new() -> CancellationToken {
+    CancellationToken {
+      inner: Rc::new(TokenInner {
+        cancelled: Cell::new(false),
+        tasks:     RefCell::new(Vec::new()),
+        children:  RefCell::new(Vec::new())
+      })
+    }
+  }
+
+  /// Creates a token that is cancelled along with this one.
+  fn child(&self) -> CancellationToken {
+    let child = CancellationToken::new();
+    child.inner.cancelled.set(self.is_cancelled());
+    self.inner.children.borrow_mut().push(Rc::downgrade(&child.inner));
+    child
+  }
+
+  /// Cancels the token, and wakes the tasks it applies to, so that they are unwound.
+  pub fn cancel(&self) {
+    if self.inner.cancelled.replace(true) { return }
+    let tasks = mem::replace(&mut *self.inner.tasks.borrow_mut(), Vec::new());
+    for task in tasks { task.unpark() }
+    let children = mem::replace(&mut *self.inner.children.borrow_mut(), Vec::new());
+    for child in children {
+      if let Some(inner) = child.upgrade() { CancellationToken { inner: inner }.cancel() }
+    }
+  }
+
+  /// Returns whether the token has been cancelled.
+  #[inline]
+  pub fn is_cancelled(&self) -> bool {
+    self.inner.cancelled.get()
+  }
+}
+
+impl fmt::Debug for CancellationToken {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("CancellationToken").field("cancelled", &self.is_cancelled()).finish()
+  }
+}
+
+/// The panic payload that unwinds a task whose scope has been cancelled.
+/// `ScopedJoinHandle::join()` returns it for a task that has been cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("task has been cancelled")
+  }
+}
+
+impl Error for Cancelled {
+  fn description(&self) -> &str { "task has been cancelled" }
+}
+
+/// A scope, in which tasks that borrow data from the enclosing stack frame can be spawned.
+pub struct Scope<'env> {
+  token:    CancellationToken,
+  children: RefCell<Vec<Child<'env>>>,
+  phantom:  PhantomData<&'env mut &'env ()>
+}
+
+struct Child<'env> {
+  task:   Task,
+  packet: Rc<ChildPacket + 'env>
+}
+
+/// The packet of a task spawned in a scope, with the type of its result erased.
+trait ChildPacket {
+  fn set_waiter(&self, task: Task);
+  /// Takes the panic payload of the task, if it has panicked and has not been joined.
+  fn take_panic(&self) -> Option<Box<Any + Send>>;
+}
+
+impl<T> ChildPacket for Packet<T> {
+  fn set_waiter(&self, task: Task) {
+    self.waiter.set(Some(task))
+  }
+
+  fn take_panic(&self) -> Option<Box<Any + Send>> {
+    let mut result = self.result.borrow_mut();
+    match result.take() {
+      Some(Err(payload)) => Some(payload),
+      other => { *result = other; None }
+    }
+  }
+}
+
+/// Calls `f` with a scope, in which it can spawn tasks that borrow data from the caller,
+/// and waits for every task spawned in the scope to finish before returning.
+///
+/// If `f` or a task it has not joined panics, the panic propagates once every task
+/// has finished. Panics of tasks cancel the scope, and so do panics of `f`, such as
+/// the unwinding started by `timeout()`. While `scope()` waits for the tasks, the current
+/// task is not interrupted; if its deadline passes, or its own scope is cancelled,
+/// the scope is cancelled, and the current task is unwound once the tasks have finished.
+///
+/// # Example
+///
+/// ```
+/// use fringe::rt;
+///
+/// rt::run(|| {
+///   let mut values = vec![1, 2, 3];
+///   rt::scope(|s| {
+///     for value in &mut values {
+///       s.spawn(rt::stack(), move |_| {
+///         rt::yield_now();
+///         *value *= 2
+///       });
+///     }
+///   });
+///   assert_eq!(values, [2, 4, 6]);
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn scope<'env, F, R>(f: F) -> R
+    where F: FnOnce(&Scope<'env>) -> R {
+  let token = {
+    let local = local();
+    let id = local.current_task().id();
+    let parent = local.tokens.borrow().get(&id).cloned();
+    match parent {
+      Some(parent) => parent.child(),
+      None => CancellationToken::new()
+    }
+  };
+  let scope = Scope { token: token, children: RefCell::new(Vec::new()), phantom: PhantomData };
+
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(&scope)));
+  if result.is_err() { scope.token.cancel() }
+  let panicked = scope.wait();
+  match (result, panicked) {
+    (Err(payload), _) | (Ok(_), Some(payload)) => panic::resume_unwind(payload),
+    (Ok(value), None) => {
+      local().check_interrupts();
+      value
+    }
+  }
+}
+
+impl<'env> Scope<'env> {
+  /// Spawns `f` as a task on `stack`, like `rt::spawn()`, passing it the scope.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a task.
+  pub fn spawn<'scope, S, F, T>(&'scope self, stack: S, f: F) -> ScopedJoinHandle<'scope, T>
+      where S: Stack + GuardedStack + 'static, F: FnOnce(&Scope<'env>) -> T + 'env, T: 'env {
+    let local = local();
+    let token = self.token.clone();
+    let scope = self as *const Scope<'env>;
+    // The scope waits for the task to finish before the data it borrows,
+    // or the scope itself, go away.
+    let handle = unsafe {
+      local.spawn_unchecked(stack, move || {
+        if token.is_cancelled() { panic::resume_unwind(Box::new(Cancelled)) }
+        // thread::panicking() would also be true while another task on this thread unwinds.
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| f(&*scope))) {
+          Ok(value) => value,
+          Err(payload) => {
+            token.cancel();
+            panic::resume_unwind(payload)
+          }
+        }
+      })
+    };
+
+    let task = handle.task.clone();
+    local.tokens.borrow_mut().insert(task.id(), self.token.clone());
+    if !self.token.is_cancelled() { self.token.inner.tasks.borrow_mut().push(task.clone()) }
+    self.children.borrow_mut().push(Child { task: task, packet: handle.packet.clone() });
+    ScopedJoinHandle { handle: handle, phantom: PhantomData }
+  }
+
+  /// Returns the cancellation token of the scope.
+  #[inline]
+  pub fn token(&self) -> &CancellationToken {
+    &self.token
+  }
+
+  /// Waits for every task spawned in the scope to finish, and returns the payload
+  /// of the first one that has panicked and has not been joined.
+  fn wait(&self) -> Option<Box<Any + Send>> {
+    let local = local();
+    let current = local.current_task();
+    loop {
+      let pending = self.children.borrow().iter()
+        .find(|child| !child.task.is_finished())
+        .map(|child| child.packet.clone());
+      let packet = match pending {
+        Some(packet) => packet,
+        None => break
+      };
+      // Unwinding now would leave the tasks with dangling borrows, so stop them instead.
+      if local.is_interrupted() { self.token.cancel() }
+      packet.set_waiter(current.clone());
+      park_uninterruptibly()
+    }
+
+    let children = mem::replace(&mut *self.children.borrow_mut(), Vec::new());
+    children.iter()
+      .filter_map(|child| child.packet.take_panic())
+      .find(|payload| !payload.is::<Cancelled>())
+  }
+}
+
+impl<'env> fmt::Debug for Scope<'env> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Scope")
+      .field("token", &self.token)
+      .field("tasks", &self.children.borrow().len())
+      .finish()
+  }
+}
+
+/// An owned permission to join a task spawned in a scope. If the handle is dropped,
+/// the scope still waits for the task to finish.
+pub struct ScopedJoinHandle<'scope, T: 'scope> {
+  handle:  JoinHandle<T>,
+  phantom: PhantomData<&'scope ()>
+}
+
+impl<'scope, T> ScopedJoinHandle<'scope, T> {
+  /// Returns a handle to the task.
+  #[inline]
+  pub fn task(&self) -> &Task { self.handle.task() }
+
+  /// Returns whether the task has finished.
+  pub fn is_finished(&self) -> bool {
+    self.handle.is_finished()
+  }
+
+  /// Waits for the task to finish, and returns its result. If the task panics,
+  /// returns the panic payload, which is `Cancelled` if the scope has been cancelled.
+  /// A panic that has been returned does not propagate out of the scope.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the task has not finished and `join()` is called by the task itself.
+  pub fn join(self) -> Result<T, Box<Any + Send>> {
+    self.handle.join()
+  }
+}
+
+impl<'scope, T> fmt::Debug for ScopedJoinHandle<'scope, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("ScopedJoinHandle").field("task", self.handle.task()).finish()
+  }
+}
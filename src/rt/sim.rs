@@ -0,0 +1,731 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A deterministic scheduler for testing concurrent tasks.
+//!
+//! A [Simulation](struct.Simulation.html) runs tasks on the current thread, like
+//! the single-threaded runtime, but every time more than one task is ready, the task
+//! that runs next is chosen by a pseudorandom number generator seeded with
+//! the seed of the simulation. Time is virtual: `now()` only advances when no task
+//! is ready and some task is sleeping, and then jumps straight to the nearest deadline.
+//! As long as the tasks themselves are deterministic, running a simulation with
+//! the same seed always results in the same interleaving.
+//!
+//! A simulation fails if a task panics, if tasks are parked and nothing can unpark
+//! them, or if it takes more steps than its limit. The [Failure](struct.Failure.html)
+//! holds the seed and the list of choices made by the scheduler, either of which
+//! can be used to replay the failing interleaving exactly.
+//!
+//! `check()` runs a function under a range of seeds, and `explore()` runs it under
+//! every possible schedule, up to a bound on the number of schedules.
+//!
+//! # Example
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use fringe::rt::sim::{self, Simulation};
+//!
+//! // Two tasks increment a counter without holding a lock across the suspend point.
+//! fn race() -> u32 {
+//!   let counter = Rc::new(Cell::new(0));
+//!   let handles = (0..2).map(|_| {
+//!     let counter = counter.clone();
+//!     sim::spawn(move || {
+//!       let value = counter.get();
+//!       sim::yield_now();
+//!       counter.set(value + 1)
+//!     })
+//!   }).collect::<Vec<_>>();
+//!   for handle in handles { handle.join() }
+//!   counter.get()
+//! }
+//!
+//! let failure = sim::try_explore(100, || assert_eq!(race(), 2)).unwrap_err();
+//! // The failing schedule can be replayed exactly.
+//! assert_eq!(Simulation::with_schedule(failure.schedule()).run(race).unwrap(), 1);
+//! ```
+extern crate std;
+
+use core::{fmt, ptr};
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use self::std::collections::BTreeMap;
+use self::std::string::{String, ToString};
+use self::std::sync::{Arc, Mutex};
+use self::std::time::Duration;
+use self::std::vec::Vec;
+use stack::{StackPool, PooledStack};
+use generator::{Generator, GeneratorState, Yielder};
+use super::DEFAULT_STACK_SIZE;
+
+/// The default limit on the number of times a simulation resumes a task.
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+/// A deterministic scheduler, which chooses the order in which tasks run
+/// using a seeded pseudorandom number generator, or by replaying a schedule.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+  strategy:  Strategy,
+  max_steps: usize,
+  pool:      StackPool
+}
+
+#[derive(Debug, Clone)]
+enum Strategy {
+  Random(u64),
+  /// The given choices are made first, and the first ready task is chosen afterwards.
+  Replay(Vec<usize>)
+}
+
+/// Chooses the next task to run, and records every choice.
+#[derive(Debug)]
+struct Chooser {
+  rng:     Option<u64>,
+  replay:  Vec<usize>,
+  /// Every choice made so far, with the number of tasks that were ready.
+  trace:   Vec<(usize, usize)>
+}
+
+impl Chooser {
+  fn new(strategy: &Strategy) -> Chooser {
+    match *strategy {
+      Strategy::Random(seed) => Chooser { rng: Some(seed), replay: Vec::new(), trace: Vec::new() },
+      Strategy::Replay(ref schedule) => Chooser { rng: None, replay: schedule.clone(), trace: Vec::new() }
+    }
+  }
+
+  /// Returns an index below `count`. Only choices between two or more tasks are recorded.
+  fn choose(&mut self, count: usize) -> usize {
+    if count == 1 { return 0 }
+    let choice = match self.rng {
+      Some(ref mut state) => {
+        // splitmix64
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        ((z ^ (z >> 31)) % count as u64) as usize
+      }
+      // A choice that does not fit, e.g. because the tasks changed, is clamped.
+      None => self.replay.get(self.trace.len()).map_or(0, |&choice| choice.min(count - 1))
+    };
+    self.trace.push((choice, count));
+    choice
+  }
+}
+
+/// Why a simulation failed, and how to replay it.
+pub struct Failure {
+  seed:     Option<u64>,
+  schedule: Vec<usize>,
+  steps:    usize,
+  message:  String,
+  payload:  Option<Box<Any + Send>>
+}
+
+impl Failure {
+  /// Returns the seed of the failing simulation, unless it replayed a schedule.
+  #[inline]
+  pub fn seed(&self) -> Option<u64> { self.seed }
+
+  /// Returns the choices the scheduler made before the failure.
+  #[inline]
+  pub fn schedule(&self) -> &[usize] { &self.schedule }
+
+  /// Returns the number of times a task was resumed before the failure.
+  #[inline]
+  pub fn steps(&self) -> usize { self.steps }
+
+  /// Returns a description of the failure.
+  #[inline]
+  pub fn message(&self) -> &str { &self.message }
+
+  /// Returns the panic payload, if a task panicked.
+  pub fn into_payload(self) -> Option<Box<Any + Send>> { self.payload }
+}
+
+impl fmt::Debug for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Failure")
+      .field("seed", &self.seed)
+      .field("schedule", &self.schedule)
+      .field("steps", &self.steps)
+      .field("message", &self.message)
+      .finish()
+  }
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    try!(write!(f, "simulation failed after {} steps: {}", self.steps, self.message));
+    match self.seed {
+      Some(seed) => write!(f, "; replay using Simulation::new({})", seed),
+      None => write!(f, "; replay using Simulation::with_schedule(&{:?})", self.schedule)
+    }
+  }
+}
+
+impl Simulation {
+  /// Creates a simulation that chooses tasks using a generator seeded with `seed`.
+  pub fn new(seed: u64) -> Simulation {
+    Simulation::with_strategy(Strategy::Random(seed))
+  }
+
+  /// Creates a simulation that makes the choices in `schedule`, as returned by
+  /// `Failure::schedule()`, and chooses the first ready task afterwards.
+  pub fn with_schedule(schedule: &[usize]) -> Simulation {
+    Simulation::with_strategy(Strategy::Replay(schedule.to_vec()))
+  }
+
+  fn with_strategy(strategy: Strategy) -> Simulation {
+    Simulation {
+      strategy:  strategy,
+      max_steps: DEFAULT_MAX_STEPS,
+      pool:      StackPool::new(DEFAULT_STACK_SIZE)
+    }
+  }
+
+  /// Sets the number of times the simulation may resume a task before it fails.
+  /// This catches tasks that never stop yielding.
+  pub fn set_max_steps(&mut self, steps: usize) {
+    self.max_steps = steps
+  }
+
+  /// Sets the pool the simulation takes stacks from.
+  pub fn set_pool(&mut self, pool: StackPool) {
+    self.pool = pool
+  }
+
+  /// Spawns `f` as a task, and runs tasks until every task has finished.
+  /// Returns the result of `f`, or why the simulation failed.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called from a task of another simulation.
+  pub fn run<F, T>(&self, f: F) -> Result<T, Failure>
+      where F: FnOnce() -> T + 'static, T: 'static {
+    self.run_traced(f).0
+  }
+
+  /// Like `run()`, and also returns every choice made, with the number of tasks
+  /// that were ready.
+  fn run_traced<F, T>(&self, f: F) -> (Result<T, Failure>, Vec<(usize, usize)>)
+      where F: FnOnce() -> T + 'static, T: 'static {
+    let state = Rc::new(State {
+      id:       SIMULATIONS.fetch_add(1, Ordering::Relaxed),
+      chooser:  RefCell::new(Chooser::new(&self.strategy)),
+      pool:     self.pool.clone(),
+      fibers:   RefCell::new(BTreeMap::new()),
+      ready:    RefCell::new(Vec::new()),
+      timers:   RefCell::new(BTreeMap::new()),
+      now:      Cell::new(Duration::from_secs(0)),
+      next_id:  Cell::new(0),
+      steps:    Cell::new(0),
+      current:  RefCell::new(None),
+      aborting: Cell::new(false)
+    });
+
+    STATE.with(|cell| {
+      let mut cell = cell.borrow_mut();
+      assert!(cell.is_none(), "cannot run a simulation inside another one");
+      *cell = Some(state.clone())
+    });
+
+    struct Exit;
+    impl Drop for Exit {
+      fn drop(&mut self) {
+        STATE.with(|cell| *cell.borrow_mut() = None)
+      }
+    }
+
+    let _exit = Exit;
+    let handle = state.spawn(f);
+    let result = match state.drive(self.max_steps) {
+      Ok(()) => Ok(handle.packet.result.borrow_mut().take().unwrap()),
+      Err((message, payload)) => {
+        state.abort();
+        Err(Failure {
+          seed:     match self.strategy { Strategy::Random(seed) => Some(seed), _ => None },
+          schedule: state.chooser.borrow().trace.iter().map(|&(choice, _)| choice).collect(),
+          steps:    state.steps.get(),
+          message:  message,
+          payload:  payload
+        })
+      }
+    };
+    let trace = state.chooser.borrow().trace.clone();
+    (result, trace)
+  }
+}
+
+/// Runs `f` in a simulation for every seed in `0..runs`.
+///
+/// # Panics
+///
+/// Panics with the failure, which includes the seed, if any simulation fails.
+pub fn check<F, T>(runs: u64, f: F)
+    where F: Fn() -> T + 'static, T: 'static {
+  let f = Rc::new(f);
+  for seed in 0..runs {
+    let f = f.clone();
+    if let Err(failure) = Simulation::new(seed).run(move || f()) {
+      panic!("{}", failure)
+    }
+  }
+}
+
+/// Runs `f` in a simulation under every possible schedule, or the first `max_schedules`
+/// of them, in depth-first order. Returns the number of schedules that were run.
+///
+/// # Panics
+///
+/// Panics with the failure, which includes the schedule, if any simulation fails.
+pub fn explore<F, T>(max_schedules: usize, f: F) -> usize
+    where F: Fn() -> T + 'static, T: 'static {
+  match try_explore(max_schedules, f) {
+    Ok(count) => count,
+    Err(failure) => panic!("{}", failure)
+  }
+}
+
+/// Like `explore()`, but returns the first failure instead of panicking.
+pub fn try_explore<F, T>(max_schedules: usize, f: F) -> Result<usize, Failure>
+    where F: Fn() -> T + 'static, T: 'static {
+  let f = Rc::new(f);
+  let mut simulation = Simulation::with_schedule(&[]);
+  let mut count = 0;
+  while count < max_schedules {
+    let (result, mut trace) = {
+      let f = f.clone();
+      simulation.run_traced(move || f())
+    };
+    count += 1;
+    try!(result);
+
+    // Move on to the next alternative of the last choice that has one left.
+    while let Some(&(choice, options)) = trace.last() {
+      if choice + 1 < options { break }
+      trace.pop();
+    }
+    match trace.last_mut() {
+      Some(last) => last.0 += 1,
+      None => break
+    }
+    simulation.strategy = Strategy::Replay(trace.into_iter().map(|(choice, _)| choice).collect());
+  }
+  Ok(count)
+}
+
+/// The number of simulations started so far, which identifies the next one.
+static SIMULATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The state of the running simulation.
+struct State {
+  id:       usize,
+  chooser:  RefCell<Chooser>,
+  pool:     StackPool,
+  fibers:   RefCell<BTreeMap<usize, Box<Fiber>>>,
+  ready:    RefCell<Vec<Task>>,
+  /// Sleeping tasks, ordered by deadline, and then by the order they went to sleep in.
+  timers:   RefCell<BTreeMap<(Duration, usize), Task>>,
+  now:      Cell<Duration>,
+  next_id:  Cell<usize>,
+  steps:    Cell<usize>,
+  current:  RefCell<Option<(Task, Rc<Cell<*const Yielder<(), ()>>>)>>,
+  /// Whether the simulation has failed, and the remaining tasks are being unwound.
+  aborting: Cell<bool>
+}
+
+self::std::thread_local! {
+  static STATE: RefCell<Option<Rc<State>>> = RefCell::new(None);
+}
+
+/// Returns the simulation of the current thread.
+fn state() -> Rc<State> {
+  STATE.with(|cell| cell.borrow().clone())
+    .expect("not running inside of a simulation")
+}
+
+/// Spawns `f` as a task. It becomes one of the ready tasks.
+///
+/// # Panics
+///
+/// Panics if called outside of a simulation.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where F: FnOnce() -> T + 'static, T: 'static {
+  state().spawn(f)
+}
+
+/// Returns a handle to the task that calls it.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn current() -> Task {
+  state().current_task()
+}
+
+/// Returns a handle to the current task, if the thread is running a task of a simulation.
+pub(crate) fn try_current() -> Option<Task> {
+  STATE.with(|cell| {
+    cell.borrow().as_ref().and_then(|state| state.current.borrow().as_ref().map(|&(ref task, _)| task.clone()))
+  })
+}
+
+/// Suspends the current task, and lets the scheduler choose which ready task runs next,
+/// possibly the current one.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn yield_now() {
+  let state = state();
+  if state.aborting.get() { return }
+  state.ready.borrow_mut().push(state.current_task());
+  state.suspend()
+}
+
+/// Suspends the current task until `unpark()` is called on its handle. If `unpark()`
+/// has been called since the task last parked, returns immediately.
+///
+/// The task may also be woken spuriously, so `park()` should be called in a loop
+/// that checks whether the condition the task is waiting for holds.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn park() {
+  let state = state();
+  if state.aborting.get() { return }
+  let task = state.current_task();
+  let previous = task.inner.update(|current| match current {
+    TaskState::Notified => TaskState::Running,
+    _ => TaskState::Parked
+  });
+  if previous != TaskState::Notified { state.suspend() }
+}
+
+/// Returns the virtual time that has passed since the simulation started.
+///
+/// # Panics
+///
+/// Panics if called outside of a simulation.
+pub fn now() -> Duration {
+  state().now.get()
+}
+
+/// Suspends the current task until `duration` of virtual time has passed.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn sleep(duration: Duration) {
+  let state = state();
+  let deadline = state.now.get() + duration;
+  if deadline > state.now.get() {
+    let id = state.next_id.get();
+    state.next_id.set(id + 1);
+    state.timers.borrow_mut().insert((deadline, id), state.current_task());
+  }
+  while state.now.get() < deadline && !state.aborting.get() { park() }
+}
+
+impl State {
+  fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+      where F: FnOnce() -> T + 'static, T: 'static {
+    let id = self.next_id.get();
+    self.next_id.set(id + 1);
+
+    let task = Task {
+      inner: Arc::new(TaskInner { id: id, simulation: self.id, state: Mutex::new(TaskState::Running) })
+    };
+    let packet = Rc::new(Packet { result: RefCell::new(None), waiter: Cell::new(None) });
+    let yielder = Rc::new(Cell::new(ptr::null()));
+    let stack = self.pool.get().expect("cannot allocate a stack");
+    let generator = {
+      let packet = packet.clone();
+      let yielder = yielder.clone();
+      Generator::new(stack, move |this: &Yielder<(), ()>, ()| {
+        yielder.set(this);
+        let value = f();
+        *packet.result.borrow_mut() = Some(value);
+        if let Some(waiter) = packet.waiter.take() { waiter.unpark() }
+      })
+    };
+
+    let fiber = TaskFiber { generator: generator, yielder: yielder };
+    self.fibers.borrow_mut().insert(id, Box::new(fiber));
+    self.ready.borrow_mut().push(task.clone());
+    JoinHandle { task: task, packet: packet }
+  }
+
+  fn current_task(&self) -> Task {
+    match *self.current.borrow() {
+      Some((ref task, _)) => task.clone(),
+      None => panic!("not running inside of a task")
+    }
+  }
+
+  /// Switches from the current task back to the scheduler.
+  fn suspend(&self) {
+    let yielder = match *self.current.borrow() {
+      Some((_, ref yielder)) => yielder.get(),
+      None => panic!("not running inside of a task")
+    };
+    unsafe { (*yielder).suspend(()) }
+  }
+
+  /// Runs tasks until every task has finished, or returns why the simulation failed.
+  fn drive(&self, max_steps: usize) -> Result<(), (String, Option<Box<Any + Send>>)> {
+    loop {
+      if self.ready.borrow().is_empty() {
+        if self.fibers.borrow().is_empty() { return Ok(()) }
+        if !self.fire_timers() {
+          let parked = self.fibers.borrow().keys().cloned().collect::<Vec<_>>();
+          return Err((self::std::format!("deadlock: tasks {:?} are parked forever", parked), None))
+        }
+        continue
+      }
+
+      if self.steps.get() >= max_steps {
+        return Err((self::std::format!("exceeded the limit of {} steps", max_steps), None))
+      }
+      self.steps.set(self.steps.get() + 1);
+
+      let task = {
+        let count = self.ready.borrow().len();
+        let index = self.chooser.borrow_mut().choose(count);
+        self.ready.borrow_mut().remove(index)
+      };
+      let mut fiber = match self.fibers.borrow_mut().remove(&task.inner.id) {
+        Some(fiber) => fiber,
+        None => continue
+      };
+      task.inner.update(|current| match current {
+        TaskState::Parked => TaskState::Running,
+        current => current
+      });
+
+      *self.current.borrow_mut() = Some((task.clone(), fiber.yielder()));
+      let result = fiber.resume();
+      *self.current.borrow_mut() = None;
+
+      match result {
+        Ok(false) => { self.fibers.borrow_mut().insert(task.inner.id, fiber); }
+        Ok(true) => task.inner.set(TaskState::Done),
+        Err(payload) => {
+          let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+              Some(message) => message.clone(),
+              None => "Box<Any>".to_string()
+            }
+          };
+          let message = self::std::format!("task {} panicked: {}", task.inner.id, message);
+          return Err((message, Some(payload)))
+        }
+      }
+    }
+  }
+
+  /// Advances the clock to the nearest deadline, and wakes every task sleeping until it.
+  /// Returns `false` if no task is sleeping.
+  fn fire_timers(&self) -> bool {
+    let deadline = match self.timers.borrow().keys().next() {
+      Some(&(deadline, _)) => deadline,
+      None => return false
+    };
+    self.now.set(deadline);
+    loop {
+      let next = match self.timers.borrow().keys().next() {
+        Some(&key) if key.0 == deadline => key,
+        _ => break
+      };
+      let task = self.timers.borrow_mut().remove(&next).unwrap();
+      task.unpark()
+    }
+    true
+  }
+
+  /// Unwinds the remaining tasks after a failure.
+  fn abort(&self) {
+    self.aborting.set(true);
+    self.ready.borrow_mut().clear();
+    self.timers.borrow_mut().clear();
+    loop {
+      // Take the fibers out one by one, since unwinding may touch the state.
+      let fiber = {
+        let mut fibers = self.fibers.borrow_mut();
+        let id = match fibers.keys().next() { Some(&id) => id, None => break };
+        fibers.remove(&id).unwrap()
+      };
+      drop(fiber)
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+  /// The task is running or ready.
+  Running,
+  /// The task is running or ready, and `unpark()` has been called since it last parked.
+  Notified,
+  Parked,
+  Done
+}
+
+/// A handle to a task of a simulation.
+///
+/// Task handles can be sent to other threads, so that they can be kept in
+/// thread-safe data structures, but a task can only be woken by the thread running
+/// its simulation, while it runs.
+#[derive(Clone)]
+pub struct Task {
+  inner: Arc<TaskInner>
+}
+
+struct TaskInner {
+  id:         usize,
+  /// The identifier of the simulation that has spawned the task.
+  simulation: usize,
+  state:      Mutex<TaskState>
+}
+
+impl TaskInner {
+  fn set(&self, state: TaskState) { *self.state.lock().unwrap() = state }
+
+  /// Replaces the state of the task with `f(state)` atomically, and returns the previous state.
+  fn update<F>(&self, f: F) -> TaskState
+      where F: FnOnce(TaskState) -> TaskState {
+    let mut state = self.state.lock().unwrap();
+    let previous = *state;
+    *state = f(previous);
+    previous
+  }
+}
+
+impl Task {
+  /// Returns the identifier of the task, which is unique within its simulation.
+  #[inline]
+  pub fn id(&self) -> usize { self.inner.id }
+
+  /// Makes the task ready if it is suspended in `park()`. Otherwise, the next call to `park()`
+  /// made by the task returns immediately.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of the simulation of the task, e.g. on another thread,
+  /// or after the simulation has finished.
+  pub fn unpark(&self) {
+    let state = STATE.with(|cell| cell.borrow().clone());
+    let state = match state {
+      Some(ref state) if state.id == self.inner.simulation => state,
+      _ => panic!("cannot unpark a task outside of its simulation")
+    };
+    let previous = self.inner.update(|current| match current {
+      TaskState::Running => TaskState::Notified,
+      TaskState::Parked => TaskState::Running,
+      current => current
+    });
+    if previous == TaskState::Parked { state.ready.borrow_mut().push(self.clone()) }
+  }
+}
+
+impl PartialEq for Task {
+  fn eq(&self, other: &Task) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+}
+
+impl Eq for Task {}
+
+impl fmt::Debug for Task {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Task").field("id", &self.inner.id).finish()
+  }
+}
+
+/// A task, with the type of its result erased.
+trait Fiber {
+  /// Resumes the task, and returns whether it has finished, or its panic payload.
+  fn resume(&mut self) -> Result<bool, Box<Any + Send>>;
+  fn yielder(&self) -> Rc<Cell<*const Yielder<(), ()>>>;
+}
+
+struct TaskFiber {
+  generator: Generator<'static, (), (), PooledStack>,
+  yielder:   Rc<Cell<*const Yielder<(), ()>>>
+}
+
+impl Fiber for TaskFiber {
+  fn resume(&mut self) -> Result<bool, Box<Any + Send>> {
+    match self.generator.try_resume(()) {
+      Ok(Some(GeneratorState::Yielded(()))) => Ok(false),
+      Ok(_) => Ok(true),
+      Err(payload) => Err(payload)
+    }
+  }
+
+  fn yielder(&self) -> Rc<Cell<*const Yielder<(), ()>>> {
+    self.yielder.clone()
+  }
+}
+
+/// The result of a task, and the task waiting for it.
+struct Packet<T> {
+  result: RefCell<Option<T>>,
+  waiter: Cell<Option<Task>>
+}
+
+/// An owned permission to join a task, that is, to wait for it to finish.
+/// If the handle is dropped, the task is detached, and its result is discarded.
+pub struct JoinHandle<T> {
+  task:   Task,
+  packet: Rc<Packet<T>>
+}
+
+impl<T> JoinHandle<T> {
+  /// Returns a handle to the task.
+  #[inline]
+  pub fn task(&self) -> &Task { &self.task }
+
+  /// Returns whether the task has finished.
+  pub fn is_finished(&self) -> bool {
+    self.packet.result.borrow().is_some()
+  }
+
+  /// Waits for the task to finish, and returns its result. A task that panics
+  /// fails the whole simulation, so there is no panic to return.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the task has not finished and `join()` is called outside of a task,
+  /// or by the task itself.
+  pub fn join(self) -> T {
+    loop {
+      if let Some(result) = self.packet.result.borrow_mut().take() { return result }
+      let current = current();
+      assert!(current != self.task, "a task cannot join itself");
+      self.packet.waiter.set(Some(current));
+      park();
+      // While a failed simulation unwinds, the result never arrives.
+      if state().aborting.get() && !self.is_finished() {
+        panic!("simulation aborted")
+      }
+    }
+  }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("JoinHandle").field("task", &self.task).finish()
+  }
+}
@@ -0,0 +1,114 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use self::std::sync::{Arc, Weak};
+use super::Shared;
+
+/// The task is running, or is in the ready queue.
+const RUNNING:  usize = 0;
+/// The task is running, and `unpark()` has been called since it last parked.
+const NOTIFIED: usize = 1;
+/// The task is suspended in `park()`.
+const PARKED:   usize = 2;
+/// The task has finished.
+const DONE:     usize = 3;
+
+/// A handle to a task spawned on a runtime.
+///
+/// Task handles are cheap to clone, and can be sent to other threads, which can
+/// then wake the task using `unpark()`.
+#[derive(Clone)]
+pub struct Task {
+  inner: Arc<Inner>
+}
+
+struct Inner {
+  id:     usize,
+  state:  AtomicUsize,
+  shared: Weak<Shared>
+}
+
+impl Task {
+  pub(crate) fn new(id: usize, shared: &Arc<Shared>) -> Task {
+    Task {
+      inner: Arc::new(Inner {
+        id:     id,
+        state:  AtomicUsize::new(RUNNING),
+        shared: Arc::downgrade(shared)
+      })
+    }
+  }
+
+  /// Returns the identifier of the task, which is unique within its runtime.
+  #[inline]
+  pub fn id(&self) -> usize { self.inner.id }
+
+  /// Wakes the task if it is suspended in `park()`. Otherwise, the next call to `park()`
+  /// made by the task returns immediately.
+  pub fn unpark(&self) {
+    let mut state = self.inner.state.load(Ordering::Acquire);
+    loop {
+      let next = match state {
+        RUNNING => NOTIFIED,
+        PARKED  => RUNNING,
+        _ => return
+      };
+      match self.inner.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => break,
+        Err(actual) => state = actual
+      }
+    }
+
+    if state == PARKED {
+      if let Some(shared) = self.inner.shared.upgrade() {
+        shared.schedule(self.clone())
+      }
+    }
+  }
+
+  /// Consumes the notification left by `unpark()`, if any. Otherwise, marks the task
+  /// as parked and returns `true`, after which the task must suspend itself.
+  pub(crate) fn prepare_park(&self) -> bool {
+    let mut state = self.inner.state.load(Ordering::Acquire);
+    loop {
+      let next = match state {
+        NOTIFIED => RUNNING,
+        RUNNING  => PARKED,
+        _ => unreachable!()
+      };
+      match self.inner.state.compare_exchange_weak(state, next, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => return next == PARKED,
+        Err(actual) => state = actual
+      }
+    }
+  }
+
+  pub(crate) fn finish(&self) {
+    self.inner.state.store(DONE, Ordering::Release)
+  }
+
+  pub(crate) fn is_finished(&self) -> bool {
+    self.inner.state.load(Ordering::Acquire) == DONE
+  }
+}
+
+impl PartialEq for Task {
+  fn eq(&self, other: &Task) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+}
+
+impl Eq for Task {}
+
+impl fmt::Debug for Task {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Task").field("id", &self.inner.id).finish()
+  }
+}
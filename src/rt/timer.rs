@@ -0,0 +1,292 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{cmp, fmt, mem};
+use core::cell::Cell;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use self::std::error::Error;
+use self::std::panic;
+use self::std::time::{Duration, Instant};
+use self::std::vec::Vec;
+use super::{Task, local, park};
+
+/// The number of bits of a deadline that select a slot within a level.
+const SLOT_BITS: u32 = 6;
+/// The number of slots in a level.
+const SLOTS: usize = 1 << SLOT_BITS;
+/// The number of levels. The wheel covers 2^36 milliseconds, a bit over two years.
+const LEVELS: usize = 6;
+/// The furthest deadline the wheel can hold, in milliseconds from its current time.
+const MAX_DELAY: u64 = (1 << (SLOT_BITS * LEVELS as u32)) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum State {
+  Pending,
+  Fired,
+  Cancelled
+}
+
+/// A deadline that a task waits for.
+#[derive(Debug)]
+pub(crate) struct Timer {
+  id:    u64,
+  state: Cell<State>
+}
+
+impl Timer {
+  #[inline]
+  pub fn state(&self) -> State { self.state.get() }
+
+  /// Makes the timer never fire, unless it has already fired.
+  pub fn cancel(&self) {
+    if self.state.get() == State::Pending { self.state.set(State::Cancelled) }
+  }
+}
+
+#[derive(Debug)]
+struct Entry {
+  /// The deadline, in milliseconds since the start of the wheel.
+  when:  u64,
+  timer: Rc<Timer>,
+  task:  Task
+}
+
+#[derive(Debug)]
+struct Level {
+  slots:    Vec<Vec<Entry>>,
+  /// A bit for every slot that holds entries.
+  occupied: u64
+}
+
+/// Wheel is a hierarchical timer wheel with a resolution of one millisecond.
+///
+/// Level `n` has 64 slots, each of which covers 64^n milliseconds. A deadline goes
+/// to the lowest level where it shares all higher bits with the current time; as
+/// time advances, the entries of a slot on a higher level are moved down to
+/// the lower levels, until they reach level 0 and fire.
+#[derive(Debug)]
+pub(crate) struct Wheel {
+  start:   Instant,
+  /// The time up to which the wheel has fired its entries, in milliseconds since `start`.
+  now:     u64,
+  next_id: u64,
+  levels:  Vec<Level>
+}
+
+impl Wheel {
+  pub fn new() -> Wheel {
+    Wheel {
+      start:   Instant::now(),
+      now:     0,
+      next_id: 0,
+      levels:  (0..LEVELS).map(|_| Level {
+        slots:    (0..SLOTS).map(|_| Vec::new()).collect(),
+        occupied: 0
+      }).collect()
+    }
+  }
+
+  /// Converts `instant` to milliseconds since the start of the wheel, rounding up
+  /// if `round_up` is true, and down otherwise.
+  fn millis(&self, instant: Instant, round_up: bool) -> u64 {
+    if instant <= self.start { return 0 }
+    let elapsed = instant - self.start;
+    let nanos = elapsed.subsec_nanos() as u64 + if round_up { 999_999 } else { 0 };
+    elapsed.as_secs().saturating_mul(1000).saturating_add(nanos / 1_000_000)
+  }
+
+  /// Adds a timer that unparks `task` once `deadline` passes. If it already has,
+  /// the timer is fired right away, without unparking the task.
+  pub fn add(&mut self, deadline: Instant, task: Task) -> Rc<Timer> {
+    let timer = Rc::new(Timer { id: self.next_id, state: Cell::new(State::Pending) });
+    self.next_id += 1;
+    // Round deadlines up and the current time down, so that timers never fire early.
+    let when = self.millis(deadline, true);
+    if when <= self.now {
+      timer.state.set(State::Fired)
+    } else {
+      self.insert(Entry { when: when, timer: timer.clone(), task: task })
+    }
+    timer
+  }
+
+  fn insert(&mut self, entry: Entry) {
+    // Deadlines beyond the range of the wheel are put in the furthest slot,
+    // and are moved again once that slot is reached.
+    let when = cmp::min(entry.when, self.now + MAX_DELAY);
+    // The lowest level where the deadline shares all higher bits with the current time.
+    let significant = 63 - ((self.now ^ when) | (SLOTS as u64 - 1)).leading_zeros();
+    let level = cmp::min((significant / SLOT_BITS) as usize, LEVELS - 1);
+    let slot = ((when >> (SLOT_BITS * level as u32)) as usize) & (SLOTS - 1);
+    self.levels[level].slots[slot].push(entry);
+    self.levels[level].occupied |= 1 << slot;
+  }
+
+  /// Returns the level and slot that has to be processed next, and the time,
+  /// in milliseconds since the start of the wheel, at which that has to happen.
+  fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+    for (index, level) in self.levels.iter().enumerate() {
+      if level.occupied == 0 { continue }
+      let slot_range  = 1u64 << (SLOT_BITS * index as u32);
+      let level_range = slot_range << SLOT_BITS;
+      let now_slot = ((self.now / slot_range) % SLOTS as u64) as u32;
+      let distance = level.occupied.rotate_right(now_slot).trailing_zeros();
+      let slot = ((now_slot + distance) % SLOTS as u32) as usize;
+      let level_start = self.now - self.now % level_range;
+      let mut deadline = level_start + slot as u64 * slot_range;
+      if (slot as u32) < now_slot { deadline += level_range }
+      return Some((index, slot, deadline))
+    }
+    None
+  }
+
+  /// Returns the time at which the wheel has to be advanced next, if it holds any timers.
+  /// The wheel may have to be advanced before any of its timers fire.
+  pub fn next_deadline(&self) -> Option<Instant> {
+    self.next_expiration().map(|(_, _, deadline)| self.start + Duration::from_millis(deadline))
+  }
+
+  /// Fires every timer whose deadline has passed by `instant`, and returns the tasks
+  /// waiting for them.
+  pub fn advance(&mut self, instant: Instant) -> Vec<Task> {
+    let target = self.millis(instant, false);
+    let mut ready = Vec::new();
+    while let Some((level, slot, deadline)) = self.next_expiration() {
+      if deadline > target { break }
+      self.now = cmp::max(self.now, deadline);
+      self.levels[level].occupied &= !(1 << slot);
+      let entries = mem::replace(&mut self.levels[level].slots[slot], Vec::new());
+      for entry in entries {
+        if entry.timer.state() != State::Pending { continue }
+        if entry.when <= self.now {
+          entry.timer.state.set(State::Fired);
+          ready.push(entry.task)
+        } else {
+          self.insert(entry)
+        }
+      }
+    }
+    self.now = cmp::max(self.now, target);
+    ready
+  }
+}
+
+/// The error returned by `timeout()` if the deadline passes before the function returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("deadline has elapsed")
+  }
+}
+
+impl Error for Elapsed {
+  fn description(&self) -> &str { "deadline has elapsed" }
+}
+
+/// The panic payload that unwinds a task to the `timeout()` call whose deadline has passed.
+struct TimedOut(u64);
+
+/// Cancels a timer when dropped.
+struct CancelOnDrop(Rc<Timer>);
+
+impl Drop for CancelOnDrop {
+  fn drop(&mut self) { self.0.cancel() }
+}
+
+/// Suspends the current task for at least `duration`.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn sleep(duration: Duration) {
+  sleep_until(Instant::now() + duration)
+}
+
+/// Suspends the current task until `deadline` passes.
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn sleep_until(deadline: Instant) {
+  let local = local();
+  let timer = local.wheel.borrow_mut().add(deadline, local.current_task());
+  let _cancel = CancelOnDrop(timer.clone());
+  while timer.state() != State::Fired { park() }
+}
+
+/// Runs `f`, and returns its result, or `Elapsed` if `duration` passes before it returns.
+///
+/// Tasks are never preempted, so `f` only finds out that the deadline has passed
+/// when it suspends the task, e.g. by waiting for I/O or calling `yield_now()`.
+/// The suspending call then unwinds `f`, running the destructors of every value
+/// it holds, and `timeout()` returns `Elapsed`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use fringe::rt;
+///
+/// rt::run(|| {
+///   let result = rt::timeout(Duration::from_millis(10), || {
+///     rt::sleep(Duration::from_secs(3600))
+///   });
+///   assert_eq!(result, Err(rt::Elapsed));
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if called outside of a task.
+pub fn timeout<F, T>(duration: Duration, f: F) -> Result<T, Elapsed>
+    where F: FnOnce() -> T {
+  let local = local();
+  let task = local.current_task();
+  let timer = local.wheel.borrow_mut().add(Instant::now() + duration, task.clone());
+  local.timeouts.borrow_mut().entry(task.id()).or_insert_with(Vec::new).push(timer.clone());
+
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+    local.check_interrupts();
+    f()
+  }));
+
+  timer.cancel();
+  {
+    let mut timeouts = local.timeouts.borrow_mut();
+    let empty = {
+      let stack = timeouts.get_mut(&task.id()).unwrap();
+      stack.pop();
+      stack.is_empty()
+    };
+    if empty { timeouts.remove(&task.id()); }
+  }
+
+  match result {
+    Ok(value) => Ok(value),
+    Err(payload) => match payload.downcast::<TimedOut>() {
+      Ok(ref timed_out) if timed_out.0 == timer.id => Err(Elapsed),
+      Ok(timed_out) => panic::resume_unwind(timed_out),
+      Err(payload) => panic::resume_unwind(payload)
+    }
+  }
+}
+
+/// Returns the identifier of the outermost `timeout()` call whose deadline has passed.
+pub(crate) fn expired(timeouts: Option<&Vec<Rc<Timer>>>) -> Option<u64> {
+  timeouts.and_then(|stack| {
+    stack.iter().find(|timer| timer.state() == State::Fired).map(|timer| timer.id)
+  })
+}
+
+/// Unwinds the current task to the outermost `timeout()` call whose deadline has passed.
+pub(crate) fn check_timeouts(timeouts: Option<&Vec<Rc<Timer>>>) {
+  if let Some(id) = expired(timeouts) { panic::resume_unwind(Box::new(TimedOut(id))) }
+}
@@ -0,0 +1,178 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use self::std::io::{self, Error as IoError};
+use self::std::sync::{Arc, Mutex};
+use self::std::vec::Vec;
+use stack::{Stack, GuardedStack};
+use stack::os::{sys, overflow};
+
+/// StackArena reserves a single memory mapping and carves it into a fixed number
+/// of equally sized stacks, each with its own guard page.
+///
+/// Every stack handed out by the arena, an [ArenaStack](struct.ArenaStack.html),
+/// returns its slot to the arena when dropped, and the most recently returned slot
+/// is handed out first. Arenas are cheap to clone, and the clones share their slots.
+///
+/// With one mapping per stack, every `OsStack` takes up two entries in the memory map
+/// of the process, which limits the number of stacks that can exist at the same time
+/// (see `vm.max_map_count` on Linux). Only on Linux 6.13 and later, where the guard pages
+/// of an arena are installed using `madvise(MADV_GUARD_INSTALL)`, does the whole arena
+/// take up a single entry and avoid that limit. Elsewhere, the guard pages are protected
+/// using `mprotect`, which splits the mapping once per guard page, so an arena takes up
+/// as many entries as the same number of `OsStack`s.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{StackArena, Generator};
+///
+/// let arena = StackArena::new(1 << 16, 16).unwrap();
+/// let stack = arena.get().unwrap().unwrap();
+/// let mut gen = Generator::new(stack, move |yielder, ()| yielder.suspend(1));
+/// assert_eq!(arena.in_use(), 1);
+/// assert_eq!(gen.next(), Some(1));
+/// drop(gen);
+/// assert_eq!(arena.in_use(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StackArena {
+  inner: Arc<Inner>
+}
+
+#[derive(Debug)]
+struct Inner {
+  ptr:       *mut u8,
+  slot_len:  usize,
+  slots:     usize,
+  guard_len: usize,
+  free:      Mutex<Free>
+}
+
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
+
+#[derive(Debug)]
+struct Free {
+  /// Slots that have been handed out and returned.
+  slots:  Vec<usize>,
+  /// The number of slots that have ever been handed out. Slots past this one
+  /// do not have a guard page yet.
+  used:   usize,
+  in_use: usize
+}
+
+impl StackArena {
+  /// Reserves an arena for `slots` stacks with at least `stack_size` accessible bytes each.
+  /// `stack_size` is rounded up to an integral number of pages.
+  ///
+  /// Fails with `InvalidInput` if the arena would not fit in the address space.
+  pub fn new(stack_size: usize, slots: usize) -> Result<StackArena, IoError> {
+    let page_size = sys::page_size();
+    let stack_len = if stack_size == 0 { page_size } else { stack_size };
+    let len = stack_len.checked_add(page_size - 1)
+      .map(|stack_len| stack_len & !(page_size - 1))
+      .and_then(|stack_len| page_size.checked_add(stack_len))
+      .and_then(|slot_len| slot_len.checked_mul(slots).map(|len| (slot_len, len)));
+    let (slot_len, len) = match len {
+      Some(len) => len,
+      None => return Err(IoError::new(io::ErrorKind::InvalidInput, "arena too large"))
+    };
+
+    let ptr = try!(unsafe { sys::map_stack(len) });
+    Ok(StackArena {
+      inner: Arc::new(Inner {
+        ptr:       ptr,
+        slot_len:  slot_len,
+        slots:     slots,
+        guard_len: page_size,
+        free:      Mutex::new(Free { slots: Vec::new(), used: 0, in_use: 0 })
+      })
+    })
+  }
+
+  /// Returns a stack from a free slot, or `None` if all slots are in use.
+  /// Fails if the guard page of a slot that is used for the first time
+  /// cannot be installed.
+  pub fn get(&self) -> Result<Option<ArenaStack>, IoError> {
+    let mut free = self.inner.free.lock().unwrap();
+    let index = match free.slots.pop() {
+      Some(index) => index,
+      None if free.used < self.inner.slots => {
+        let index = free.used;
+        try!(unsafe { sys::guard_pages(self.inner.slot(index), self.inner.guard_len) });
+        free.used += 1;
+        index
+      }
+      None => return Ok(None)
+    };
+    free.in_use += 1;
+
+    let stack = ArenaStack { index: index, arena: self.inner.clone() };
+    overflow::register(self.inner.slot(index), stack.limit(), stack.base());
+    Ok(Some(stack))
+  }
+
+  /// Returns the number of slots that are in use.
+  pub fn in_use(&self) -> usize {
+    self.inner.free.lock().unwrap().in_use
+  }
+
+  /// Returns the total number of slots.
+  pub fn capacity(&self) -> usize {
+    self.inner.slots
+  }
+}
+
+impl Inner {
+  fn slot(&self, index: usize) -> *mut u8 {
+    unsafe { self.ptr.offset((index * self.slot_len) as isize) }
+  }
+}
+
+impl Drop for Inner {
+  fn drop(&mut self) {
+    unsafe { sys::unmap_stack(self.ptr, self.slot_len * self.slots) }.expect("cannot unmap stack arena")
+  }
+}
+
+/// ArenaStack holds a stack in a slot of a [StackArena](struct.StackArena.html).
+/// When dropped, it returns the slot to the arena.
+#[derive(Debug)]
+pub struct ArenaStack {
+  index: usize,
+  arena: Arc<Inner>
+}
+
+unsafe impl Stack for ArenaStack {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    unsafe { self.arena.slot(self.index).offset(self.arena.slot_len as isize) }
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    unsafe { self.arena.slot(self.index).offset(self.arena.guard_len as isize) }
+  }
+}
+
+unsafe impl GuardedStack for ArenaStack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    self.arena.guard_len
+  }
+}
+
+impl Drop for ArenaStack {
+  fn drop(&mut self) {
+    overflow::deregister(self.limit());
+    let mut free = self.arena.free.lock().unwrap();
+    free.slots.push(self.index);
+    free.in_use -= 1
+  }
+}
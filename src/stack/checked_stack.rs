@@ -0,0 +1,107 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+use core::{ptr, mem};
+use stack::Stack;
+
+/// The size of the canary, in bytes.
+const CANARY_SIZE: usize = 64;
+
+/// Returns the canary word stored at `addr`. The address is mixed in, so that
+/// a copy of the canary elsewhere on the stack does not look intact.
+#[inline(always)]
+fn canary_word(addr: usize) -> usize {
+  addr ^ (0xa5c3_5a3c_c35a_3ca5_u64 as usize)
+}
+
+/// CheckedStack wraps a stack without a guard page and places a canary just above
+/// the limit address of the wrapped stack. Every time a generator running on it is
+/// resumed or suspends itself, the canary is verified and the saved stack pointer
+/// of the generator is checked against the bounds of the stack; if the stack has
+/// overflowed, the generator is poisoned and the resumer panics.
+///
+/// This catches overflows in environments without an MMU, but only after the fact:
+/// an overflow can still corrupt the memory below the stack before it is detected.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{OwnedStack, CheckedStack, Generator};
+///
+/// let stack = CheckedStack::new(OwnedStack::new(1 << 16));
+/// let mut gen = unsafe {
+///   Generator::unsafe_new(stack, move |yielder, ()| {
+///     yielder.suspend(1);
+///   })
+/// };
+/// assert_eq!(gen.next(), Some(1));
+/// ```
+#[derive(Debug)]
+pub struct CheckedStack<S: Stack>(S);
+
+impl<S: Stack> CheckedStack<S> {
+  /// Writes the canary to `stack` and wraps it.
+  ///
+  /// This function will panic if `stack` is too small to hold the canary.
+  pub fn new(stack: S) -> CheckedStack<S> {
+    let stack = CheckedStack(stack);
+    if stack.canary() as usize + CANARY_SIZE > stack.0.base() as usize {
+      panic!("CheckedStack too small");
+    }
+    for index in 0..CANARY_SIZE / mem::size_of::<usize>() {
+      unsafe {
+        let word = stack.canary().offset(index as isize);
+        ptr::write_volatile(word, canary_word(word as usize))
+      }
+    }
+    stack
+  }
+
+  /// Extracts the wrapped stack.
+  pub fn into_inner(self) -> S {
+    self.0
+  }
+
+  /// Returns the address of the canary, which is the limit address of the wrapped
+  /// stack, aligned to a word boundary.
+  #[inline(always)]
+  fn canary(&self) -> *mut usize {
+    let align = mem::align_of::<usize>();
+    ((self.0.limit() as usize + align - 1) & !(align - 1)) as *mut usize
+  }
+}
+
+unsafe impl<S: Stack> Stack for CheckedStack<S> {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    self.0.base()
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    unsafe { (self.canary() as *mut u8).offset(CANARY_SIZE as isize) }
+  }
+
+  fn high_water_mark(&self) -> Option<usize> {
+    self.0.high_water_mark()
+  }
+
+  fn check_overflow(&self, stack_ptr: *mut u8) {
+    if stack_ptr < self.limit() || stack_ptr > self.base() {
+      panic!("generator stack overflow: stack pointer {:p} is outside of the stack {:p}-{:p}",
+             stack_ptr, self.limit(), self.base())
+    }
+    for index in 0..CANARY_SIZE / mem::size_of::<usize>() {
+      unsafe {
+        let word = self.canary().offset(index as isize);
+        if ptr::read_volatile(word) != canary_word(word as usize) {
+          panic!("generator stack overflow: canary at {:p} below the stack {:p}-{:p} was overwritten",
+                 word, self.limit(), self.base())
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,89 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::slice;
+use core::mem::ManuallyDrop;
+use core::alloc::Layout;
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use self::std::io::Error as IoError;
+use stack::{Stack, GuardedStack, GuardedSliceStack};
+use stack::os::sys;
+
+/// GuardedOwnedStack holds a guarded, heap-allocated stack. Unlike `OsStack`, it is
+/// allocated using the default Rust allocator, and the lowest pages of the allocation
+/// serve as the guard area.
+#[derive(Debug)]
+pub struct GuardedOwnedStack {
+  stack:  ManuallyDrop<GuardedSliceStack<'static>>,
+  layout: Layout
+}
+
+impl GuardedOwnedStack {
+  /// Allocates a new stack with at least `size` accessible bytes and a guard page.
+  /// `size` is rounded up to an integral number of pages.
+  pub fn new(size: usize) -> Result<GuardedOwnedStack, IoError> {
+    GuardedOwnedStack::with_guard(size, 0)
+  }
+
+  /// Same as `new`, but the guard area is at least `guard_size` bytes long.
+  /// `guard_size` is rounded up to an integral number of pages, and is at least one page.
+  pub fn with_guard(size: usize, guard_size: usize) -> Result<GuardedOwnedStack, IoError> {
+    let page_size = sys::page_size();
+    let round_up = |len: usize| {
+      let len = if len == 0 { page_size } else { len };
+      (len + page_size - 1) & !(page_size - 1)
+    };
+
+    let guard_len = round_up(guard_size);
+    let layout = Layout::from_size_align(guard_len + round_up(size), page_size).unwrap();
+    unsafe {
+      let ptr = alloc(layout);
+      if ptr.is_null() { handle_alloc_error(layout) }
+
+      match GuardedSliceStack::with_guard(slice::from_raw_parts_mut(ptr, layout.size()), guard_len) {
+        Ok(stack) => Ok(GuardedOwnedStack { stack: ManuallyDrop::new(stack), layout: layout }),
+        Err(err) => {
+          dealloc(ptr, layout);
+          Err(err)
+        }
+      }
+    }
+  }
+}
+
+unsafe impl Send for GuardedOwnedStack {}
+
+unsafe impl Stack for GuardedOwnedStack {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    self.stack.base()
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    self.stack.limit()
+  }
+}
+
+unsafe impl GuardedStack for GuardedOwnedStack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    self.stack.guard_size()
+  }
+}
+
+impl Drop for GuardedOwnedStack {
+  fn drop(&mut self) {
+    unsafe {
+      let ptr = self.stack.limit().offset(-(self.stack.guard_size() as isize));
+      // Make the guard area accessible before giving the memory back.
+      ManuallyDrop::drop(&mut self.stack);
+      dealloc(ptr, self.layout)
+    }
+  }
+}
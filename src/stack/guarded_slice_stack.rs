@@ -0,0 +1,134 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::marker::PhantomData;
+use self::std::io::Error as IoError;
+use stack::{Stack, GuardedStack};
+use stack::os::{sys, overflow};
+
+/// GuardedSliceStack holds a guarded stack allocated elsewhere and provided as
+/// a mutable slice. The lowest pages of the slice are made inaccessible and serve
+/// as the guard area; they are made accessible again when the `GuardedSliceStack`
+/// is dropped.
+///
+/// Since `Generator::new` requires a `'static` stack, memory borrowed for a shorter
+/// lifetime can only be used with `Generator::unsafe_new`.
+/// [GuardedOwnedStack](struct.GuardedOwnedStack.html) allocates the memory itself,
+/// and can be created safely.
+///
+/// # Example
+///
+/// ```
+/// extern crate libc;
+/// extern crate fringe;
+///
+/// use std::alloc::{alloc_zeroed, Layout};
+/// use std::slice;
+/// use fringe::{GuardedSliceStack, Generator};
+///
+/// # fn main() {
+/// let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+/// let layout = Layout::from_size_align(16 * page_size, page_size).unwrap();
+/// let memory: &'static mut [u8] = unsafe {
+///   slice::from_raw_parts_mut(alloc_zeroed(layout), layout.size())
+/// };
+/// // The stack is dropped along with the generator, before the memory is reused.
+/// let stack = unsafe { GuardedSliceStack::new(memory).unwrap() };
+/// let mut gen = Generator::new(stack, move |yielder, ()| {
+///   yielder.suspend(1);
+/// });
+/// assert_eq!(gen.next(), Some(1));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct GuardedSliceStack<'a> {
+  // The slice is not kept as a reference, since part of it is inaccessible.
+  ptr:       *mut u8,
+  len:       usize,
+  guard_len: usize,
+  phantom:   PhantomData<&'a mut [u8]>
+}
+
+unsafe impl<'a> Send for GuardedSliceStack<'a> {}
+
+impl<'a> GuardedSliceStack<'a> {
+  /// Creates a `GuardedSliceStack` from an existing slice, using its lowest page
+  /// as the guard area.
+  ///
+  /// This function will panic if the slice does not start at a page boundary,
+  /// or if it is not large enough to hold the guard area and at least one more page.
+  ///
+  /// This function is unsafe because the guard area stays inaccessible until
+  /// the `GuardedSliceStack` is dropped. If it is leaked instead, e.g. using
+  /// `mem::forget`, the borrow of the slice ends while its lowest pages are still
+  /// inaccessible, and any later access to them, including one made by an allocator
+  /// or a stack frame reusing the memory, faults. The caller must ensure that
+  /// the `GuardedSliceStack` is dropped, unless the memory is never used again.
+  pub unsafe fn new(slice: &'a mut [u8]) -> Result<GuardedSliceStack<'a>, IoError> {
+    GuardedSliceStack::with_guard(slice, 0)
+  }
+
+  /// Same as `new`, but the guard area is at least `guard_size` bytes long.
+  /// `guard_size` is rounded up to an integral number of pages, and is at least one page.
+  ///
+  /// This function is unsafe for the same reason as `new`.
+  pub unsafe fn with_guard(slice: &'a mut [u8], guard_size: usize) -> Result<GuardedSliceStack<'a>, IoError> {
+    let page_size = sys::page_size();
+    if slice.as_ptr() as usize & (page_size - 1) != 0 {
+      panic!("GuardedSliceStack must start at a page boundary");
+    }
+
+    let guard_len = if guard_size == 0 { page_size } else { guard_size };
+    let guard_len = (guard_len + page_size - 1) & !(page_size - 1);
+    if slice.len() < guard_len + page_size {
+      panic!("GuardedSliceStack too small");
+    }
+
+    // Only use the part of the slice that ends at an aligned address.
+    let len = slice.len() & !(::STACK_ALIGNMENT - 1);
+    let stack = GuardedSliceStack {
+      ptr:       slice.as_mut_ptr(),
+      len:       len,
+      guard_len: guard_len,
+      phantom:   PhantomData
+    };
+
+    // If this fails, `stack` will be dropped, making the guard area accessible again.
+    try!(sys::protect_stack(stack.ptr, guard_len));
+    overflow::register(stack.ptr, stack.limit(), stack.base());
+
+    Ok(stack)
+  }
+}
+
+unsafe impl<'a> Stack for GuardedSliceStack<'a> {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    // The slice cannot wrap around the address space, so the conversion from usize
+    // to isize will not wrap either.
+    unsafe { self.ptr.offset(self.len as isize) }
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    unsafe { self.ptr.offset(self.guard_len as isize) }
+  }
+}
+
+unsafe impl<'a> GuardedStack for GuardedSliceStack<'a> {
+  #[inline(always)]
+  fn guard_size(&self) -> usize { self.guard_len }
+}
+
+impl<'a> Drop for GuardedSliceStack<'a> {
+  fn drop(&mut self) {
+    overflow::deregister(self.limit());
+    unsafe { sys::unprotect_stack(self.ptr, self.guard_len) }
+      .expect("cannot unprotect stack guard")
+  }
+}
@@ -9,15 +9,41 @@
 mod slice_stack;
 pub use stack::slice_stack::SliceStack;
 
+mod painted_stack;
+pub use stack::painted_stack::PaintedStack;
+
+mod checked_stack;
+pub use stack::checked_stack::CheckedStack;
+
 #[cfg(feature = "alloc")]
 mod owned_stack;
 #[cfg(feature = "alloc")]
 pub use stack::owned_stack::OwnedStack;
 
 #[cfg(unix)]
-mod os;
+pub(crate) mod os;
+#[cfg(unix)]
+pub use stack::os::{OsStack, install_overflow_handler};
+
+#[cfg(unix)]
+mod guarded_slice_stack;
 #[cfg(unix)]
-pub use stack::os::OsStack;
+pub use stack::guarded_slice_stack::GuardedSliceStack;
+
+#[cfg(all(unix, feature = "alloc"))]
+mod guarded_owned_stack;
+#[cfg(all(unix, feature = "alloc"))]
+pub use stack::guarded_owned_stack::GuardedOwnedStack;
+
+#[cfg(all(unix, feature = "std"))]
+mod pool;
+#[cfg(all(unix, feature = "std"))]
+pub use stack::pool::{StackPool, StackPoolConfig, PooledStack};
+
+#[cfg(all(unix, feature = "std"))]
+mod arena;
+#[cfg(all(unix, feature = "std"))]
+pub use stack::arena::{StackArena, ArenaStack};
 
 /// A trait for objects that hold ownership of a stack.
 ///
@@ -38,13 +64,78 @@ pub unsafe trait Stack {
   /// On all modern architectures, the stack grows downwards,
   /// so this is the lowest address.
   fn limit(&self) -> *mut u8;
+  /// Returns the largest amount of stack space, in bytes, that has been used
+  /// since the stack was created, if the stack keeps track of it.
+  /// See also [PaintedStack](struct.PaintedStack.html).
+  fn high_water_mark(&self) -> Option<usize> { None }
+  /// Panics if the stack has overflowed, given the saved stack pointer of
+  /// the generator running on it. Generators call this every time they are resumed
+  /// and every time they suspend themselves. The default implementation does nothing.
+  /// See also [CheckedStack](struct.CheckedStack.html).
+  fn check_overflow(&self, _stack_ptr: *mut u8) {}
+}
+
+/// The amount of memory used by a stack, as returned by
+/// [Generator::stack_usage](generator/struct.Generator.html#method.stack_usage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackUsage {
+  /// The largest amount of stack space, in bytes, that has been used,
+  /// if the stack keeps track of it.
+  pub high_water_mark: Option<usize>,
+  /// The amount of memory, in bytes, in the pages backing the stack that are
+  /// resident in physical memory, if it can be determined on this platform.
+  /// Pages are allocated by the operating system as they are touched, so for
+  /// a stack that is not painted, this is an upper bound on the high-water mark,
+  /// with a granularity of a page.
+  pub resident: Option<usize>
+}
+
+impl StackUsage {
+  pub(crate) fn of<S: Stack + ?Sized>(stack: &S) -> StackUsage {
+    #[cfg(unix)]
+    let resident = os::sys::resident_size(stack.limit(), stack.base());
+    #[cfg(not(unix))]
+    let resident = None;
+
+    StackUsage {
+      high_water_mark: stack.high_water_mark(),
+      resident:        resident
+    }
+  }
 }
 
-/// A marker trait for `Stack` objects with a guard page.
+/// A trait for `Stack` objects with a guard page.
 ///
 /// To preserve memory safety, an implementation of this trait must fulfill
 /// the following contract, in addition to the [contract](trait.Stack.html) of `Stack`:
 ///
-///   * Any access of data at addresses `limit()` to `limit().offset(4096)` must
-///     abnormally terminate, at least, the thread that performs the access.
-pub unsafe trait GuardedStack {}
+///   * Any access of data at addresses `limit().offset(-guard_size())` to `limit()`
+///     must abnormally terminate, at least, the thread that performs the access.
+///   * `guard_size()` must be at least 4096.
+pub unsafe trait GuardedStack {
+  /// Returns the size of the guard area below the limit address of the stack.
+  fn guard_size(&self) -> usize { 4096 }
+  /// Returns the size of the guard area above the base address of the stack,
+  /// if there is one, or zero otherwise.
+  fn upper_guard_size(&self) -> usize { 0 }
+}
+
+unsafe impl<'a, S: Stack + ?Sized> Stack for &'a mut S {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 { (**self).base() }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 { (**self).limit() }
+
+  fn high_water_mark(&self) -> Option<usize> { (**self).high_water_mark() }
+
+  fn check_overflow(&self, stack_ptr: *mut u8) { (**self).check_overflow(stack_ptr) }
+}
+
+unsafe impl<'a, S: GuardedStack + ?Sized> GuardedStack for &'a mut S {
+  #[inline(always)]
+  fn guard_size(&self) -> usize { (**self).guard_size() }
+
+  #[inline(always)]
+  fn upper_guard_size(&self) -> usize { (**self).upper_guard_size() }
+}
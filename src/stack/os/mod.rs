@@ -9,14 +9,18 @@ extern crate std;
 use self::std::io::Error as IoError;
 use stack::{Stack, GuardedStack};
 
-mod sys;
+pub(crate) mod sys;
+pub(crate) mod overflow;
+pub use self::overflow::install_overflow_handler;
 
 /// OsStack holds a guarded stack allocated using the operating system's anonymous
 /// memory mapping facility.
 #[derive(Debug)]
 pub struct OsStack {
-  ptr: *mut u8,
-  len: usize,
+  ptr:             *mut u8,
+  len:             usize,
+  guard_len:       usize,
+  upper_guard_len: usize
 }
 
 unsafe impl Send for OsStack {}
@@ -27,53 +31,95 @@ impl OsStack {
   /// and allocates the smallest possible stack, consisting of one data page and
   /// one guard page.
   pub fn new(size: usize) -> Result<OsStack, IoError> {
-    let page_size = sys::page_size();
+    OsStack::with_guards(size, 0, false)
+  }
+
+  /// Same as `new`, but the guard area below the stack is at least `guard_size`
+  /// bytes long. `guard_size` is rounded up to an integral number of pages, and is
+  /// at least one page.
+  pub fn with_guard(size: usize, guard_size: usize) -> Result<OsStack, IoError> {
+    OsStack::with_guards(size, guard_size, false)
+  }
 
-    // Stacks have to be at least one page long.
-    let len = if size == 0 { page_size } else { size };
+  /// Same as `with_guard`, but if `upper_guard` is true, also places a guard page
+  /// above the base of the stack, which catches accesses past the outermost frame
+  /// of the stack.
+  pub fn with_guards(size: usize, guard_size: usize, upper_guard: bool) -> Result<OsStack, IoError> {
+    let page_size = sys::page_size();
+    let round_up = |len: usize| {
+      // Stacks and guard areas have to be at least one page long.
+      let len = if len == 0 { page_size } else { len };
+      // Round the length one page size up, using the fact that the page size
+      // is a power of two.
+      (len + page_size - 1) & !(page_size - 1)
+    };
 
-    // Round the length one page size up, using the fact that the page size
-    // is a power of two.
-    let len = (len + page_size - 1) & !(page_size - 1);
+    let guard_len = round_up(guard_size);
+    let upper_guard_len = if upper_guard { page_size } else { 0 };
 
-    // Increase the length to fit the guard page.
-    let len = len + page_size;
+    // Increase the length to fit the guard areas.
+    let len = guard_len + round_up(size) + upper_guard_len;
 
     // Allocate a stack.
     let ptr = try!(unsafe { sys::map_stack(len) });
     let stack = OsStack {
-      ptr: ptr,
-      len: len,
+      ptr:             ptr,
+      len:             len,
+      guard_len:       guard_len,
+      upper_guard_len: upper_guard_len
     };
 
-    // Mark the guard page. If this fails, `stack` will be dropped,
+    // Mark the guard areas. If this fails, `stack` will be dropped,
     // unmapping it.
-    try!(unsafe { sys::protect_stack(stack.ptr) });
+    try!(unsafe { sys::protect_stack(stack.ptr, guard_len) });
+    if upper_guard {
+      try!(unsafe { sys::protect_stack(stack.base(), upper_guard_len) });
+    }
+    overflow::register(stack.ptr, stack.limit(), stack.base());
 
     Ok(stack)
   }
+
+  /// Returns the length of the mapping, including the guard page.
+  pub(crate) fn mapping_len(&self) -> usize {
+    self.len
+  }
+
+  /// Gives the physical memory backing the stack back to the operating system.
+  /// The stack remains usable, but its contents are lost.
+  pub(crate) fn release_memory(&self) -> Result<(), IoError> {
+    let len = self.base() as usize - self.limit() as usize;
+    unsafe { sys::release_stack(self.limit(), len) }
+  }
 }
 
 unsafe impl Stack for OsStack {
   #[inline(always)]
   fn base(&self) -> *mut u8 {
     unsafe {
-      self.ptr.offset(self.len as isize)
+      self.ptr.offset((self.len - self.upper_guard_len) as isize)
     }
   }
 
   #[inline(always)]
   fn limit(&self) -> *mut u8 {
     unsafe {
-      self.ptr.offset(sys::page_size() as isize)
+      self.ptr.offset(self.guard_len as isize)
     }
   }
 }
 
-unsafe impl GuardedStack for OsStack {}
+unsafe impl GuardedStack for OsStack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize { self.guard_len }
+
+  #[inline(always)]
+  fn upper_guard_size(&self) -> usize { self.upper_guard_len }
+}
 
 impl Drop for OsStack {
   fn drop(&mut self) {
+    overflow::deregister(self.limit());
     unsafe { sys::unmap_stack(self.ptr, self.len) }.expect("cannot unmap stack")
   }
 }
@@ -0,0 +1,285 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Diagnosis of generator stack overflows.
+//!
+//! Every live guarded stack is recorded in a registry, which is consulted by
+//! a SIGSEGV/SIGBUS handler to tell whether a fault has hit a guard page.
+//! The handler runs on an alternate signal stack and cannot allocate or take
+//! locks, so the registry is protected by spinlocks that the handler only
+//! ever tries to acquire. The registry is split into shards by the address
+//! of the stacks, so that threads creating stacks rarely wait for each other.
+extern crate std;
+extern crate libc;
+
+use core::{hint, mem, ptr};
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use self::std::boxed::Box;
+use self::std::collections::BTreeMap;
+use self::std::io::Error as IoError;
+use self::libc::{c_int, c_void, siginfo_t};
+use stack::os::sys;
+
+/// A guarded stack, keyed by its limit address in the registry.
+#[derive(Debug, Clone, Copy)]
+struct Guarded {
+  guard:   usize,
+  base:    usize,
+  name:    Option<&'static str>,
+  recover: bool
+}
+
+struct Registry {
+  lock:   AtomicBool,
+  stacks: UnsafeCell<Option<BTreeMap<usize, Guarded>>>
+}
+
+unsafe impl Sync for Registry {}
+
+const SHARDS: usize = 16;
+
+const EMPTY: Registry = Registry {
+  lock:   AtomicBool::new(false),
+  stacks: UnsafeCell::new(None)
+};
+
+static REGISTRY: [Registry; SHARDS] = [EMPTY; SHARDS];
+
+/// Returns the shard of the registry that records the stack with the given limit address.
+#[inline]
+fn shard(limit: usize) -> &'static Registry {
+  // Stacks are often the same size, so their addresses are hashed to spread them out.
+  let hash = ((limit >> 12) as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+  &REGISTRY[(hash >> 60) as usize % SHARDS]
+}
+
+impl Registry {
+  fn with<F, R>(&self, f: F) -> R
+      where F: FnOnce(&mut BTreeMap<usize, Guarded>) -> R {
+    while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+      hint::spin_loop()
+    }
+    let result = f(unsafe { (*self.stacks.get()).get_or_insert_with(BTreeMap::new) });
+    self.lock.store(false, Ordering::Release);
+    result
+  }
+
+  /// Same as `with`, but gives up after a while instead of spinning forever;
+  /// the lock could be held by the very thread that is handling a signal.
+  fn try_with<F, R>(&self, f: F) -> Option<R>
+      where F: FnOnce(&BTreeMap<usize, Guarded>) -> R {
+    for _ in 0..1000 {
+      if self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+        let result = unsafe { (*self.stacks.get()).as_ref().map(f) };
+        self.lock.store(false, Ordering::Release);
+        return result
+      }
+      hint::spin_loop()
+    }
+    None
+  }
+}
+
+/// Records a stack with a guard area spanning `guard..limit`.
+pub fn register(guard: *mut u8, limit: *mut u8, base: *mut u8) {
+  let stack = Guarded { guard: guard as usize, base: base as usize, name: None, recover: false };
+  shard(limit as usize).with(|stacks| stacks.insert(limit as usize, stack));
+}
+
+/// Forgets a stack recorded using `register`.
+pub fn deregister(limit: *mut u8) {
+  shard(limit as usize).with(|stacks| stacks.remove(&(limit as usize)));
+}
+
+/// Sets the name reported if the stack with the given limit address overflows,
+/// and whether the generator running on it should be abandoned instead.
+/// Does nothing if the stack is not recorded, e.g. because it is not an `OsStack`.
+pub fn describe(limit: *mut u8, name: Option<&'static str>, recover: bool) {
+  shard(limit as usize).with(|stacks| {
+    if let Some(stack) = stacks.get_mut(&(limit as usize)) {
+      stack.name = name;
+      stack.recover = recover
+    }
+  })
+}
+
+/// The value returned by `arch::swap` to the resumer of a generator that has
+/// been abandoned after overflowing its stack. It is never a valid pointer.
+pub const OVERFLOWED: usize = !0;
+
+/// Returns the limit address of the stack whose guard area contains `addr`,
+/// along with that stack, if it is recorded and its shard is not locked.
+fn find(addr: usize) -> Option<(usize, Guarded)> {
+  REGISTRY.iter().filter_map(|shard| shard.try_with(|stacks| lookup(stacks, addr))).flatten().next()
+}
+
+/// Same as `find`, but only looks at the stacks recorded in one shard.
+fn lookup(stacks: &BTreeMap<usize, Guarded>, addr: usize) -> Option<(usize, Guarded)> {
+  match stacks.range(addr + 1..).next() {
+    Some((&limit, &stack)) if addr >= stack.guard => Some((limit, stack)),
+    _ => None
+  }
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static mut PREVIOUS_SIGSEGV: *mut libc::sigaction = 0 as *mut _;
+static mut PREVIOUS_SIGBUS:  *mut libc::sigaction = 0 as *mut _;
+
+/// Installs a SIGSEGV and SIGBUS handler that recognizes accesses to the guard page
+/// of an [OsStack](struct.OsStack.html), reports a generator stack overflow along
+/// with the bounds of the stack and the [name](generator/struct.Generator.html#method.set_name)
+/// of the generator, if any, and aborts the process. Other faults are passed on
+/// to the handler that was installed previously.
+///
+/// On x86_64 Linux, generators with the [overflow policy](generator/enum.OverflowPolicy.html)
+/// `Poison` are abandoned instead, and the pending `resume()` call fails.
+///
+/// The handler runs on the alternate signal stack of the faulting thread.
+/// The standard library sets one up for every thread it spawns, and this function
+/// sets one up for the calling thread if it has none.
+///
+/// Calling this function more than once has no further effect.
+pub fn install_overflow_handler() -> Result<(), IoError> {
+  unsafe {
+    try!(ensure_sigaltstack());
+
+    if INSTALLED.swap(true, Ordering::SeqCst) { return Ok(()) }
+
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handler as unsafe extern "C" fn(c_int, *mut siginfo_t, *mut c_void) as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+    libc::sigemptyset(&mut action.sa_mask);
+
+    PREVIOUS_SIGSEGV = try!(replace_action(libc::SIGSEGV, &action));
+    PREVIOUS_SIGBUS  = try!(replace_action(libc::SIGBUS,  &action));
+
+    Ok(())
+  }
+}
+
+/// Installs `action` for `signal`, returning the previously installed action.
+unsafe fn replace_action(signal: c_int, action: &libc::sigaction)
+    -> Result<*mut libc::sigaction, IoError> {
+  let mut previous: libc::sigaction = mem::zeroed();
+  if libc::sigaction(signal, action, &mut previous) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  Ok(Box::into_raw(Box::new(previous)))
+}
+
+/// Sets up an alternate signal stack for the current thread if it has none.
+/// The alternate signal stack is never freed.
+unsafe fn ensure_sigaltstack() -> Result<(), IoError> {
+  let mut current: libc::stack_t = mem::zeroed();
+  if libc::sigaltstack(ptr::null(), &mut current) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  if current.ss_flags & libc::SS_DISABLE == 0 { return Ok(()) }
+
+  let size = if libc::SIGSTKSZ > 1 << 16 { libc::SIGSTKSZ } else { 1 << 16 };
+  let stack = libc::stack_t {
+    ss_sp:    try!(sys::map_stack(size)) as *mut c_void,
+    ss_flags: 0,
+    ss_size:  size
+  };
+  if libc::sigaltstack(&stack, ptr::null_mut()) != 0 {
+    return Err(IoError::last_os_error())
+  }
+  Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn fault_address(info: *mut siginfo_t) -> usize {
+  (*info).si_addr() as usize
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn fault_address(info: *mut siginfo_t) -> usize {
+  (*info).si_addr as usize
+}
+
+unsafe extern "C" fn handler(signal: c_int, info: *mut siginfo_t, context: *mut c_void) {
+  let addr = fault_address(info);
+  if let Some((limit, stack)) = find(addr) {
+    if stack.recover && recover(stack, context) { return }
+    let _ = report(addr, limit, stack);
+    libc::abort()
+  }
+
+  // Not a guard page we know about; let the previous handler deal with it.
+  let previous = if signal == libc::SIGSEGV { PREVIOUS_SIGSEGV } else { PREVIOUS_SIGBUS };
+  let previous = &*previous;
+  if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+    // Returning will retry the faulting access, which will then be handled
+    // by the default action.
+    libc::sigaction(signal, previous, ptr::null_mut());
+  } else if previous.sa_flags & libc::SA_SIGINFO != 0 {
+    let previous: extern "C" fn(c_int, *mut siginfo_t, *mut c_void) =
+      mem::transmute(previous.sa_sigaction);
+    previous(signal, info, context)
+  } else {
+    let previous: extern "C" fn(c_int) = mem::transmute(previous.sa_sigaction);
+    previous(signal)
+  }
+}
+
+/// Makes the signal handler return to the resumer of the generator running on `stack`,
+/// as if the generator has switched back to it, passing `OVERFLOWED`. The generator
+/// stack is never switched to again.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn recover(stack: Guarded, context: *mut c_void) -> bool {
+  use arch;
+
+  let context = &mut *(context as *mut libc::ucontext_t);
+  let gregs = &mut context.uc_mcontext.gregs;
+
+  // Make sure that the faulting thread is running on the stack that has overflowed,
+  // and not just touching its guard page from elsewhere.
+  let sp = gregs[libc::REG_RSP as usize] as usize;
+  if sp >= stack.base || sp < stack.guard.saturating_sub(stack.base - stack.guard) { return false }
+
+  let (sp, pc) = arch::abandon(stack.base as *mut u8);
+  gregs[libc::REG_RSP as usize] = sp as i64;
+  gregs[libc::REG_RIP as usize] = pc as i64;
+  gregs[libc::REG_RDI as usize] = OVERFLOWED as i64;
+  true
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+unsafe fn recover(_stack: Guarded, _context: *mut c_void) -> bool {
+  false
+}
+
+fn report(addr: usize, limit: usize, stack: Guarded) -> fmt::Result {
+  let mut stderr = Stderr;
+  try!(writeln!(stderr, "fatal runtime error: generator stack overflow"));
+  if let Some(name) = stack.name {
+    try!(writeln!(stderr, "  generator: {}", name));
+  }
+  try!(writeln!(stderr, "  stack:     {:#x}-{:#x}", limit, stack.base));
+  try!(writeln!(stderr, "  guard:     {:#x}-{:#x}", stack.guard, limit));
+  writeln!(stderr, "  fault:     {:#x}", addr)
+}
+
+/// An async-signal-safe writer to the standard error.
+struct Stderr;
+
+impl Write for Stderr {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    let mut bytes = s.as_bytes();
+    while !bytes.is_empty() {
+      let written = unsafe {
+        libc::write(libc::STDERR_FILENO, bytes.as_ptr() as *const c_void, bytes.len())
+      };
+      if written <= 0 { return Err(fmt::Error) }
+      bytes = &bytes[written as usize..];
+    }
+    Ok(())
+  }
+}
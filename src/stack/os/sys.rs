@@ -8,10 +8,10 @@ extern crate std;
 extern crate libc;
 
 use self::std::sync::atomic::{AtomicUsize, Ordering};
-use self::std::ptr;
+use self::std::{ptr, mem};
 use self::std::io::Error as IoError;
 use self::libc::{c_void, c_int, size_t};
-use self::libc::{mmap, mprotect, munmap};
+use self::libc::{mmap, mprotect, munmap, madvise, mincore};
 use self::libc::MAP_FAILED;
 
 const GUARD_PROT:  c_int = libc::PROT_NONE;
@@ -36,8 +36,29 @@ pub unsafe fn map_stack(len: usize) -> Result<*mut u8, IoError> {
   }
 }
 
-pub unsafe fn protect_stack(ptr: *mut u8) -> Result<(), IoError> {
-  if mprotect(ptr as *mut c_void, page_size() as size_t, GUARD_PROT) == 0 {
+pub unsafe fn protect_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  if mprotect(ptr as *mut c_void, len as size_t, GUARD_PROT) == 0 {
+    Ok(())
+  } else {
+    Err(IoError::last_os_error())
+  }
+}
+
+/// Makes `len` bytes at `ptr` inaccessible. Where the kernel supports it, lightweight
+/// guard regions are used, which do not split the mapping that contains them.
+pub unsafe fn guard_pages(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  #[cfg(target_os = "linux")]
+  {
+    const MADV_GUARD_INSTALL: c_int = 102;
+    if madvise(ptr as *mut c_void, len as size_t, MADV_GUARD_INSTALL) == 0 {
+      return Ok(())
+    }
+  }
+  protect_stack(ptr, len)
+}
+
+pub unsafe fn unprotect_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  if mprotect(ptr as *mut c_void, len as size_t, STACK_PROT) == 0 {
     Ok(())
   } else {
     Err(IoError::last_os_error())
@@ -52,6 +73,31 @@ pub unsafe fn unmap_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
   }
 }
 
+pub unsafe fn release_stack(ptr: *mut u8, len: usize) -> Result<(), IoError> {
+  if madvise(ptr as *mut c_void, len as size_t, libc::MADV_DONTNEED) == 0 {
+    Ok(())
+  } else {
+    Err(IoError::last_os_error())
+  }
+}
+
+/// Returns the amount of memory in the pages overlapping `limit..base`
+/// that are resident in physical memory.
+pub fn resident_size(limit: *mut u8, base: *mut u8) -> Option<usize> {
+  let page_size = page_size();
+  let start = limit as usize & !(page_size - 1);
+  let end   = (base as usize + page_size - 1) & !(page_size - 1);
+  let pages = (end - start) / page_size;
+
+  let mut residency = self::std::vec![0u8; pages];
+  let ret = unsafe {
+    mincore(start as *mut c_void, (end - start) as size_t, residency.as_mut_ptr() as *mut _)
+  };
+  if ret != 0 { return None }
+
+  Some(residency.iter().filter(|&&page| page & 1 != 0).count() * page_size)
+}
+
 pub fn page_size() -> usize {
   #[cold]
   pub fn sys_page_size() -> usize {
@@ -70,3 +116,40 @@ pub fn page_size() -> usize {
     page_size => page_size
   }
 }
+
+/// Returns the limit address of the stack of the current thread.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+pub fn thread_stack_limit() -> Option<usize> {
+  unsafe {
+    let mut attr: libc::pthread_attr_t = mem::zeroed();
+    #[cfg(target_os = "linux")]
+    let ret = libc::pthread_getattr_np(libc::pthread_self(), &mut attr);
+    #[cfg(not(target_os = "linux"))]
+    let ret = {
+      libc::pthread_attr_init(&mut attr);
+      libc::pthread_attr_get_np(libc::pthread_self(), &mut attr)
+    };
+    if ret != 0 { return None }
+
+    let mut addr = ptr::null_mut();
+    let mut size = 0;
+    let ret = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+    libc::pthread_attr_destroy(&mut attr);
+    if ret == 0 { Some(addr as usize) } else { None }
+  }
+}
+
+/// Returns the limit address of the stack of the current thread.
+#[cfg(target_vendor = "apple")]
+pub fn thread_stack_limit() -> Option<usize> {
+  unsafe {
+    let thread = libc::pthread_self();
+    Some(libc::pthread_get_stackaddr_np(thread) as usize - libc::pthread_get_stacksize_np(thread))
+  }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly",
+              target_vendor = "apple")))]
+pub fn thread_stack_limit() -> Option<usize> {
+  None
+}
@@ -0,0 +1,95 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+use core::{ptr, mem};
+use stack::{Stack, GuardedStack};
+
+/// The byte every word of a painted stack is filled with.
+const PAINT_BYTE: u8 = 0xf1;
+
+/// PaintedStack wraps another stack and fills it with a known pattern,
+/// so that the amount of stack space used by a generator can be measured
+/// afterwards by looking for the deepest word that no longer holds the pattern.
+///
+/// Painting writes to every page of the stack, so none of them can be lazily
+/// allocated by the operating system anymore.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{OsStack, PaintedStack, Generator};
+///
+/// let stack = PaintedStack::new(OsStack::new(1 << 16).unwrap());
+/// let mut gen = Generator::new(stack, move |yielder, ()| {
+///   let buffer = [1u8; 1024];
+///   yielder.suspend(buffer.iter().map(|&x| x as u32).sum::<u32>())
+/// });
+/// assert_eq!(gen.next(), Some(1024));
+/// assert!(gen.stack_usage().high_water_mark.unwrap() >= 1024);
+/// ```
+#[derive(Debug)]
+pub struct PaintedStack<S: Stack>(S);
+
+impl<S: Stack> PaintedStack<S> {
+  /// Paints `stack` and wraps it.
+  pub fn new(stack: S) -> PaintedStack<S> {
+    let mut stack = PaintedStack(stack);
+    stack.repaint();
+    stack
+  }
+
+  /// Paints the stack again, resetting its high-water mark.
+  pub fn repaint(&mut self) {
+    let len = self.0.base() as usize - self.0.limit() as usize;
+    unsafe { ptr::write_bytes(self.0.limit(), PAINT_BYTE, len) }
+  }
+
+  /// Extracts the wrapped stack.
+  pub fn into_inner(self) -> S {
+    self.0
+  }
+}
+
+unsafe impl<S: Stack> Stack for PaintedStack<S> {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    self.0.base()
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    self.0.limit()
+  }
+
+  fn high_water_mark(&self) -> Option<usize> {
+    let paint = usize::from_ne_bytes([PAINT_BYTE; mem::size_of::<usize>()]);
+
+    // The limit of a stack is not necessarily aligned, but its base is.
+    let base  = self.base() as usize;
+    let words = (base - self.limit() as usize) / mem::size_of::<usize>();
+    let lowest = (base - words * mem::size_of::<usize>()) as *const usize;
+    let untouched = (0..words)
+      .take_while(|&index| unsafe { ptr::read_volatile(lowest.offset(index as isize)) } == paint)
+      .count();
+    Some((words - untouched) * mem::size_of::<usize>())
+  }
+
+  fn check_overflow(&self, stack_ptr: *mut u8) {
+    self.0.check_overflow(stack_ptr)
+  }
+}
+
+unsafe impl<S: GuardedStack + Stack> GuardedStack for PaintedStack<S> {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    self.0.guard_size()
+  }
+
+  #[inline(always)]
+  fn upper_guard_size(&self) -> usize {
+    self.0.upper_guard_size()
+  }
+}
@@ -0,0 +1,248 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::ptr;
+use core::mem::ManuallyDrop;
+use self::std::cell::RefCell;
+use self::std::io::Error as IoError;
+use self::std::sync::{Arc, Weak, Mutex};
+use self::std::vec::Vec;
+use stack::{Stack, GuardedStack, OsStack};
+
+/// Limits on the idle stacks kept by a [StackPool](struct.StackPool.html).
+#[derive(Debug, Clone, Copy)]
+pub struct StackPoolConfig {
+  /// The maximum number of idle stacks shared by all threads.
+  pub max_idle_stacks: usize,
+  /// The maximum total size, in bytes, of idle stacks shared by all threads,
+  /// including their guard pages.
+  pub max_idle_bytes: usize,
+  /// The maximum number of idle stacks cached by each thread, in addition to the shared ones.
+  /// Stacks are returned to the cache of the thread that drops them, and taken from
+  /// the cache of the thread that requests them, without any synchronization.
+  pub thread_cache_size: usize,
+  /// Whether to give the memory of stacks returned to the shared idle list back
+  /// to the operating system, using `madvise(MADV_DONTNEED)`. The address space
+  /// stays reserved, so reusing such a stack is still much cheaper than allocating
+  /// a new one, but its pages have to be faulted in again.
+  pub release_memory: bool
+}
+
+impl Default for StackPoolConfig {
+  fn default() -> StackPoolConfig {
+    StackPoolConfig {
+      max_idle_stacks:   1024,
+      max_idle_bytes:    256 << 20,
+      thread_cache_size: 16,
+      release_memory:    false
+    }
+  }
+}
+
+/// StackPool recycles stacks allocated using [OsStack](struct.OsStack.html),
+/// avoiding the cost of creating and destroying a memory mapping for every stack.
+///
+/// Every stack handed out by the pool, a [PooledStack](struct.PooledStack.html),
+/// returns to the pool when dropped. Pools are cheap to clone, and the clones
+/// share their idle stacks.
+///
+/// # Example
+///
+/// ```
+/// use fringe::{StackPool, Generator};
+///
+/// let pool = StackPool::new(1 << 16);
+/// for i in 0..10 {
+///   let stack = pool.get().unwrap();
+///   let mut gen = Generator::new(stack, move |yielder, ()| yielder.suspend(i));
+///   assert_eq!(gen.next(), Some(i));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StackPool {
+  inner: Arc<Inner>
+}
+
+#[derive(Debug)]
+struct Inner {
+  stack_size: usize,
+  config:     StackPoolConfig,
+  idle:       Mutex<Idle>
+}
+
+#[derive(Debug)]
+struct Idle {
+  stacks: Vec<OsStack>,
+  bytes:  usize
+}
+
+/// The stacks cached by the current thread for a single pool.
+struct ThreadCache {
+  pool:   Weak<Inner>,
+  id:     usize,
+  stacks: Vec<OsStack>
+}
+
+self::std::thread_local! {
+  static THREAD_CACHES: RefCell<Vec<ThreadCache>> = RefCell::new(Vec::new());
+}
+
+impl StackPool {
+  /// Creates a pool of stacks with at least `size` accessible bytes each,
+  /// using the default limits. See also `OsStack::new`.
+  pub fn new(size: usize) -> StackPool {
+    StackPool::with_config(size, StackPoolConfig::default())
+  }
+
+  /// Creates a pool of stacks with at least `size` accessible bytes each,
+  /// using the limits in `config`.
+  pub fn with_config(size: usize, config: StackPoolConfig) -> StackPool {
+    StackPool {
+      inner: Arc::new(Inner {
+        stack_size: size,
+        config:     config,
+        idle:       Mutex::new(Idle { stacks: Vec::new(), bytes: 0 })
+      })
+    }
+  }
+
+  /// Takes an idle stack from the pool, or allocates a new one if there are none.
+  pub fn get(&self) -> Result<PooledStack, IoError> {
+    let stack = match Inner::take(&self.inner) {
+      Some(stack) => stack,
+      None => try!(OsStack::new(self.inner.stack_size))
+    };
+    Ok(PooledStack {
+      stack: ManuallyDrop::new(stack),
+      pool:  self.inner.clone()
+    })
+  }
+
+  /// Returns the number of idle stacks shared by all threads.
+  pub fn idle_stacks(&self) -> usize {
+    self.inner.idle.lock().unwrap().stacks.len()
+  }
+
+  /// Returns the total size, in bytes, of idle stacks shared by all threads.
+  pub fn idle_bytes(&self) -> usize {
+    self.inner.idle.lock().unwrap().bytes
+  }
+}
+
+impl Inner {
+  /// Runs `f` on the cache of the current thread for `pool`, or returns `None`
+  /// if the thread is being torn down.
+  fn with_thread_cache<F, R>(pool: &Arc<Inner>, f: F) -> Option<R>
+      where F: FnOnce(&mut Vec<OsStack>) -> R {
+    THREAD_CACHES.try_with(|caches| {
+      let mut caches = caches.borrow_mut();
+      // Get rid of caches of the pools that no longer exist.
+      caches.retain(|cache| cache.pool.upgrade().is_some());
+      let id = &**pool as *const Inner as usize;
+      let index = match caches.iter().position(|cache| cache.id == id) {
+        Some(index) => index,
+        None => {
+          caches.push(ThreadCache { pool: Arc::downgrade(pool), id: id, stacks: Vec::new() });
+          caches.len() - 1
+        }
+      };
+      f(&mut caches[index].stacks)
+    }).ok()
+  }
+
+  fn take(pool: &Arc<Inner>) -> Option<OsStack> {
+    if pool.config.thread_cache_size > 0 {
+      if let Some(Some(stack)) = Inner::with_thread_cache(pool, |stacks| stacks.pop()) {
+        return Some(stack)
+      }
+    }
+
+    let mut idle = pool.idle.lock().unwrap();
+    let stack = idle.stacks.pop();
+    if let Some(ref stack) = stack { idle.bytes -= stack.mapping_len() }
+    stack
+  }
+
+  fn put(pool: &Arc<Inner>, stack: OsStack) {
+    let thread_cache_size = pool.config.thread_cache_size;
+    let mut stack = Some(stack);
+    if thread_cache_size > 0 {
+      Inner::with_thread_cache(pool, |stacks| {
+        if stacks.len() < thread_cache_size { stacks.push(stack.take().unwrap()) }
+      });
+    }
+
+    if let Some(stack) = stack { pool.put_idle(stack) }
+  }
+
+  fn put_idle(&self, stack: OsStack) {
+    if self.config.release_memory {
+      // If this fails, the stack is merely not as cheap to keep around.
+      let _ = stack.release_memory();
+    }
+
+    let mut idle = self.idle.lock().unwrap();
+    let len = stack.mapping_len();
+    if idle.stacks.len() < self.config.max_idle_stacks &&
+       idle.bytes + len <= self.config.max_idle_bytes {
+      idle.stacks.push(stack);
+      idle.bytes += len;
+    } else {
+      // Don't unmap the stack while holding the lock.
+      drop(idle);
+      drop(stack)
+    }
+  }
+}
+
+impl Drop for ThreadCache {
+  fn drop(&mut self) {
+    if let Some(pool) = self.pool.upgrade() {
+      for stack in self.stacks.drain(..) { pool.put_idle(stack) }
+    }
+  }
+}
+
+/// PooledStack holds a guarded stack taken from a [StackPool](struct.StackPool.html),
+/// and returns it to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledStack {
+  stack: ManuallyDrop<OsStack>,
+  pool:  Arc<Inner>
+}
+
+unsafe impl Stack for PooledStack {
+  #[inline(always)]
+  fn base(&self) -> *mut u8 {
+    self.stack.base()
+  }
+
+  #[inline(always)]
+  fn limit(&self) -> *mut u8 {
+    self.stack.limit()
+  }
+}
+
+unsafe impl GuardedStack for PooledStack {
+  #[inline(always)]
+  fn guard_size(&self) -> usize {
+    self.stack.guard_size()
+  }
+
+  #[inline(always)]
+  fn upper_guard_size(&self) -> usize {
+    self.stack.upper_guard_size()
+  }
+}
+
+impl Drop for PooledStack {
+  fn drop(&mut self) {
+    let stack = unsafe { ptr::read(&*self.stack) };
+    Inner::put(&self.pool, stack)
+  }
+}
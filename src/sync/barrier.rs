@@ -0,0 +1,82 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::marker::PhantomData;
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use super::{Park, DefaultPark, Waiter, OnUnwind, remove};
+
+/// A barrier, which suspends fibers until a given number of them have reached it.
+/// The barrier can be reused afterwards.
+pub struct Barrier<P: Park = DefaultPark> {
+  count:   usize,
+  state:   StdMutex<State<P::Unparker>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct State<U> {
+  waiters: VecDeque<Arc<Waiter<U>>>
+}
+
+/// Returned by `Barrier::wait()`; exactly one of the fibers passing the barrier
+/// together is the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+  /// Returns whether this fiber is the leader, i.e. the last one to reach the barrier.
+  #[inline]
+  pub fn is_leader(&self) -> bool { self.0 }
+}
+
+impl Barrier {
+  /// Creates a barrier for `count` fibers that uses `DefaultPark`.
+  pub fn new(count: usize) -> Barrier {
+    Barrier::with_park(count)
+  }
+}
+
+impl<P: Park> Barrier<P> {
+  /// Creates a barrier for `count` fibers that uses `P`.
+  pub fn with_park(count: usize) -> Barrier<P> {
+    Barrier {
+      count:   count,
+      state:   StdMutex::new(State { waiters: VecDeque::new() }),
+      phantom: PhantomData
+    }
+  }
+
+  /// Suspends the current fiber until `count` fibers have called `wait()`.
+  pub fn wait(&self) -> BarrierWaitResult {
+    let waiter = {
+      let mut state = self.state.lock().unwrap();
+      if state.waiters.len() + 1 >= self.count {
+        let waiters = mem::replace(&mut state.waiters, VecDeque::new());
+        drop(state);
+        for waiter in waiters { waiter.notify() }
+        return BarrierWaitResult(true)
+      }
+      let waiter = Waiter::new::<P>();
+      state.waiters.push_back(waiter.clone());
+      waiter
+    };
+
+    // A fiber that is unwound no longer counts as having reached the barrier.
+    let abandon = OnUnwind(|| { remove(&mut self.state.lock().unwrap().waiters, &waiter); });
+    waiter.wait::<P>();
+    mem::forget(abandon);
+    BarrierWaitResult(false)
+  }
+}
+
+impl<P: Park> fmt::Debug for Barrier<P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Barrier").field("count", &self.count).finish()
+  }
+}
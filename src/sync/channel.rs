@@ -0,0 +1,689 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Channels for sending values between fibers.
+//!
+//! A channel is unbounded, bounded, or a rendezvous channel, which holds no values at all:
+//! `send()` on it waits until a receiver takes the value. Sending on a full channel
+//! and receiving from an empty one suspend the current fiber. Both ends can be cloned,
+//! and every value is received once.
+//!
+//! The [select!](../../macro.select.html) macro waits for the first of several
+//! operations on channels to complete.
+//!
+//! # Example
+//!
+//! ```
+//! use fringe::rt;
+//! use fringe::sync::channel;
+//!
+//! rt::run(|| {
+//!   let (tx, rx) = channel::bounded(1);
+//!   let producer = rt::spawn(rt::stack(), move || {
+//!     for i in 0..5 { tx.send(i).unwrap() }
+//!   });
+//!   assert_eq!(rx.iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+//!   producer.join().unwrap();
+//! });
+//! ```
+extern crate std;
+
+use core::{fmt, mem};
+use core::marker::PhantomData;
+use alloc::boxed::Box;
+use self::std::collections::VecDeque;
+use self::std::error::Error;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use self::std::vec::Vec;
+use super::{Park, DefaultPark, Waiter, OnUnwind};
+
+/// The sending half of a channel.
+pub struct Sender<T, P: Park = DefaultPark> {
+  chan: Arc<Chan<T, P::Unparker>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T, P: Park = DefaultPark> {
+  chan: Arc<Chan<T, P::Unparker>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct Chan<T, U> {
+  state: StdMutex<State<T, U>>
+}
+
+struct State<T, U> {
+  /// The values, with their sequence numbers.
+  queue:     VecDeque<(u64, T)>,
+  /// The capacity, or `None` if the channel is unbounded.
+  capacity:  Option<usize>,
+  senders:   usize,
+  receivers: usize,
+  /// The number of values ever sent.
+  sent:      u64,
+  /// The number of fibers waiting to receive. A rendezvous channel holds
+  /// as many values as there are.
+  receiving: usize,
+  /// The fibers waiting for the channel to change.
+  recv_waiters: Vec<Arc<Waiter<U>>>,
+  send_waiters: Vec<Arc<Waiter<U>>>
+}
+
+impl<T, U: super::Unpark> State<T, U> {
+  fn has_room(&self) -> bool {
+    match self.capacity {
+      None => true,
+      Some(0) => self.queue.len() < self.receiving,
+      Some(capacity) => self.queue.len() < capacity
+    }
+  }
+
+  /// Sends `value` if possible, and returns its sequence number.
+  fn try_send(&mut self, value: T) -> Result<u64, TrySendError<T>> {
+    if self.receivers == 0 { return Err(TrySendError::Disconnected(value)) }
+    if !self.has_room() { return Err(TrySendError::Full(value)) }
+    self.sent += 1;
+    self.queue.push_back((self.sent, value));
+    wake(&mut self.recv_waiters);
+    Ok(self.sent)
+  }
+
+  fn try_recv(&mut self) -> Result<T, TryRecvError> {
+    match self.queue.pop_front() {
+      Some((_, value)) => {
+        wake(&mut self.send_waiters);
+        Ok(value)
+      }
+      None if self.senders == 0 => Err(TryRecvError::Disconnected),
+      None => Err(TryRecvError::Empty)
+    }
+  }
+}
+
+fn wake<U: super::Unpark>(waiters: &mut Vec<Arc<Waiter<U>>>) {
+  for waiter in waiters.drain(..) { waiter.notify() }
+}
+
+fn unregister<U>(waiters: &mut Vec<Arc<Waiter<U>>>, waiter: &Arc<Waiter<U>>) {
+  waiters.retain(|other| !Arc::ptr_eq(other, waiter))
+}
+
+/// Creates a channel that can hold any number of values.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+  with_park(None)
+}
+
+/// Creates a channel that holds up to `capacity` values. If `capacity` is zero,
+/// it is a rendezvous channel.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+  with_park(Some(capacity))
+}
+
+/// Creates a rendezvous channel, which holds no values: every `send()` waits
+/// until a receiver takes the value.
+pub fn rendezvous<T>() -> (Sender<T>, Receiver<T>) {
+  with_park(Some(0))
+}
+
+/// Creates a channel that uses `P`, and holds up to `capacity` values,
+/// or any number of them if `capacity` is `None`.
+pub fn with_park<T, P: Park>(capacity: Option<usize>) -> (Sender<T, P>, Receiver<T, P>) {
+  let chan = Arc::new(Chan {
+    state: StdMutex::new(State {
+      queue:        VecDeque::new(),
+      capacity:     capacity,
+      senders:      1,
+      receivers:    1,
+      sent:         0,
+      receiving:    0,
+      recv_waiters: Vec::new(),
+      send_waiters: Vec::new()
+    })
+  });
+  (Sender { chan: chan.clone(), phantom: PhantomData }, Receiver { chan: chan, phantom: PhantomData })
+}
+
+impl<T, P: Park> Sender<T, P> {
+  /// Sends `value`, suspending the current fiber while the channel is full.
+  /// On a rendezvous channel, waits until a receiver has taken the value.
+  ///
+  /// Fails if every receiver has been dropped, returning the value.
+  pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+    let mut value = Some(value);
+    let mut result = None;
+    let seq = {
+      let mut op = SendOp { chan: &*self.chan, value: &mut value, result: &mut result, seq: 0 };
+      wait::<P>(&mut [&mut op]);
+      op.seq
+    };
+    match result.unwrap() {
+      Ok(()) if self.chan.state.lock().unwrap().capacity == Some(0) => self.wait_received(seq),
+      result => result
+    }
+  }
+
+  /// Waits until the value with the sequence number `seq` has been received.
+  fn wait_received(&self, seq: u64) -> Result<(), SendError<T>> {
+    loop {
+      let waiter = {
+        let mut state = self.chan.state.lock().unwrap();
+        let index = match state.queue.iter().position(|&(other, _)| other == seq) {
+          Some(index) => index,
+          None => return Ok(())
+        };
+        if state.receivers == 0 {
+          let (_, value) = state.queue.remove(index).unwrap();
+          return Err(SendError(value))
+        }
+        let waiter = Waiter::new::<P>();
+        state.send_waiters.push(waiter.clone());
+        waiter
+      };
+      let abandon = OnUnwind(|| self.abandon(seq, &waiter));
+      waiter.wait::<P>();
+      mem::forget(abandon);
+      unregister(&mut self.chan.state.lock().unwrap().send_waiters, &waiter)
+    }
+  }
+
+  /// Takes back the value with the sequence number `seq` if it has not been received,
+  /// since the `send()` call that queued it is being unwound.
+  fn abandon(&self, seq: u64, waiter: &Arc<Waiter<P::Unparker>>) {
+    let value = {
+      let mut state = self.chan.state.lock().unwrap();
+      unregister(&mut state.send_waiters, waiter);
+      let value = state.queue.iter().position(|&(other, _)| other == seq)
+        .and_then(|index| state.queue.remove(index));
+      // The value took up the room made by a waiting receiver.
+      if value.is_some() { wake(&mut state.send_waiters) }
+      value
+    };
+    // The value is dropped without holding the lock, since its destructor may use the channel.
+    drop(value)
+  }
+
+  /// Sends `value` if the channel has room for it.
+  pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+    self.chan.state.lock().unwrap().try_send(value).map(|_| ())
+  }
+
+  /// Returns the number of values in the channel.
+  pub fn len(&self) -> usize {
+    self.chan.state.lock().unwrap().queue.len()
+  }
+
+  /// Returns whether the channel holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<T, P: Park> Clone for Sender<T, P> {
+  fn clone(&self) -> Sender<T, P> {
+    self.chan.state.lock().unwrap().senders += 1;
+    Sender { chan: self.chan.clone(), phantom: PhantomData }
+  }
+}
+
+impl<T, P: Park> Drop for Sender<T, P> {
+  fn drop(&mut self) {
+    let mut state = self.chan.state.lock().unwrap();
+    state.senders -= 1;
+    if state.senders == 0 { wake(&mut state.recv_waiters) }
+  }
+}
+
+impl<T, P: Park> fmt::Debug for Sender<T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("Sender { .. }")
+  }
+}
+
+impl<T, P: Park> Receiver<T, P> {
+  /// Receives a value, suspending the current fiber while the channel is empty.
+  ///
+  /// Fails once the channel is empty and every sender has been dropped.
+  pub fn recv(&self) -> Result<T, RecvError> {
+    let mut result = None;
+    wait::<P>(&mut [&mut RecvOp { chan: &*self.chan, result: &mut result }]);
+    result.unwrap()
+  }
+
+  /// Receives a value if the channel holds one.
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    self.chan.state.lock().unwrap().try_recv()
+  }
+
+  /// Returns an iterator that receives values until every sender has been dropped.
+  pub fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+    Iter { receiver: self }
+  }
+
+  /// Returns an iterator over the values the channel holds, which does not wait.
+  pub fn try_iter<'a>(&'a self) -> TryIter<'a, T, P> {
+    TryIter { receiver: self }
+  }
+
+  /// Returns the number of values in the channel.
+  pub fn len(&self) -> usize {
+    self.chan.state.lock().unwrap().queue.len()
+  }
+
+  /// Returns whether the channel holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<T, P: Park> Clone for Receiver<T, P> {
+  fn clone(&self) -> Receiver<T, P> {
+    self.chan.state.lock().unwrap().receivers += 1;
+    Receiver { chan: self.chan.clone(), phantom: PhantomData }
+  }
+}
+
+impl<T, P: Park> Drop for Receiver<T, P> {
+  fn drop(&mut self) {
+    let mut state = self.chan.state.lock().unwrap();
+    state.receivers -= 1;
+    if state.receivers == 0 { wake(&mut state.send_waiters) }
+  }
+}
+
+impl<T, P: Park> fmt::Debug for Receiver<T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("Receiver { .. }")
+  }
+}
+
+/// An iterator that receives values until every sender has been dropped.
+#[derive(Debug)]
+pub struct Iter<'a, T: 'a, P: Park + 'a = DefaultPark> {
+  receiver: &'a Receiver<T, P>
+}
+
+impl<'a, T, P: Park> Iterator for Iter<'a, T, P> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> { self.receiver.recv().ok() }
+}
+
+/// An iterator over the values a channel holds.
+#[derive(Debug)]
+pub struct TryIter<'a, T: 'a, P: Park + 'a = DefaultPark> {
+  receiver: &'a Receiver<T, P>
+}
+
+impl<'a, T, P: Park> Iterator for TryIter<'a, T, P> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> { self.receiver.try_recv().ok() }
+}
+
+/// An iterator that receives values until every sender has been dropped.
+#[derive(Debug)]
+pub struct IntoIter<T, P: Park = DefaultPark> {
+  receiver: Receiver<T, P>
+}
+
+impl<T, P: Park> Iterator for IntoIter<T, P> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> { self.receiver.recv().ok() }
+}
+
+impl<'a, T, P: Park> IntoIterator for &'a Receiver<T, P> {
+  type Item = T;
+  type IntoIter = Iter<'a, T, P>;
+  fn into_iter(self) -> Iter<'a, T, P> { self.iter() }
+}
+
+impl<T, P: Park> IntoIterator for Receiver<T, P> {
+  type Item = T;
+  type IntoIter = IntoIter<T, P>;
+  fn into_iter(self) -> IntoIter<T, P> { IntoIter { receiver: self } }
+}
+
+/// An operation on a channel that `wait()` can wait for.
+trait Operation<U> {
+  /// Tries to complete the operation without waiting, and returns whether it has.
+  fn try_complete(&mut self) -> bool;
+  /// Makes `waiter` be notified once the operation may be able to complete.
+  fn register(&mut self, waiter: &Arc<Waiter<U>>);
+  fn unregister(&mut self, waiter: &Arc<Waiter<U>>);
+}
+
+struct SendOp<'a, T: 'a, U: 'a> {
+  chan:   &'a Chan<T, U>,
+  value:  &'a mut Option<T>,
+  result: &'a mut Option<Result<(), SendError<T>>>,
+  seq:    u64
+}
+
+impl<'a, T, U: super::Unpark> Operation<U> for SendOp<'a, T, U> {
+  fn try_complete(&mut self) -> bool {
+    let value = self.value.take().expect("send operation completed twice");
+    match self.chan.state.lock().unwrap().try_send(value) {
+      Ok(seq) => { self.seq = seq; *self.result = Some(Ok(())); true }
+      Err(TrySendError::Disconnected(value)) => { *self.result = Some(Err(SendError(value))); true }
+      Err(TrySendError::Full(value)) => { *self.value = Some(value); false }
+    }
+  }
+
+  fn register(&mut self, waiter: &Arc<Waiter<U>>) {
+    self.chan.state.lock().unwrap().send_waiters.push(waiter.clone())
+  }
+
+  fn unregister(&mut self, waiter: &Arc<Waiter<U>>) {
+    unregister(&mut self.chan.state.lock().unwrap().send_waiters, waiter)
+  }
+}
+
+struct RecvOp<'a, T: 'a, U: 'a> {
+  chan:   &'a Chan<T, U>,
+  result: &'a mut Option<Result<T, RecvError>>
+}
+
+impl<'a, T, U: super::Unpark> Operation<U> for RecvOp<'a, T, U> {
+  fn try_complete(&mut self) -> bool {
+    match self.chan.state.lock().unwrap().try_recv() {
+      Ok(value) => { *self.result = Some(Ok(value)); true }
+      Err(TryRecvError::Disconnected) => { *self.result = Some(Err(RecvError)); true }
+      Err(TryRecvError::Empty) => false
+    }
+  }
+
+  fn register(&mut self, waiter: &Arc<Waiter<U>>) {
+    let mut state = self.chan.state.lock().unwrap();
+    state.recv_waiters.push(waiter.clone());
+    // A waiting receiver makes room in a rendezvous channel.
+    state.receiving += 1;
+    wake(&mut state.send_waiters)
+  }
+
+  fn unregister(&mut self, waiter: &Arc<Waiter<U>>) {
+    let mut state = self.chan.state.lock().unwrap();
+    unregister(&mut state.recv_waiters, waiter);
+    state.receiving -= 1;
+  }
+}
+
+/// Waits until one of `operations` completes, trying them in order, and returns its index.
+fn wait<'a, P: Park>(operations: &mut [&mut (Operation<P::Unparker> + 'a)]) -> usize {
+  loop {
+    if let Some(index) = operations.iter_mut().position(|op| op.try_complete()) { return index }
+    if operations.is_empty() { P::park(); continue }
+
+    let waiter = Waiter::new::<P>();
+    for op in operations.iter_mut() { op.register(&waiter) }
+    // Whatever happens after registering notifies the waiter, so check once more.
+    let completed = operations.iter_mut().position(|op| op.try_complete());
+    if completed.is_none() {
+      let abandon = OnUnwind(|| for op in operations.iter_mut() { op.unregister(&waiter) });
+      waiter.wait::<P>();
+      mem::forget(abandon);
+    }
+    for op in operations.iter_mut() { op.unregister(&waiter) }
+    if let Some(index) = completed { return index }
+  }
+}
+
+/// Waits for the first of several channel operations to complete. This is what
+/// the [select!](../../macro.select.html) macro expands to.
+///
+/// The operations are tried in the order they were added in.
+pub struct Select<'a, P: Park = DefaultPark> {
+  operations: Vec<Box<Operation<P::Unparker> + 'a>>
+}
+
+impl<'a, P: Park + 'a> Select<'a, P> {
+  /// Creates a selection without operations.
+  pub fn new() -> Select<'a, P> {
+    Select { operations: Vec::new() }
+  }
+
+  /// Adds an operation that receives from `receiver`, and stores the result in `result`.
+  /// Returns the index of the operation.
+  pub fn recv<T>(&mut self, receiver: &'a Receiver<T, P>, result: &'a mut Option<Result<T, RecvError>>) -> usize {
+    self.operations.push(Box::new(RecvOp { chan: &*receiver.chan, result: result }));
+    self.operations.len() - 1
+  }
+
+  /// Adds an operation that sends the value in `value` on `sender`, and stores the result
+  /// in `result`. Returns the index of the operation.
+  ///
+  /// Unlike `Sender::send()`, the operation completes as soon as a rendezvous channel
+  /// takes the value, without waiting for the receiver to take it out.
+  pub fn send<T>(&mut self, sender: &'a Sender<T, P>, value: &'a mut Option<T>,
+                 result: &'a mut Option<Result<(), SendError<T>>>) -> usize {
+    assert!(value.is_some(), "nothing to send");
+    self.operations.push(Box::new(SendOp { chan: &*sender.chan, value: value, result: result, seq: 0 }));
+    self.operations.len() - 1
+  }
+
+  /// Waits until one of the operations completes, and returns its index.
+  pub fn wait(&mut self) -> usize {
+    let mut operations = self.operations.iter_mut().map(|op| &mut **op).collect::<Vec<_>>();
+    wait::<P>(&mut operations)
+  }
+
+  /// Completes one of the operations if that is possible without waiting,
+  /// and returns its index.
+  pub fn try_wait(&mut self) -> Option<usize> {
+    self.operations.iter_mut().position(|op| op.try_complete())
+  }
+}
+
+impl<'a, P: Park> fmt::Debug for Select<'a, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Select").field("operations", &self.operations.len()).finish()
+  }
+}
+
+/// Waits for the first of several channel operations to complete, and runs
+/// the code of its arm.
+///
+/// Every arm is `recv(receiver) -> result => expression` or
+/// `send(sender, value) -> result => expression`, and at most one can be
+/// `default => expression`, which runs if no operation can complete without waiting.
+/// The values of all `send` arms are evaluated first, and the ones that are not sent
+/// are dropped. Arms are separated by commas.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate fringe;
+///
+/// use fringe::rt;
+/// use fringe::sync::channel;
+///
+/// # fn main() {
+/// rt::run(|| {
+///   let (tx1, rx1) = channel::unbounded();
+///   let (tx2, rx2) = channel::unbounded::<&str>();
+///   tx1.send(1).unwrap();
+///   let value = select! {
+///     recv(rx1) -> value => value.unwrap(),
+///     recv(rx2) -> _ => unreachable!(),
+///   };
+///   assert_eq!(value, 1);
+///   drop(tx2);
+///   select! {
+///     recv(rx1) -> _ => panic!("no value was sent"),
+///     recv(rx2) -> result => assert!(result.is_err()),
+///   }
+/// });
+/// # }
+/// ```
+#[macro_export]
+macro_rules! select {
+  ($($tokens:tt)*) => {
+    __fringe_select!(@parse [] [] $($tokens)*)
+  };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __fringe_select {
+  (@parse [$($arms:tt)*] [$($default:tt)*] recv($rx:expr) -> $res:pat => $body:expr, $($rest:tt)*) => {
+    __fringe_select!(@parse [$($arms)* (recv slot ($rx) ($res) ($body))] [$($default)*] $($rest)*)
+  };
+  (@parse [$($arms:tt)*] [$($default:tt)*] recv($rx:expr) -> $res:pat => $body:expr) => {
+    __fringe_select!(@parse [$($arms)* (recv slot ($rx) ($res) ($body))] [$($default)*])
+  };
+  (@parse [$($arms:tt)*] [$($default:tt)*] send($tx:expr, $value:expr) -> $res:pat => $body:expr, $($rest:tt)*) => {
+    __fringe_select!(@parse [$($arms)* (send slot ($tx, $value) ($res) ($body))] [$($default)*] $($rest)*)
+  };
+  (@parse [$($arms:tt)*] [$($default:tt)*] send($tx:expr, $value:expr) -> $res:pat => $body:expr) => {
+    __fringe_select!(@parse [$($arms)* (send slot ($tx, $value) ($res) ($body))] [$($default)*])
+  };
+  (@parse [$($arms:tt)*] [] default => $body:expr, $($rest:tt)*) => {
+    __fringe_select!(@parse [$($arms)*] [($body)] $($rest)*)
+  };
+  (@parse [$($arms:tt)*] [] default => $body:expr) => {
+    __fringe_select!(@parse [$($arms)*] [($body)])
+  };
+  (@parse [$($arms:tt)*] [$($default:tt)*]) => {{
+    $(__fringe_select!(@declare $arms);)*
+    let completed = {
+      let mut select = $crate::sync::channel::Select::new();
+      $(__fringe_select!(@add select $arms);)*
+      __fringe_select!(@wait select [$($default)*])
+    };
+    __fringe_select!(@dispatch completed [$($arms)*] [$($default)*])
+  }};
+
+  (@declare (recv $slot:ident ($rx:expr) $res:tt $body:tt)) => {
+    let mut $slot = None;
+  };
+  (@declare (send $slot:ident ($tx:expr, $value:expr) $res:tt $body:tt)) => {
+    let mut $slot = (Some($value), None);
+  };
+
+  (@add $select:ident (recv $slot:ident ($rx:expr) $res:tt $body:tt)) => {
+    $select.recv(&$rx, &mut $slot);
+  };
+  (@add $select:ident (send $slot:ident ($tx:expr, $value:expr) $res:tt $body:tt)) => {
+    $select.send(&$tx, &mut $slot.0, &mut $slot.1);
+  };
+
+  (@wait $select:ident []) => { Some($select.wait()) };
+  (@wait $select:ident [$default:tt]) => { $select.try_wait() };
+
+  (@dispatch $completed:ident [] []) => { unreachable!() };
+  (@dispatch $completed:ident [] [($default:expr)]) => { $default };
+  (@dispatch $completed:ident [(recv $slot:ident $rx:tt ($res:pat) ($body:expr)) $($rest:tt)*] $default:tt) => {
+    match $slot.take() {
+      Some($res) => $body,
+      None => __fringe_select!(@dispatch $completed [$($rest)*] $default)
+    }
+  };
+  (@dispatch $completed:ident [(send $slot:ident $args:tt ($res:pat) ($body:expr)) $($rest:tt)*] $default:tt) => {
+    match $slot.1.take() {
+      Some($res) => $body,
+      None => __fringe_select!(@dispatch $completed [$($rest)*] $default)
+    }
+  };
+}
+
+/// The error returned by `Sender::send()` if every receiver has been dropped.
+/// It holds the value that could not be sent.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// The error returned by `Sender::try_send()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+  /// The channel is full.
+  Full(T),
+  /// Every receiver has been dropped.
+  Disconnected(T)
+}
+
+/// The error returned by `Receiver::recv()` once the channel is empty, and every
+/// sender has been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// The error returned by `Receiver::try_recv()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+  /// The channel is empty.
+  Empty,
+  /// The channel is empty, and every sender has been dropped.
+  Disconnected
+}
+
+impl<T> fmt::Debug for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("SendError { .. }")
+  }
+}
+
+impl<T> fmt::Display for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("sending on a disconnected channel")
+  }
+}
+
+impl<T: Send> Error for SendError<T> {
+  fn description(&self) -> &str { "sending on a disconnected channel" }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      TrySendError::Full(_) => f.write_str("Full(..)"),
+      TrySendError::Disconnected(_) => f.write_str("Disconnected(..)")
+    }
+  }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      TrySendError::Full(_) => f.write_str("sending on a full channel"),
+      TrySendError::Disconnected(_) => f.write_str("sending on a disconnected channel")
+    }
+  }
+}
+
+impl<T: Send> Error for TrySendError<T> {
+  fn description(&self) -> &str {
+    match *self {
+      TrySendError::Full(_) => "sending on a full channel",
+      TrySendError::Disconnected(_) => "sending on a disconnected channel"
+    }
+  }
+}
+
+impl fmt::Display for RecvError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("receiving on a disconnected channel")
+  }
+}
+
+impl Error for RecvError {
+  fn description(&self) -> &str { "receiving on a disconnected channel" }
+}
+
+impl fmt::Display for TryRecvError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+      TryRecvError::Disconnected => f.write_str("receiving on a disconnected channel")
+    }
+  }
+}
+
+impl Error for TryRecvError {
+  fn description(&self) -> &str {
+    match *self {
+      TryRecvError::Empty => "receiving on an empty channel",
+      TryRecvError::Disconnected => "receiving on a disconnected channel"
+    }
+  }
+}
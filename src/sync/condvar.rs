@@ -0,0 +1,84 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::marker::PhantomData;
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use super::{Park, DefaultPark, Waiter, OnUnwind, MutexGuard, remove};
+
+/// A condition variable, which suspends the current fiber while it waits.
+pub struct Condvar<P: Park = DefaultPark> {
+  waiters: StdMutex<VecDeque<Arc<Waiter<P::Unparker>>>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+impl Condvar {
+  /// Creates a condition variable that uses `DefaultPark`.
+  pub fn new() -> Condvar {
+    Condvar::with_park()
+  }
+}
+
+impl<P: Park> Condvar<P> {
+  /// Creates a condition variable that uses `P`.
+  pub fn with_park() -> Condvar<P> {
+    Condvar { waiters: StdMutex::new(VecDeque::new()), phantom: PhantomData }
+  }
+
+  /// Releases the lock held by `guard`, suspends the current fiber until it is notified,
+  /// and acquires the lock again.
+  ///
+  /// The fiber may also be woken spuriously, so `wait()` should be called in a loop
+  /// that checks whether the condition holds.
+  pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T, P>) -> MutexGuard<'a, T, P> {
+    let mutex = guard.mutex;
+    let waiter = Waiter::new::<P>();
+    // Queue up while holding the lock, so that a notification cannot be missed.
+    self.waiters.lock().unwrap().push_back(waiter.clone());
+    drop(guard);
+
+    let abandon = OnUnwind(|| {
+      let removed = remove(&mut self.waiters.lock().unwrap(), &waiter);
+      // Pass the notification on to another waiter.
+      if !removed { self.notify_one() }
+    });
+    waiter.wait::<P>();
+    mem::forget(abandon);
+    mutex.lock()
+  }
+
+  /// Calls `wait()` until `condition` returns `false`.
+  pub fn wait_while<'a, T, F>(&self, mut guard: MutexGuard<'a, T, P>, mut condition: F) -> MutexGuard<'a, T, P>
+      where F: FnMut(&mut T) -> bool {
+    while condition(&mut *guard) { guard = self.wait(guard) }
+    guard
+  }
+
+  /// Wakes the fiber that has been waiting the longest, if any.
+  pub fn notify_one(&self) {
+    let waiter = self.waiters.lock().unwrap().pop_front();
+    if let Some(waiter) = waiter { waiter.notify() }
+  }
+
+  /// Wakes every waiting fiber.
+  pub fn notify_all(&self) {
+    let waiters = mem::replace(&mut *self.waiters.lock().unwrap(), VecDeque::new());
+    for waiter in waiters { waiter.notify() }
+  }
+}
+
+impl Default for Condvar {
+  fn default() -> Condvar { Condvar::new() }
+}
+
+impl<P: Park> fmt::Debug for Condvar<P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Condvar").field("waiters", &self.waiters.lock().unwrap().len()).finish()
+  }
+}
@@ -0,0 +1,225 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Synchronization primitives that suspend the current fiber instead of blocking
+//! the thread.
+//!
+//! The primitives in this module wait using the [Park](trait.Park.html) trait,
+//! which suspends the current fiber, and wake waiters using the [Unpark](trait.Unpark.html)
+//! handles it provides. By default, they use [DefaultPark](struct.DefaultPark.html),
+//! which parks tasks of the runtimes in [rt](../rt/index.html), and blocks the thread
+//! outside of a task. Any other driver loop built around `Generator::resume` can be used
+//! by implementing `Park` for it, and creating the primitives using `with_park()`.
+//!
+//! Waiters are served in the order they arrive in, and whatever a waiter is waiting for
+//! is handed over to it directly, so that a fiber that keeps releasing and reacquiring
+//! a lock cannot starve the others. A waiter that is unwound, e.g. by `rt::timeout()`
+//! or because its generator is dropped, gives up its place.
+//!
+//! Values can be passed between fibers using [channels](channel/index.html).
+//!
+//! # Example
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::ptr;
+//! use fringe::{Generator, OsStack};
+//! use fringe::generator::{GeneratorState, Yielder};
+//! use fringe::sync::{Mutex, Park, Unpark};
+//!
+//! // A driver that resumes every generator in turn. A parked generator simply
+//! // suspends, and waking it up needs no work, since it is resumed anyway.
+//! thread_local!(static YIELDER: Cell<*const Yielder<(), ()>> = Cell::new(ptr::null()));
+//!
+//! struct RoundRobin;
+//! struct Nothing;
+//!
+//! impl Unpark for Nothing {
+//!   fn unpark(&self) {}
+//! }
+//!
+//! impl Park for RoundRobin {
+//!   type Unparker = Nothing;
+//!   fn unparker() -> Nothing { Nothing }
+//!   fn park() {
+//!     let yielder = YIELDER.with(|cell| cell.get());
+//!     unsafe { (*yielder).suspend(()) };
+//!     YIELDER.with(|cell| cell.set(yielder))
+//!   }
+//! }
+//!
+//! let log = Mutex::<_, RoundRobin>::with_park(Vec::new());
+//! let mut generators = (0..3).map(|i| {
+//!   let log = &log;
+//!   Generator::new(OsStack::new(1 << 16).unwrap(), move |yielder, ()| {
+//!     YIELDER.with(|cell| cell.set(yielder));
+//!     let mut guard = log.lock();
+//!     guard.push(i);
+//!     RoundRobin::park();
+//!     guard.push(i);
+//!   })
+//! }).collect::<Vec<Generator<(), (), OsStack>>>();
+//!
+//! let mut running = generators.len();
+//! while running > 0 {
+//!   running = 0;
+//!   for generator in &mut generators {
+//!     if let Some(GeneratorState::Yielded(())) = generator.resume(()) { running += 1 }
+//!   }
+//! }
+//! drop(generators);
+//! assert_eq!(log.into_inner(), [0, 0, 1, 1, 2, 2]);
+//! ```
+extern crate std;
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use self::std::collections::VecDeque;
+use self::std::sync::Arc;
+use self::std::thread;
+#[cfg(unix)]
+use rt;
+
+pub use self::mutex::{Mutex, MutexGuard};
+pub use self::condvar::Condvar;
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::{Semaphore, SemaphorePermit};
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::once_cell::OnceCell;
+
+mod mutex;
+mod condvar;
+mod rwlock;
+mod semaphore;
+mod barrier;
+mod once_cell;
+
+pub mod channel;
+
+/// A way to suspend the current fiber until it is woken up.
+pub trait Park {
+  /// The handle that wakes a parked fiber.
+  type Unparker: Unpark;
+
+  /// Returns a handle that wakes the current fiber.
+  fn unparker() -> Self::Unparker;
+
+  /// Suspends the current fiber until its handle is used, or returns immediately
+  /// if it has been used since the fiber last parked. The fiber may also be woken
+  /// spuriously; the primitives call `park()` in a loop.
+  fn park();
+}
+
+/// A handle that wakes a fiber suspended in `Park::park()`.
+pub trait Unpark {
+  /// Wakes the fiber, or makes its next call to `Park::park()` return immediately.
+  fn unpark(&self);
+}
+
+/// Parks the current task of a single-threaded runtime, executor or simulation
+/// from [rt](../rt/index.html), or the current thread outside of a task.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultPark;
+
+/// The handle that wakes a task or thread parked using `DefaultPark`.
+#[derive(Debug, Clone)]
+pub struct DefaultUnparker(Unparker);
+
+#[derive(Debug, Clone)]
+enum Unparker {
+  Thread(thread::Thread),
+  #[cfg(unix)]
+  Runtime(rt::Task),
+  #[cfg(unix)]
+  Executor(rt::mt::Task),
+  #[cfg(unix)]
+  Simulation(rt::sim::Task)
+}
+
+impl Park for DefaultPark {
+  type Unparker = DefaultUnparker;
+
+  fn unparker() -> DefaultUnparker {
+    #[cfg(unix)]
+    {
+      if let Some(task) = rt::sim::try_current() { return DefaultUnparker(Unparker::Simulation(task)) }
+      if let Some(task) = rt::try_current() { return DefaultUnparker(Unparker::Runtime(task)) }
+      if let Some(task) = rt::mt::try_current() { return DefaultUnparker(Unparker::Executor(task)) }
+    }
+    DefaultUnparker(Unparker::Thread(thread::current()))
+  }
+
+  fn park() {
+    #[cfg(unix)]
+    {
+      if rt::sim::try_current().is_some() { return rt::sim::park() }
+      if rt::try_current().is_some() { return rt::park() }
+      if rt::mt::try_current().is_some() { return rt::mt::park() }
+    }
+    thread::park()
+  }
+}
+
+impl Unpark for DefaultUnparker {
+  fn unpark(&self) {
+    match self.0 {
+      Unparker::Thread(ref thread) => thread.unpark(),
+      #[cfg(unix)]
+      Unparker::Runtime(ref task) => task.unpark(),
+      #[cfg(unix)]
+      Unparker::Executor(ref task) => task.unpark(),
+      #[cfg(unix)]
+      Unparker::Simulation(ref task) => task.unpark()
+    }
+  }
+}
+
+/// A fiber waiting in the queue of a primitive.
+struct Waiter<U> {
+  unparker: U,
+  notified: AtomicBool
+}
+
+impl<U: Unpark> Waiter<U> {
+  fn new<P: Park<Unparker = U>>() -> Arc<Waiter<U>> {
+    Arc::new(Waiter { unparker: P::unparker(), notified: AtomicBool::new(false) })
+  }
+
+  /// Wakes the waiter. Whatever it waits for must be handed over before this is called.
+  fn notify(&self) {
+    self.notified.store(true, Ordering::Release);
+    self.unparker.unpark()
+  }
+
+  /// Parks the current fiber until the waiter is notified.
+  fn wait<P: Park<Unparker = U>>(&self) {
+    while !self.notified.load(Ordering::Acquire) { P::park() }
+  }
+}
+
+impl<U> fmt::Debug for Waiter<U> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Waiter").field("notified", &self.notified.load(Ordering::Relaxed)).finish()
+  }
+}
+
+/// Removes `waiter` from `queue`, and returns `false` if it is not there, because
+/// it has been notified.
+fn remove<U>(queue: &mut VecDeque<Arc<Waiter<U>>>, waiter: &Arc<Waiter<U>>) -> bool {
+  match queue.iter().position(|other| Arc::ptr_eq(other, waiter)) {
+    Some(index) => { queue.remove(index); true }
+    None => false
+  }
+}
+
+/// Runs a closure when dropped, unless it is forgotten. Waiters use it to give up
+/// their place in a queue when they are unwound.
+struct OnUnwind<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnUnwind<F> {
+  fn drop(&mut self) { (self.0)() }
+}
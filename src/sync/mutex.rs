@@ -0,0 +1,143 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use super::{Park, DefaultPark, Waiter, OnUnwind, remove};
+
+/// A mutual exclusion lock, which suspends the current fiber while it waits.
+///
+/// Unlike `std::sync::Mutex`, this mutex is not poisoned when a fiber panics
+/// while holding it.
+pub struct Mutex<T, P: Park = DefaultPark> {
+  state:   StdMutex<State<P::Unparker>>,
+  data:    UnsafeCell<T>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct State<U> {
+  locked:  bool,
+  waiters: VecDeque<Arc<Waiter<U>>>
+}
+
+unsafe impl<T: Send, P: Park> Sync for Mutex<T, P> where P::Unparker: Send + Sync {}
+
+/// A guard that releases the lock of a `Mutex` when dropped, and gives access
+/// to the data it protects.
+pub struct MutexGuard<'a, T: 'a, P: Park + 'a = DefaultPark> {
+  pub(crate) mutex: &'a Mutex<T, P>
+}
+
+unsafe impl<'a, T: Sync, P: Park> Sync for MutexGuard<'a, T, P> where P::Unparker: Send + Sync {}
+
+impl<T> Mutex<T> {
+  /// Creates an unlocked mutex that uses `DefaultPark`.
+  pub fn new(value: T) -> Mutex<T> {
+    Mutex::with_park(value)
+  }
+}
+
+impl<T, P: Park> Mutex<T, P> {
+  /// Creates an unlocked mutex that uses `P`.
+  pub fn with_park(value: T) -> Mutex<T, P> {
+    Mutex {
+      state:   StdMutex::new(State { locked: false, waiters: VecDeque::new() }),
+      data:    UnsafeCell::new(value),
+      phantom: PhantomData
+    }
+  }
+
+  /// Acquires the lock, suspending the current fiber until it is available.
+  pub fn lock<'a>(&'a self) -> MutexGuard<'a, T, P> {
+    let waiter = {
+      let mut state = self.state.lock().unwrap();
+      if !state.locked {
+        state.locked = true;
+        return MutexGuard { mutex: self }
+      }
+      let waiter = Waiter::new::<P>();
+      state.waiters.push_back(waiter.clone());
+      waiter
+    };
+
+    let abandon = OnUnwind(|| {
+      let removed = remove(&mut self.state.lock().unwrap().waiters, &waiter);
+      // The lock has been handed over already.
+      if !removed { self.unlock() }
+    });
+    waiter.wait::<P>();
+    mem::forget(abandon);
+    MutexGuard { mutex: self }
+  }
+
+  /// Acquires the lock if it is available.
+  pub fn try_lock<'a>(&'a self) -> Option<MutexGuard<'a, T, P>> {
+    let mut state = self.state.lock().unwrap();
+    if state.locked { return None }
+    state.locked = true;
+    Some(MutexGuard { mutex: self })
+  }
+
+  /// Returns a mutable reference to the data; no locking is needed, since the mutex
+  /// is borrowed mutably.
+  pub fn get_mut(&mut self) -> &mut T {
+    unsafe { &mut *self.data.get() }
+  }
+
+  /// Consumes the mutex, and returns the data.
+  pub fn into_inner(self) -> T {
+    self.data.into_inner()
+  }
+
+  /// Hands the lock over to the first waiter, or releases it.
+  fn unlock(&self) {
+    let next = {
+      let mut state = self.state.lock().unwrap();
+      let next = state.waiters.pop_front();
+      if next.is_none() { state.locked = false }
+      next
+    };
+    if let Some(waiter) = next { waiter.notify() }
+  }
+}
+
+impl<T: Default> Default for Mutex<T> {
+  fn default() -> Mutex<T> { Mutex::new(T::default()) }
+}
+
+impl<T: fmt::Debug, P: Park> fmt::Debug for Mutex<T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.try_lock() {
+      Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+      None => f.write_str("Mutex { <locked> }")
+    }
+  }
+}
+
+impl<'a, T, P: Park> Deref for MutexGuard<'a, T, P> {
+  type Target = T;
+  fn deref(&self) -> &T { unsafe { &*self.mutex.data.get() } }
+}
+
+impl<'a, T, P: Park> DerefMut for MutexGuard<'a, T, P> {
+  fn deref_mut(&mut self) -> &mut T { unsafe { &mut *self.mutex.data.get() } }
+}
+
+impl<'a, T, P: Park> Drop for MutexGuard<'a, T, P> {
+  fn drop(&mut self) { self.mutex.unlock() }
+}
+
+impl<'a, T: fmt::Debug, P: Park> fmt::Debug for MutexGuard<'a, T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&**self, f)
+  }
+}
@@ -0,0 +1,143 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+use self::std::sync::{Arc, Mutex as StdMutex};
+use self::std::vec::Vec;
+use super::{Park, DefaultPark, Waiter, OnUnwind};
+
+/// A cell that is written to once. While one fiber initializes it, the others
+/// wanting its value are suspended.
+pub struct OnceCell<T, P: Park = DefaultPark> {
+  done:    AtomicBool,
+  state:   StdMutex<State<P::Unparker>>,
+  value:   UnsafeCell<Option<T>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct State<U> {
+  running: bool,
+  waiters: Vec<Arc<Waiter<U>>>
+}
+
+unsafe impl<T: Send + Sync, P: Park> Sync for OnceCell<T, P> where P::Unparker: Send + Sync {}
+
+impl<T> OnceCell<T> {
+  /// Creates an empty cell that uses `DefaultPark`.
+  pub fn new() -> OnceCell<T> {
+    OnceCell::with_park()
+  }
+}
+
+impl<T, P: Park> OnceCell<T, P> {
+  /// Creates an empty cell that uses `P`.
+  pub fn with_park() -> OnceCell<T, P> {
+    OnceCell {
+      done:    AtomicBool::new(false),
+      state:   StdMutex::new(State { running: false, waiters: Vec::new() }),
+      value:   UnsafeCell::new(None),
+      phantom: PhantomData
+    }
+  }
+
+  /// Returns the value, if the cell has been initialized.
+  pub fn get(&self) -> Option<&T> {
+    if self.done.load(Ordering::Acquire) {
+      unsafe { (*self.value.get()).as_ref() }
+    } else {
+      None
+    }
+  }
+
+  /// Initializes the cell with `value`, or returns it if the cell has been initialized.
+  /// If another fiber is initializing the cell, waits for it to finish first.
+  pub fn set(&self, value: T) -> Result<(), T> {
+    let mut value = Some(value);
+    self.get_or_init(|| value.take().unwrap());
+    match value {
+      None => Ok(()),
+      Some(value) => Err(value)
+    }
+  }
+
+  /// Returns the value, initializing the cell with the result of `f` if it is empty.
+  /// If another fiber is initializing the cell, waits for it to finish. If `f` panics,
+  /// the panic propagates, and the cell stays empty.
+  pub fn get_or_init<F>(&self, f: F) -> &T
+      where F: FnOnce() -> T {
+    let mut f = Some(f);
+    loop {
+      if let Some(value) = self.get() { return value }
+
+      let waiter = {
+        let mut state = self.state.lock().unwrap();
+        if self.done.load(Ordering::Acquire) { continue }
+        if !state.running {
+          state.running = true;
+          None
+        } else {
+          let waiter = Waiter::new::<P>();
+          state.waiters.push(waiter.clone());
+          Some(waiter)
+        }
+      };
+
+      match waiter {
+        Some(waiter) => {
+          let abandon = OnUnwind(|| {
+            self.state.lock().unwrap().waiters.retain(|other| !Arc::ptr_eq(other, &waiter))
+          });
+          waiter.wait::<P>();
+          mem::forget(abandon);
+        }
+        None => {
+          // If `f` unwinds, let the waiters try again.
+          let abandon = OnUnwind(|| self.finish());
+          let value = (f.take().unwrap())();
+          mem::forget(abandon);
+          unsafe { *self.value.get() = Some(value) };
+          self.done.store(true, Ordering::Release);
+          self.finish();
+        }
+      }
+    }
+  }
+
+  /// Ends an initialization, and wakes the fibers waiting for it.
+  fn finish(&self) {
+    let waiters = {
+      let mut state = self.state.lock().unwrap();
+      state.running = false;
+      mem::replace(&mut state.waiters, Vec::new())
+    };
+    for waiter in waiters { waiter.notify() }
+  }
+
+  /// Returns a mutable reference to the value, if the cell has been initialized.
+  pub fn get_mut(&mut self) -> Option<&mut T> {
+    unsafe { (*self.value.get()).as_mut() }
+  }
+
+  /// Consumes the cell, and returns the value, if it has been initialized.
+  pub fn into_inner(self) -> Option<T> {
+    self.value.into_inner()
+  }
+}
+
+impl<T> Default for OnceCell<T> {
+  fn default() -> OnceCell<T> { OnceCell::new() }
+}
+
+impl<T: fmt::Debug, P: Park> fmt::Debug for OnceCell<T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("OnceCell").field("value", &self.get()).finish()
+  }
+}
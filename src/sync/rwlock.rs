@@ -0,0 +1,212 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use self::std::vec::Vec;
+use super::{Park, DefaultPark, Waiter, OnUnwind};
+
+/// A reader-writer lock, which suspends the current fiber while it waits.
+///
+/// Once a writer waits, readers that arrive later queue up behind it,
+/// so that writers are not starved.
+pub struct RwLock<T, P: Park = DefaultPark> {
+  state:   StdMutex<State<P::Unparker>>,
+  data:    UnsafeCell<T>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct State<U> {
+  readers: usize,
+  writer:  bool,
+  /// The waiting fibers, and whether each of them wants to write.
+  waiters: VecDeque<(Arc<Waiter<U>>, bool)>
+}
+
+impl<U> State<U> {
+  /// Hands the lock over to the waiters at the front of the queue that can have it,
+  /// and returns them, so that they can be notified.
+  fn grant(&mut self) -> Vec<Arc<Waiter<U>>> {
+    let mut granted = Vec::new();
+    while let Some(&(_, write)) = self.waiters.front() {
+      if self.writer || (write && self.readers > 0) { break }
+      if write { self.writer = true } else { self.readers += 1 }
+      granted.push(self.waiters.pop_front().unwrap().0);
+    }
+    granted
+  }
+
+  /// Removes `waiter` from the queue, and returns `false` if it is not there,
+  /// because it has been granted the lock.
+  fn remove(&mut self, waiter: &Arc<Waiter<U>>) -> bool {
+    match self.waiters.iter().position(|&(ref other, _)| Arc::ptr_eq(other, waiter)) {
+      Some(index) => { self.waiters.remove(index); true }
+      None => false
+    }
+  }
+}
+
+unsafe impl<T: Send + Sync, P: Park> Sync for RwLock<T, P> where P::Unparker: Send + Sync {}
+
+/// A guard that releases the shared access to an `RwLock` when dropped.
+pub struct RwLockReadGuard<'a, T: 'a, P: Park + 'a = DefaultPark> {
+  lock: &'a RwLock<T, P>
+}
+
+/// A guard that releases the exclusive access to an `RwLock` when dropped.
+pub struct RwLockWriteGuard<'a, T: 'a, P: Park + 'a = DefaultPark> {
+  lock: &'a RwLock<T, P>
+}
+
+unsafe impl<'a, T: Sync, P: Park> Sync for RwLockWriteGuard<'a, T, P> where P::Unparker: Send + Sync {}
+
+impl<T> RwLock<T> {
+  /// Creates an unlocked reader-writer lock that uses `DefaultPark`.
+  pub fn new(value: T) -> RwLock<T> {
+    RwLock::with_park(value)
+  }
+}
+
+impl<T, P: Park> RwLock<T, P> {
+  /// Creates an unlocked reader-writer lock that uses `P`.
+  pub fn with_park(value: T) -> RwLock<T, P> {
+    RwLock {
+      state:   StdMutex::new(State { readers: 0, writer: false, waiters: VecDeque::new() }),
+      data:    UnsafeCell::new(value),
+      phantom: PhantomData
+    }
+  }
+
+  /// Acquires shared access, suspending the current fiber until it is available.
+  pub fn read<'a>(&'a self) -> RwLockReadGuard<'a, T, P> {
+    self.acquire(false);
+    RwLockReadGuard { lock: self }
+  }
+
+  /// Acquires exclusive access, suspending the current fiber until it is available.
+  pub fn write<'a>(&'a self) -> RwLockWriteGuard<'a, T, P> {
+    self.acquire(true);
+    RwLockWriteGuard { lock: self }
+  }
+
+  /// Acquires shared access if it is available without waiting.
+  pub fn try_read<'a>(&'a self) -> Option<RwLockReadGuard<'a, T, P>> {
+    let mut state = self.state.lock().unwrap();
+    if state.writer || !state.waiters.is_empty() { return None }
+    state.readers += 1;
+    Some(RwLockReadGuard { lock: self })
+  }
+
+  /// Acquires exclusive access if it is available without waiting.
+  pub fn try_write<'a>(&'a self) -> Option<RwLockWriteGuard<'a, T, P>> {
+    let mut state = self.state.lock().unwrap();
+    if state.writer || state.readers > 0 { return None }
+    state.writer = true;
+    Some(RwLockWriteGuard { lock: self })
+  }
+
+  /// Returns a mutable reference to the data; no locking is needed, since the lock
+  /// is borrowed mutably.
+  pub fn get_mut(&mut self) -> &mut T {
+    unsafe { &mut *self.data.get() }
+  }
+
+  /// Consumes the lock, and returns the data.
+  pub fn into_inner(self) -> T {
+    self.data.into_inner()
+  }
+
+  /// Acquires shared or exclusive access.
+  fn acquire(&self, write: bool) {
+    let waiter = {
+      let mut state = self.state.lock().unwrap();
+      let available = !state.writer && state.waiters.is_empty() && (!write || state.readers == 0);
+      if available {
+        if write { state.writer = true } else { state.readers += 1 }
+        return
+      }
+      let waiter = Waiter::new::<P>();
+      state.waiters.push_back((waiter.clone(), write));
+      waiter
+    };
+
+    let abandon = OnUnwind(|| {
+      let granted = {
+        let mut state = self.state.lock().unwrap();
+        // A waiter that is gone may have held back the ones behind it.
+        if state.remove(&waiter) { Some(state.grant()) } else { None }
+      };
+      match granted {
+        Some(granted) => for waiter in granted { waiter.notify() },
+        None => self.release(write)
+      }
+    });
+    waiter.wait::<P>();
+    mem::forget(abandon);
+  }
+
+  fn release(&self, write: bool) {
+    let granted = {
+      let mut state = self.state.lock().unwrap();
+      if write { state.writer = false } else { state.readers -= 1 }
+      state.grant()
+    };
+    for waiter in granted { waiter.notify() }
+  }
+}
+
+impl<T: Default> Default for RwLock<T> {
+  fn default() -> RwLock<T> { RwLock::new(T::default()) }
+}
+
+impl<T: fmt::Debug, P: Park> fmt::Debug for RwLock<T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.try_read() {
+      Some(guard) => f.debug_struct("RwLock").field("data", &&*guard).finish(),
+      None => f.write_str("RwLock { <locked> }")
+    }
+  }
+}
+
+impl<'a, T, P: Park> Deref for RwLockReadGuard<'a, T, P> {
+  type Target = T;
+  fn deref(&self) -> &T { unsafe { &*self.lock.data.get() } }
+}
+
+impl<'a, T, P: Park> Drop for RwLockReadGuard<'a, T, P> {
+  fn drop(&mut self) { self.lock.release(false) }
+}
+
+impl<'a, T: fmt::Debug, P: Park> fmt::Debug for RwLockReadGuard<'a, T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&**self, f)
+  }
+}
+
+impl<'a, T, P: Park> Deref for RwLockWriteGuard<'a, T, P> {
+  type Target = T;
+  fn deref(&self) -> &T { unsafe { &*self.lock.data.get() } }
+}
+
+impl<'a, T, P: Park> DerefMut for RwLockWriteGuard<'a, T, P> {
+  fn deref_mut(&mut self) -> &mut T { unsafe { &mut *self.lock.data.get() } }
+}
+
+impl<'a, T, P: Park> Drop for RwLockWriteGuard<'a, T, P> {
+  fn drop(&mut self) { self.lock.release(true) }
+}
+
+impl<'a, T: fmt::Debug, P: Park> fmt::Debug for RwLockWriteGuard<'a, T, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&**self, f)
+  }
+}
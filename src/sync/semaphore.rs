@@ -0,0 +1,123 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate std;
+
+use core::{fmt, mem};
+use core::marker::PhantomData;
+use self::std::collections::VecDeque;
+use self::std::sync::{Arc, Mutex as StdMutex};
+use self::std::vec::Vec;
+use super::{Park, DefaultPark, Waiter, OnUnwind, remove};
+
+/// A counting semaphore, which suspends the current fiber while it waits for a permit.
+pub struct Semaphore<P: Park = DefaultPark> {
+  state:   StdMutex<State<P::Unparker>>,
+  phantom: PhantomData<fn() -> P>
+}
+
+struct State<U> {
+  permits: usize,
+  waiters: VecDeque<Arc<Waiter<U>>>
+}
+
+/// A permit acquired from a `Semaphore`, which is returned to it when dropped.
+pub struct SemaphorePermit<'a, P: Park + 'a = DefaultPark> {
+  semaphore: &'a Semaphore<P>
+}
+
+impl Semaphore {
+  /// Creates a semaphore with `permits` permits that uses `DefaultPark`.
+  pub fn new(permits: usize) -> Semaphore {
+    Semaphore::with_park(permits)
+  }
+}
+
+impl<P: Park> Semaphore<P> {
+  /// Creates a semaphore with `permits` permits that uses `P`.
+  pub fn with_park(permits: usize) -> Semaphore<P> {
+    Semaphore {
+      state:   StdMutex::new(State { permits: permits, waiters: VecDeque::new() }),
+      phantom: PhantomData
+    }
+  }
+
+  /// Acquires a permit, suspending the current fiber until one is available.
+  pub fn acquire<'a>(&'a self) -> SemaphorePermit<'a, P> {
+    let waiter = {
+      let mut state = self.state.lock().unwrap();
+      if state.permits > 0 && state.waiters.is_empty() {
+        state.permits -= 1;
+        return SemaphorePermit { semaphore: self }
+      }
+      let waiter = Waiter::new::<P>();
+      state.waiters.push_back(waiter.clone());
+      waiter
+    };
+
+    let abandon = OnUnwind(|| {
+      let removed = remove(&mut self.state.lock().unwrap().waiters, &waiter);
+      // A permit has been handed over already.
+      if !removed { self.add_permits(1) }
+    });
+    waiter.wait::<P>();
+    mem::forget(abandon);
+    SemaphorePermit { semaphore: self }
+  }
+
+  /// Acquires a permit if one is available without waiting.
+  pub fn try_acquire<'a>(&'a self) -> Option<SemaphorePermit<'a, P>> {
+    let mut state = self.state.lock().unwrap();
+    if state.permits == 0 || !state.waiters.is_empty() { return None }
+    state.permits -= 1;
+    Some(SemaphorePermit { semaphore: self })
+  }
+
+  /// Adds `count` permits, handing them over to waiting fibers first.
+  pub fn add_permits(&self, count: usize) {
+    let woken = {
+      let mut state = self.state.lock().unwrap();
+      state.permits += count;
+      let mut woken = Vec::new();
+      while state.permits > 0 {
+        match state.waiters.pop_front() {
+          Some(waiter) => { state.permits -= 1; woken.push(waiter) }
+          None => break
+        }
+      }
+      woken
+    };
+    for waiter in woken { waiter.notify() }
+  }
+
+  /// Returns the number of permits that are available.
+  pub fn available_permits(&self) -> usize {
+    self.state.lock().unwrap().permits
+  }
+}
+
+impl<P: Park> fmt::Debug for Semaphore<P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Semaphore").field("permits", &self.available_permits()).finish()
+  }
+}
+
+impl<'a, P: Park> SemaphorePermit<'a, P> {
+  /// Consumes the permit without returning it to the semaphore.
+  pub fn forget(self) {
+    mem::forget(self)
+  }
+}
+
+impl<'a, P: Park> Drop for SemaphorePermit<'a, P> {
+  fn drop(&mut self) { self.semaphore.add_permits(1) }
+}
+
+impl<'a, P: Park> fmt::Debug for SemaphorePermit<'a, P> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("SemaphorePermit")
+  }
+}
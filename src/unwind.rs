@@ -0,0 +1,97 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+pub use self::imp::*;
+
+/// The reason a generator function did not return.
+pub enum Unwound {
+  /// The generator was cancelled.
+  Cancelled,
+  /// The generator function panicked.
+  Panicked(Payload),
+  /// The generator overflowed its stack, and was abandoned.
+  Overflowed
+}
+
+#[cfg(feature = "std")]
+mod imp {
+  extern crate std;
+
+  use self::std::any::Any;
+  use self::std::boxed::Box;
+  use self::std::{panic, process};
+  use unwind::Unwound;
+  use generator::StackOverflow;
+
+  /// The payload of a panic.
+  pub type Payload = Box<Any + Send>;
+
+  /// The payload of the unwinding started by `cancel`. It is never observed
+  /// outside of the generator stack.
+  struct Cancel;
+
+  /// Starts unwinding the stack of a generator that is being cancelled.
+  pub fn cancel() -> ! {
+    panic::resume_unwind(Box::new(Cancel))
+  }
+
+  /// Calls `f`, catching any unwinding, so that it never has to cross
+  /// the boundary of the generator stack.
+  #[inline(always)]
+  pub fn catch<F: FnOnce() -> R, R>(f: F) -> Result<R, Unwound> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+      Ok(value) => Ok(value),
+      Err(ref payload) if payload.is::<Cancel>() => Err(Unwound::Cancelled),
+      Err(payload) => Err(Unwound::Panicked(payload))
+    }
+  }
+
+  /// Continues unwinding with a payload returned by `catch`.
+  pub fn resume(payload: Payload) -> ! {
+    panic::resume_unwind(payload)
+  }
+
+  /// Starts unwinding the stack of the resumer of a generator that has
+  /// overflowed its stack.
+  pub fn overflow() -> ! {
+    self::std::panic!(StackOverflow)
+  }
+
+  pub fn abort() -> ! {
+    process::abort()
+  }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+  use core::intrinsics;
+  use unwind::Unwound;
+
+  /// Without unwinding, panics are never caught.
+  pub enum Payload {}
+
+  /// Without unwinding, generators are never cancelled.
+  pub fn cancel() -> ! {
+    unreachable!()
+  }
+
+  #[inline(always)]
+  pub fn catch<F: FnOnce() -> R, R>(f: F) -> Result<R, Unwound> {
+    Ok(f())
+  }
+
+  pub fn resume(payload: Payload) -> ! {
+    match payload {}
+  }
+
+  pub fn overflow() -> ! {
+    panic!("generator overflowed its stack")
+  }
+
+  pub fn abort() -> ! {
+    unsafe { intrinsics::abort() }
+  }
+}
@@ -0,0 +1,78 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+use std::{io, thread};
+use fringe::{Stack, GuardedStack, StackArena, Generator};
+
+#[test]
+fn slots() {
+  let arena = StackArena::new(1 << 16, 4).unwrap();
+  assert_eq!(arena.capacity(), 4);
+  assert_eq!(arena.in_use(), 0);
+
+  let stacks = (0..4).map(|_| arena.get().unwrap().unwrap()).collect::<Vec<_>>();
+  assert_eq!(arena.in_use(), 4);
+  assert!(arena.get().unwrap().is_none());
+
+  for stack in stacks.iter() {
+    assert_eq!(stack.base() as usize - stack.limit() as usize, 1 << 16);
+    assert_eq!(stack.guard_size(), 4096);
+    unsafe { *stack.limit() = 0; *stack.base().offset(-1) = 0 }
+  }
+  // Stacks do not overlap each other's guard pages.
+  for pair in stacks.windows(2) {
+    assert_eq!(pair[1].limit() as usize - pair[0].base() as usize, 4096);
+  }
+
+  drop(stacks);
+  assert_eq!(arena.in_use(), 0);
+}
+
+#[test]
+fn reuse() {
+  let arena = StackArena::new(1 << 16, 4).unwrap();
+  let limit = {
+    let stack = arena.get().unwrap().unwrap();
+    stack.limit()
+  };
+  // The most recently freed slot is reused first.
+  assert_eq!(arena.get().unwrap().unwrap().limit(), limit);
+}
+
+#[test]
+fn generators_across_threads() {
+  let arena = StackArena::new(1 << 16, 16).unwrap();
+  let threads = (0..4).map(|i| {
+    let arena = arena.clone();
+    thread::spawn(move || {
+      for j in 0..100 {
+        let stack = arena.get().unwrap().unwrap();
+        let mut gen = Generator::new(stack, move |yielder, ()| yielder.suspend(i * j));
+        assert_eq!(gen.next(), Some(i * j));
+      }
+    })
+  }).collect::<Vec<_>>();
+  for thread in threads { thread.join().unwrap() }
+  assert_eq!(arena.in_use(), 0);
+}
+
+#[test]
+fn outlives_arena() {
+  let stack = StackArena::new(1 << 16, 1).unwrap().get().unwrap().unwrap();
+  let mut gen = Generator::new(stack, move |yielder, ()| yielder.suspend(1));
+  assert_eq!(gen.next(), Some(1));
+}
+
+#[test]
+fn too_large() {
+  let err = StackArena::new(1 << 16, usize::max_value() / 4096).unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+  let err = StackArena::new(usize::max_value(), 1).unwrap_err();
+  assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,130 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+mod common;
+
+use std::{env, fs, process, thread};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use fringe::{rt, BlockingPool, Generator, OsStack};
+use fringe::rt::{mt, sim};
+use common::RoundRobin;
+
+#[test]
+fn other_tasks_run() {
+  rt::run(|| {
+    let ticks = Rc::new(Cell::new(0));
+    let ticker = {
+      let ticks = ticks.clone();
+      rt::spawn(rt::stack(), move || {
+        for _ in 0..5 {
+          rt::sleep(Duration::from_millis(5));
+          ticks.set(ticks.get() + 1)
+        }
+      })
+    };
+    let value = fringe::blocking(|| {
+      thread::sleep(Duration::from_millis(100));
+      42
+    });
+    assert_eq!(value, 42);
+    // The runtime kept running the other task while the call blocked.
+    assert_eq!(ticks.get(), 5);
+    ticker.join().unwrap();
+  })
+}
+
+#[test]
+fn files() {
+  let path = env::temp_dir().join(format!("fringe-blocking-{}", process::id()));
+  rt::run(move || {
+    let contents = {
+      let path = path.clone();
+      fringe::blocking(move || {
+        try!(fs::write(&path, b"hello"));
+        fs::read(&path)
+      })
+    };
+    assert_eq!(contents.unwrap(), b"hello");
+    fringe::blocking(move || fs::remove_file(path)).unwrap();
+  })
+}
+
+#[test]
+fn panic_propagates() {
+  rt::run(|| {
+    let handle = rt::spawn(rt::stack(), || fringe::blocking(|| -> () { panic!("foo") }));
+    let payload = handle.join().unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"foo"));
+  })
+}
+
+#[test]
+fn pool_limits_threads() {
+  let pool = Arc::new(BlockingPool::new(2));
+  let current = Arc::new(AtomicUsize::new(0));
+  let executor = mt::Executor::new(4);
+  let start = Instant::now();
+  let handles = (0..6).map(|_| {
+    let (pool, current) = (pool.clone(), current.clone());
+    unsafe {
+      executor.spawn(move || {
+        pool.run(move || {
+          assert!(current.fetch_add(1, Ordering::SeqCst) < 2);
+          thread::sleep(Duration::from_millis(20));
+          current.fetch_sub(1, Ordering::SeqCst);
+        })
+      })
+    }
+  }).collect::<Vec<_>>();
+  for handle in handles { handle.join().unwrap() }
+  // Two threads make the six calls in three rounds.
+  assert!(start.elapsed() >= Duration::from_millis(60));
+}
+
+#[test]
+fn custom_park() {
+  let pool = BlockingPool::new(1);
+  let value = Cell::new(0);
+  let ticks = Cell::new(0);
+  let mut generators = vec![
+    Generator::new(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+      common::enter(yielder);
+      value.set(pool.run_with_park::<RoundRobin, _, _>(|| {
+        thread::sleep(Duration::from_millis(50));
+        42
+      }));
+    }),
+    Generator::new(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+      while value.get() == 0 {
+        ticks.set(ticks.get() + 1);
+        yielder.suspend(())
+      }
+    })
+  ];
+
+  common::run(&mut generators);
+  drop(generators);
+  assert_eq!(value.get(), 42);
+  // The driver kept resuming the other generator while the call blocked.
+  assert!(ticks.get() > 1);
+}
+
+#[test]
+fn inline_outside_of_tasks() {
+  let caller = thread::current().id();
+  assert_eq!(fringe::blocking(move || thread::current().id() == caller), true);
+  sim::check(10, || {
+    let caller = thread::current().id();
+    assert!(fringe::blocking(move || thread::current().id() == caller));
+  });
+}
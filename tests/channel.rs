@@ -0,0 +1,240 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+#[macro_use]
+extern crate fringe;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use fringe::rt::{self, mt, sim};
+use fringe::sync::channel::{self, RecvError, SendError, TryRecvError, TrySendError};
+
+#[test]
+fn unbounded_order() {
+  rt::run(|| {
+    let (tx, rx) = channel::unbounded();
+    for i in 0..100 { tx.send(i).unwrap() }
+    assert_eq!(rx.len(), 100);
+    drop(tx);
+    assert_eq!(rx.iter().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+    assert_eq!(rx.recv(), Err(RecvError));
+  })
+}
+
+#[test]
+fn bounded_suspends_sender() {
+  rt::run(|| {
+    let (tx, rx) = channel::bounded(2);
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let producer = {
+      let log = log.clone();
+      rt::spawn(rt::stack(), move || {
+        for i in 0..4 {
+          tx.send(i).unwrap();
+          log.borrow_mut().push(i);
+        }
+      })
+    };
+    rt::yield_now();
+    // The third value does not fit.
+    assert_eq!(*log.borrow(), [0, 1]);
+    assert_eq!(rx.iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    producer.join().unwrap();
+  })
+}
+
+#[test]
+fn try_operations() {
+  let (tx, rx) = channel::bounded(1);
+  assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+  assert_eq!(tx.try_send(1), Ok(()));
+  assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+  assert_eq!(rx.try_recv(), Ok(1));
+  drop(rx);
+  assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+  assert_eq!(tx.send(4), Err(SendError(4)));
+}
+
+#[test]
+fn rendezvous_waits_for_receiver() {
+  rt::run(|| {
+    let (tx, rx) = channel::rendezvous();
+    assert_eq!(tx.try_send(0), Err(TrySendError::Full(0)));
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let sender = {
+      let log = log.clone();
+      rt::spawn(rt::stack(), move || {
+        tx.send(1).unwrap();
+        log.borrow_mut().push("sent");
+      })
+    };
+    rt::yield_now();
+    rt::yield_now();
+    assert!(log.borrow().is_empty());
+    assert_eq!(rx.recv(), Ok(1));
+    log.borrow_mut().push("received");
+    sender.join().unwrap();
+    assert_eq!(*log.borrow(), ["received", "sent"]);
+  })
+}
+
+#[test]
+fn rendezvous_receiver_dropped() {
+  rt::run(|| {
+    let (tx, rx) = channel::rendezvous();
+    let (other_tx, other_rx) = channel::unbounded();
+    let receiver = rt::spawn(rt::stack(), move || {
+      select! {
+        recv(other_rx) -> value => value.unwrap(),
+        recv(rx) -> _ => unreachable!(),
+      }
+    });
+    rt::yield_now();
+    // The receiver waiting in select! lets the value in, but picks the other channel.
+    other_tx.send(()).unwrap();
+    assert_eq!(tx.send(1), Err(SendError(1)));
+    receiver.join().unwrap();
+  })
+}
+
+#[test]
+fn rendezvous_send_timeout() {
+  rt::run(|| {
+    let (tx, rx) = channel::rendezvous();
+    assert_eq!(rt::timeout(Duration::from_millis(5), || tx.send(1)), Err(rt::Elapsed));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+    let (other_tx, other_rx) = channel::unbounded();
+    let receiver = rt::spawn(rt::stack(), move || {
+      select! {
+        recv(other_rx) -> value => value.unwrap(),
+        recv(rx) -> _ => unreachable!(),
+      };
+      rx
+    });
+    rt::yield_now();
+    // The receiver waiting in select! lets the value in, but picks the other channel.
+    // The value is taken back once the send times out.
+    other_tx.send(()).unwrap();
+    assert_eq!(rt::timeout(Duration::from_millis(5), || tx.send(2)), Err(rt::Elapsed));
+    let rx = receiver.join().unwrap();
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+  })
+}
+
+#[test]
+fn select_ready() {
+  rt::run(|| {
+    let (tx1, rx1) = channel::unbounded::<u32>();
+    let (tx2, rx2) = channel::unbounded::<u32>();
+    tx2.send(2).unwrap();
+    let value = select! {
+      recv(rx1) -> _ => unreachable!(),
+      recv(rx2) -> value => value.unwrap(),
+    };
+    assert_eq!(value, 2);
+    let value = select! {
+      recv(rx1) -> _ => 1,
+      default => 0
+    };
+    assert_eq!(value, 0);
+    drop(tx1);
+    select! {
+      recv(rx1) -> result => assert_eq!(result, Err(RecvError)),
+      recv(rx2) -> _ => unreachable!(),
+    }
+  })
+}
+
+#[test]
+fn select_waits() {
+  rt::run(|| {
+    let (tx1, rx1) = channel::unbounded();
+    let (tx2, rx2) = channel::bounded(0);
+    // A full channel that stays connected.
+    let (tx3, _rx3) = channel::bounded(1);
+    tx3.send(()).unwrap();
+    let sender = rt::spawn(rt::stack(), move || {
+      rt::sleep(Duration::from_millis(5));
+      tx1.send("late").unwrap();
+      tx2
+    });
+    // Nothing can happen until the first channel receives something.
+    let result = select! {
+      recv(rx1) -> value => value.unwrap(),
+      send(tx3, ()) -> _ => "full",
+    };
+    assert_eq!(result, "late");
+    let tx2 = sender.join().unwrap();
+    // A receiver is waiting, so the rendezvous channel takes the value.
+    let receiver = rt::spawn(rt::stack(), move || rx2.recv().unwrap());
+    rt::yield_now();
+    select! {
+      send(tx2, 7) -> result => result.unwrap(),
+      default => panic!("the receiver is not waiting")
+    }
+    assert_eq!(receiver.join().unwrap(), 7);
+  })
+}
+
+#[test]
+fn executor_many_senders() {
+  let executor = mt::Executor::new(4);
+  let (tx, rx) = channel::bounded(4);
+  let handles = (0..8).map(|i| {
+    let tx = tx.clone();
+    unsafe {
+      executor.spawn(move || {
+        for j in 0..100 { tx.send(i * 100 + j).unwrap() }
+      })
+    }
+  }).collect::<Vec<_>>();
+  drop(tx);
+  let consumer = unsafe {
+    executor.spawn(move || {
+      let mut values = rx.iter().collect::<Vec<_>>();
+      values.sort();
+      values
+    })
+  };
+  for handle in handles { handle.join().unwrap() }
+  assert_eq!(consumer.join().unwrap(), (0..800).collect::<Vec<_>>());
+}
+
+#[test]
+fn threads() {
+  let (tx, rx) = channel::rendezvous();
+  let thread = thread::spawn(move || {
+    for i in 0..100 { tx.send(i).unwrap() }
+  });
+  assert_eq!(rx.iter().sum::<i32>(), 4950);
+  thread.join().unwrap();
+}
+
+#[test]
+fn select_explore() {
+  sim::explore(10000, || {
+    let (tx1, rx1) = channel::rendezvous();
+    let (tx2, rx2) = channel::rendezvous();
+    // Keeping the senders alive, so that neither channel is disconnected.
+    let senders = vec![(tx1.clone(), 1), (tx2.clone(), 2)].into_iter().map(|(tx, value)| {
+      sim::spawn(move || tx.send(value).unwrap())
+    }).collect::<Vec<_>>();
+    let mut values = Vec::new();
+    for _ in 0..2 {
+      select! {
+        recv(rx1) -> value => values.push(value.unwrap()),
+        recv(rx2) -> value => values.push(value.unwrap()),
+      }
+    }
+    for sender in senders { sender.join() }
+    values.sort();
+    assert_eq!(values, [1, 2]);
+  });
+}
@@ -0,0 +1,53 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A driver that resumes every generator in turn, as in the example of `fringe::sync`.
+//! A parked generator simply suspends, and waking it up needs no work, since it is
+//! resumed anyway.
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::ptr;
+use fringe::{Generator, Stack};
+use fringe::generator::{GeneratorState, Yielder};
+use fringe::sync::{Park, Unpark};
+
+thread_local!(static YIELDER: Cell<*const Yielder<(), ()>> = Cell::new(ptr::null()));
+
+pub struct RoundRobin;
+pub struct Nothing;
+
+impl Unpark for Nothing {
+  fn unpark(&self) {}
+}
+
+impl Park for RoundRobin {
+  type Unparker = Nothing;
+  fn unparker() -> Nothing { Nothing }
+  fn park() {
+    let yielder = YIELDER.with(|cell| cell.get());
+    unsafe { (*yielder).suspend(()) };
+    YIELDER.with(|cell| cell.set(yielder))
+  }
+}
+
+/// Makes `RoundRobin::park()` suspend the generator that `yielder` belongs to.
+/// Every generator driven by `run` has to call it first.
+pub fn enter(yielder: &Yielder<(), ()>) {
+  YIELDER.with(|cell| cell.set(yielder))
+}
+
+/// Resumes every generator in turn, until they have all finished.
+pub fn run<S: Stack>(generators: &mut [Generator<(), (), S>]) {
+  let mut running = generators.len();
+  while running > 0 {
+    running = 0;
+    for generator in generators.iter_mut() {
+      if let Some(GeneratorState::Yielded(())) = generator.resume(()) { running += 1 }
+    }
+  }
+}
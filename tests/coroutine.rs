@@ -0,0 +1,116 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use std::cell::Cell;
+use std::panic;
+use std::rc::Rc;
+use fringe::{OsStack, Coroutine};
+use fringe::generator::{GeneratorState, State};
+
+fn new_stack() -> OsStack {
+  OsStack::new(1 << 16).unwrap()
+}
+
+#[test]
+fn chain() {
+  let last = Coroutine::new(new_stack(), |_, n: u32| n + 1);
+  let middle = Coroutine::new(new_stack(), |switcher, n: u32| switcher.transfer(&last, n * 2));
+  let first = Coroutine::new(new_stack(), |switcher, n: u32| switcher.transfer(&middle, n + 3));
+  assert_eq!(first.resume(1), Some(GeneratorState::Complete(9)));
+  assert_eq!(last.state(), State::Unavailable);
+  assert_eq!(middle.state(), State::Runnable);
+  assert_eq!(first.state(), State::Runnable);
+}
+
+#[test]
+fn ring() {
+  type Ring = [Cell<Option<&'static Coroutine<'static, u32, OsStack>>>; 3];
+  let ring: &'static Ring = Box::leak(Box::new([Cell::new(None), Cell::new(None), Cell::new(None)]));
+
+  for i in 0..3 {
+    let coroutine = Coroutine::new(new_stack(), move |switcher, mut n: u32| {
+      loop {
+        if n >= 30 { n = switcher.suspend(n) }
+        let next = ring[(i + 1) % 3].get().unwrap();
+        n = switcher.transfer(next, n + 1);
+      }
+    });
+    ring[i].set(Some(Box::leak(Box::new(coroutine))));
+  }
+
+  let first = ring[0].get().unwrap();
+  assert_eq!(first.resume(0), Some(GeneratorState::Yielded(30)));
+  // Resuming any coroutine of the ring continues from its pending `transfer()` call,
+  // and passes through the one that has suspended itself.
+  let third = ring[2].get().unwrap();
+  assert_eq!(third.resume(0), Some(GeneratorState::Yielded(30)));
+}
+
+#[test]
+fn suspend_and_resume() {
+  let coroutine = Coroutine::new(new_stack(), |switcher, n: u32| {
+    let n = switcher.suspend(n + 1);
+    let n = switcher.suspend(n + 1);
+    n + 1
+  });
+  assert_eq!(coroutine.resume(0), Some(GeneratorState::Yielded(1)));
+  assert_eq!(coroutine.resume(10), Some(GeneratorState::Yielded(11)));
+  assert_eq!(coroutine.resume(20), Some(GeneratorState::Complete(21)));
+  assert_eq!(coroutine.resume(30), None);
+  coroutine.unwrap();
+}
+
+#[test]
+fn moved_while_suspended() {
+  let coroutine = Coroutine::new(new_stack(), |switcher, n: u32| switcher.suspend(n) + 1);
+  assert_eq!(coroutine.resume(1), Some(GeneratorState::Yielded(1)));
+  let coroutine = Box::new(coroutine);
+  assert_eq!(coroutine.resume(2), Some(GeneratorState::Complete(3)));
+}
+
+#[test]
+#[should_panic(expected = "foo")]
+fn panic_propagates() {
+  let last = Coroutine::new(new_stack(), |_, _: u32| panic!("foo"));
+  let first = Coroutine::new(new_stack(), |switcher, n: u32| switcher.transfer(&last, n));
+  first.resume(0);
+}
+
+#[test]
+fn transfer_to_self() {
+  let this: Rc<Cell<Option<*const Coroutine<u32, OsStack>>>> = Rc::new(Cell::new(None));
+  let coroutine = {
+    let this = this.clone();
+    Coroutine::new(new_stack(), move |switcher, n: u32| {
+      switcher.transfer(unsafe { &*this.get().unwrap() }, n)
+    })
+  };
+  this.set(Some(&coroutine));
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(|| coroutine.resume(0)));
+  assert!(result.is_err());
+  assert_eq!(coroutine.state(), State::Unavailable);
+}
+
+#[test]
+fn drop_cancels() {
+  struct SetOnDrop(Rc<Cell<bool>>);
+  impl Drop for SetOnDrop {
+    fn drop(&mut self) { self.0.set(true) }
+  }
+
+  let dropped = Rc::new(Cell::new(false));
+  let guard = SetOnDrop(dropped.clone());
+  let coroutine = Coroutine::new(new_stack(), move |switcher, n: u32| {
+    let _guard = guard;
+    switcher.suspend(n)
+  });
+  assert_eq!(coroutine.resume(0), Some(GeneratorState::Yielded(0)));
+  assert!(!dropped.get());
+  drop(coroutine);
+  assert!(dropped.get());
+}
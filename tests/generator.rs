@@ -8,7 +8,7 @@
 extern crate fringe;
 
 use fringe::{SliceStack, OwnedStack, OsStack};
-use fringe::generator::{Generator, Yielder};
+use fringe::generator::{Generator, GeneratorState, Yielder, State, DropPolicy};
 
 fn add_one_fn(yielder: &Yielder<i32, i32>, mut input: i32) {
   loop {
@@ -25,21 +25,58 @@ fn new_add_one() -> Generator<'static, i32, i32, OsStack> {
 #[test]
 fn generator() {
   let mut add_one = new_add_one();
-  assert_eq!(add_one.resume(1), Some(2));
-  assert_eq!(add_one.resume(2), Some(3));
-  assert_eq!(add_one.resume(0), None);
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
+  assert_eq!(add_one.resume(2), Some(GeneratorState::Yielded(3)));
+  assert_eq!(add_one.resume(0), Some(GeneratorState::Complete(())));
+}
+
+#[test]
+fn return_value() {
+  let stack = OsStack::new(0).unwrap();
+  let mut sum = Generator::new(stack, |yielder, mut input| {
+    let mut sum = 0;
+    while input != 0 {
+      sum += input;
+      input = yielder.suspend(sum)
+    }
+    sum
+  });
+  assert_eq!(sum.resume(1), Some(GeneratorState::Yielded(1)));
+  assert_eq!(sum.resume(2), Some(GeneratorState::Yielded(3)));
+  assert_eq!(sum.resume(0), Some(GeneratorState::Complete(3)));
+  assert_eq!(sum.resume(0), None);
+  sum.unwrap();
+}
+
+#[test]
+fn return_value_dropped_once() {
+  use std::rc::Rc;
+  let counter = Rc::new(());
+  let stack = OsStack::new(0).unwrap();
+  let mut generator: Generator<(), (), OsStack, Rc<()>> = {
+    let counter = counter.clone();
+    Generator::new(stack, move |_, ()| counter)
+  };
+  let value = match generator.resume(()) {
+    Some(GeneratorState::Complete(value)) => value,
+    other => panic!("unexpected {:?}", other)
+  };
+  assert_eq!(Rc::strong_count(&counter), 2);
+  drop(value);
+  drop(generator);
+  assert_eq!(Rc::strong_count(&counter), 1);
 }
 
 #[test]
 fn move_after_new() {
   let mut add_one = new_add_one();
-  assert_eq!(add_one.resume(1), Some(2));
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
 
   #[inline(never)]
   fn run_moved(mut add_one: Generator<i32, i32, OsStack>) {
-    assert_eq!(add_one.resume(2), Some(3));
-    assert_eq!(add_one.resume(3), Some(4));
-    assert_eq!(add_one.resume(0), None);
+    assert_eq!(add_one.resume(2), Some(GeneratorState::Yielded(3)));
+    assert_eq!(add_one.resume(3), Some(GeneratorState::Yielded(4)));
+    assert_eq!(add_one.resume(0), Some(GeneratorState::Complete(())));
   }
   run_moved(add_one);
 }
@@ -71,18 +108,18 @@ fn with_slice_stack() {
   let mut memory = [0; 1024];
   let stack = SliceStack::new(&mut memory);
   let mut add_one = unsafe { Generator::unsafe_new(stack, add_one_fn) };
-  assert_eq!(add_one.resume(1), Some(2));
-  assert_eq!(add_one.resume(2), Some(3));
-  assert_eq!(add_one.resume(0), None);
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
+  assert_eq!(add_one.resume(2), Some(GeneratorState::Yielded(3)));
+  assert_eq!(add_one.resume(0), Some(GeneratorState::Complete(())));
 }
 
 #[test]
 fn with_owned_stack() {
   let stack = OwnedStack::new(1024);
   let mut add_one = unsafe { Generator::unsafe_new(stack, add_one_fn) };
-  assert_eq!(add_one.resume(1), Some(2));
-  assert_eq!(add_one.resume(2), Some(3));
-  assert_eq!(add_one.resume(0), None);
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
+  assert_eq!(add_one.resume(2), Some(GeneratorState::Yielded(3)));
+  assert_eq!(add_one.resume(0), Some(GeneratorState::Complete(())));
 }
 
 #[test]
@@ -112,7 +149,7 @@ fn forget_yielded() {
 fn unwrap_returned() {
   let stack = OsStack::new(0).unwrap();
   let mut generator = Generator::new(stack, |_, ()| {});
-  assert_eq!(generator.resume(()), None::<()>);
+  assert_eq!(generator.resume(()), Some(GeneratorState::Complete::<(), ()>(())));
   generator.unwrap();
 }
 
@@ -132,6 +169,200 @@ fn unwrap_panicked() {
 #[should_panic(expected = "Argh! Bastard! Don't touch that!")]
 fn unwrap_running() {
   let mut add_one = new_add_one();
-  assert_eq!(add_one.resume(1), Some(2));
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
   add_one.unwrap();
 }
+
+struct SetOnDrop<'a>(&'a std::cell::Cell<bool>);
+
+impl<'a> Drop for SetOnDrop<'a> {
+  fn drop(&mut self) {
+    self.0.set(true);
+  }
+}
+
+#[test]
+fn drop_unfinished() {
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    let _guard = SetOnDrop(&dropped);
+    loop { yielder.suspend(()) }
+  });
+  generator.resume(());
+  assert!(!dropped.get());
+  drop(generator);
+  assert!(dropped.get());
+}
+
+#[test]
+fn cancel() {
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    let _guard = SetOnDrop(&dropped);
+    yielder.suspend(1);
+    yielder.suspend(2);
+  });
+  assert_eq!(generator.resume(()), Some(GeneratorState::Yielded(1)));
+  generator.cancel();
+  assert!(dropped.get());
+  assert_eq!(generator.state(), State::Unavailable);
+  assert_eq!(generator.resume(()), None);
+  generator.unwrap();
+}
+
+#[test]
+fn cancel_unstarted() {
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let guard = SetOnDrop(&dropped);
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, move |yielder, ()| {
+    let _guard = guard;
+    yielder.suspend(());
+    unreachable!()
+  });
+  generator.cancel();
+  assert!(dropped.get());
+  generator.unwrap();
+}
+
+#[test]
+fn cancel_caught() {
+  use std::panic;
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    let _guard = SetOnDrop(&dropped);
+    let caught = panic::catch_unwind(panic::AssertUnwindSafe(|| yielder.suspend(1)));
+    assert!(caught.is_err());
+    yielder.suspend(2);
+    unreachable!()
+  });
+  assert_eq!(generator.resume(()), Some(GeneratorState::Yielded(1)));
+  generator.cancel();
+  assert!(dropped.get());
+  generator.unwrap();
+}
+
+#[test]
+fn drop_unfinished_while_panicking() {
+  use std::panic;
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+    let stack = OsStack::new(1 << 16).unwrap();
+    let mut generator = Generator::new(stack, |yielder, ()| {
+      let _guard = SetOnDrop(&dropped);
+      loop { yielder.suspend(()) }
+    });
+    generator.resume(());
+    panic!("outer")
+  }));
+  assert!(result.is_err());
+  assert!(dropped.get());
+}
+
+#[test]
+fn drop_policy_leak() {
+  use std::cell::Cell;
+  let dropped = Cell::new(false);
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    let _guard = SetOnDrop(&dropped);
+    loop { yielder.suspend(()) }
+  });
+  generator.set_drop_policy(DropPolicy::Leak);
+  generator.resume(());
+  drop(generator);
+  assert!(!dropped.get());
+}
+
+#[test]
+#[should_panic(expected = "dropped unfinished Generator")]
+fn drop_policy_panic() {
+  let mut add_one = new_add_one();
+  add_one.set_drop_policy(DropPolicy::Panic);
+  assert_eq!(add_one.resume(1), Some(GeneratorState::Yielded(2)));
+}
+
+#[test]
+fn try_resume_panicked() {
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    yielder.suspend(1);
+    panic!("foo")
+  });
+  assert_eq!(generator.try_resume(()).unwrap(), Some(GeneratorState::Yielded(1)));
+  let payload = generator.try_resume(()).unwrap_err();
+  assert_eq!(payload.downcast_ref::<&str>(), Some(&"foo"));
+  assert_eq!(generator.state(), State::Unavailable);
+  assert_eq!(generator.try_resume(()).unwrap(), None);
+  generator.unwrap();
+}
+
+#[test]
+fn try_resume_returned() {
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |_: &Yielder<(), ()>, ()| 42);
+  assert_eq!(generator.try_resume(()).unwrap(), Some(GeneratorState::Complete(42)));
+  generator.unwrap();
+}
+
+#[test]
+#[should_panic(expected = "foo")]
+fn try_resume_reraise() {
+  use std::panic;
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator: Generator<(), (), OsStack> = Generator::new(stack, |_, ()| panic!("foo"));
+  let payload = generator.try_resume(()).unwrap_err();
+  panic::resume_unwind(payload)
+}
+
+#[test]
+fn send_across_threads() {
+  use std::thread;
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = unsafe {
+    Generator::new_send(stack, |yielder, mut input: Vec<u32>| {
+      loop {
+        input.push(input.len() as u32);
+        input = yielder.suspend(input)
+      }
+    })
+  };
+  let values = match generator.resume(vec![]) {
+    Some(GeneratorState::Yielded(values)) => values,
+    _ => panic!()
+  };
+  let (mut generator, values) = thread::spawn(move || {
+    let values = generator.resume(values);
+    (generator, values)
+  }).join().unwrap();
+  assert_eq!(values, Some(GeneratorState::Yielded(vec![0, 1])));
+  generator.cancel();
+  assert_eq!(generator.state(), State::Unavailable);
+  generator.unwrap();
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn resume_on_foreign_thread() {
+  use std::thread;
+  struct AssertSend<T>(T);
+  unsafe impl<T> Send for AssertSend<T> {}
+
+  let mut generator = new_add_one();
+  assert_eq!(generator.resume(1), Some(GeneratorState::Yielded(2)));
+  let generator = AssertSend(generator);
+  let result = thread::spawn(move || {
+    let mut generator = generator;
+    generator.0.resume(2)
+  }).join();
+  let payload = result.unwrap_err();
+  assert!(payload.downcast_ref::<&str>().unwrap().contains("other than the one that created it"));
+}
@@ -0,0 +1,93 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use fringe::{OsStack, Generator, Coroutine};
+use fringe::generator::GeneratorState;
+use fringe::raw::{self, Context};
+
+fn depth(n: u32) -> u32 {
+  if n == 0 { 0 } else { 1 + depth(n - 1) }
+}
+
+#[test]
+fn on_stack() {
+  let mut stack = OsStack::new(16 << 20).unwrap();
+  assert_eq!(fringe::on_stack(&mut stack, || depth(100_000)), 100_000);
+  // The stack can be reused afterwards.
+  assert_eq!(fringe::on_stack(&mut stack, || depth(10)), 10);
+}
+
+#[test]
+#[should_panic(expected = "foo")]
+fn on_stack_panic() {
+  let mut stack = OsStack::new(1 << 16).unwrap();
+  fringe::on_stack(&mut stack, || panic!("foo"))
+}
+
+#[test]
+fn remaining_stack() {
+  let mut stack = OsStack::new(1 << 16).unwrap();
+  let remaining = fringe::on_stack(&mut stack, || fringe::remaining_stack()).unwrap();
+  assert!(remaining > 0 && remaining < 1 << 16);
+  assert!(fringe::remaining_stack().unwrap() > 1 << 16);
+}
+
+#[test]
+fn remaining_stack_in_generator() {
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    yielder.suspend(fringe::remaining_stack().unwrap());
+  });
+  let remaining = generator.next().unwrap();
+  assert!(remaining > 0 && remaining < 1 << 16);
+}
+
+#[test]
+fn remaining_stack_in_coroutine() {
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    let coroutine = Coroutine::new(OsStack::new(1 << 16).unwrap(), |switcher, _| {
+      switcher.suspend(fringe::remaining_stack().unwrap())
+    });
+    let inner = match coroutine.resume(0) {
+      Some(GeneratorState::Yielded(remaining)) => remaining,
+      _ => unreachable!()
+    };
+    // Back on the stack of the generator.
+    yielder.suspend((inner, fringe::remaining_stack().unwrap()));
+  });
+  let (inner, outer) = generator.next().unwrap();
+  assert!(inner > 0 && inner < 1 << 16);
+  assert!(outer > 0 && outer < 1 << 16);
+}
+
+#[test]
+fn remaining_stack_in_raw_context() {
+  unsafe extern "C" fn report(_: usize, parent: Context) -> ! {
+    raw::switch(fringe::remaining_stack().unwrap(), parent, None);
+    unreachable!()
+  }
+
+  let stack = OsStack::new(1 << 16).unwrap();
+  let remaining = unsafe { raw::switch(0, raw::init(&stack, report), Some(&stack)).0 };
+  assert!(remaining > 0 && remaining < 1 << 16);
+  assert!(fringe::remaining_stack().unwrap() > 1 << 16);
+}
+
+#[test]
+fn maybe_grow() {
+  fn grow_depth(n: u32) -> u32 {
+    fringe::maybe_grow(32 << 10, 1 << 20, || if n == 0 { 0 } else { 1 + grow_depth(n - 1) })
+  }
+
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut generator = Generator::new(stack, |yielder, ()| {
+    yielder.suspend(grow_depth(1_000_000));
+  });
+  assert_eq!(generator.next(), Some(1_000_000));
+}
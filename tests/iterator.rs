@@ -20,3 +20,31 @@ fn producer() {
   assert_eq!(gen.next(), Some(2));
   unsafe { gen.unsafe_unwrap(); }
 }
+
+#[test]
+fn finite() {
+  let stack = OsStack::new(0).unwrap();
+  let gen = Generator::new(stack, move |yielder, ()| {
+    for i in 0..3 { yielder.suspend(i) }
+    "done"
+  });
+  assert_eq!(gen.collect::<Vec<_>>(), vec![0, 1, 2]);
+}
+
+#[test]
+fn early_break() {
+  use std::rc::Rc;
+  let counter = Rc::new(());
+  let stack = OsStack::new(1 << 16).unwrap();
+  let gen = {
+    let counter = counter.clone();
+    Generator::new(stack, move |yielder, ()| {
+      let _counter = counter;
+      for i in 0.. { yielder.suspend(i) }
+    })
+  };
+  for i in gen {
+    if i == 2 { break }
+  }
+  assert_eq!(Rc::strong_count(&counter), 1);
+}
@@ -0,0 +1,123 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#[macro_use]
+extern crate fringe;
+
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use fringe::{OsStack, Generator};
+
+fiber_local! {
+  static COUNTER: Cell<u32> = Cell::new(0);
+  static LOG: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+}
+
+fn bump() -> u32 {
+  COUNTER.with(|counter| {
+    counter.set(counter.get() + 1);
+    counter.get()
+  })
+}
+
+#[test]
+fn per_generator() {
+  let make = || Generator::new(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+    loop { yielder.suspend(bump()) }
+  });
+  let (mut a, mut b) = (make(), make());
+  assert_eq!(a.next(), Some(1));
+  assert_eq!(a.next(), Some(2));
+  assert_eq!(b.next(), Some(1));
+  assert_eq!(a.next(), Some(3));
+  // Outside of any generator, the value of the thread is used.
+  assert_eq!(bump(), 1);
+  assert_eq!(b.next(), Some(2));
+  assert_eq!(bump(), 2);
+}
+
+#[test]
+fn nested() {
+  let mut outer = Generator::new(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+    LOG.with(|log| log.borrow_mut().push("outer"));
+    let mut inner = Generator::new(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+      LOG.with(|log| log.borrow_mut().push("inner"));
+      yielder.suspend(LOG.with(|log| log.borrow().clone()))
+    });
+    let inner_log = inner.next().unwrap();
+    yielder.suspend(inner_log);
+    yielder.suspend(LOG.with(|log| log.borrow().clone()))
+  });
+  assert_eq!(outer.next(), Some(vec!["inner"]));
+  assert_eq!(outer.next(), Some(vec!["outer"]));
+  assert!(LOG.with(|log| log.borrow().is_empty()));
+}
+
+struct Counted(Arc<AtomicUsize>);
+
+impl Drop for Counted {
+  fn drop(&mut self) {
+    // Destructors can use other fiber-local values.
+    bump();
+    self.0.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
+fiber_local!(static COUNTED: RefCell<Option<Counted>> = RefCell::new(None));
+
+fn track(drops: &Arc<AtomicUsize>) {
+  COUNTED.with(|counted| *counted.borrow_mut() = Some(Counted(drops.clone())))
+}
+
+#[test]
+fn destroyed_on_finish() {
+  let drops = Arc::new(AtomicUsize::new(0));
+  let mut generator = {
+    let drops = drops.clone();
+    Generator::new(OsStack::new(1 << 16).unwrap(), move |yielder, ()| {
+      track(&drops);
+      yielder.suspend(());
+    })
+  };
+  generator.resume(());
+  assert_eq!(drops.load(Ordering::SeqCst), 0);
+  generator.resume(());
+  assert_eq!(drops.load(Ordering::SeqCst), 1);
+  drop(generator);
+  assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn destroyed_on_drop() {
+  let drops = Arc::new(AtomicUsize::new(0));
+  let mut generator = {
+    let drops = drops.clone();
+    Generator::<(), (), _>::new(OsStack::new(1 << 16).unwrap(), move |yielder, ()| {
+      track(&drops);
+      loop { yielder.suspend(()) }
+    })
+  };
+  generator.resume(());
+  drop(generator);
+  assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn send_generator() {
+  let mut generator = unsafe {
+    Generator::new_send(OsStack::new(1 << 16).unwrap(), |yielder, ()| {
+      loop { yielder.suspend(bump()) }
+    })
+  };
+  assert_eq!(generator.next(), Some(1));
+  let mut generator = thread::spawn(move || {
+    assert_eq!(generator.next(), Some(2));
+    generator
+  }).join().unwrap();
+  assert_eq!(generator.next(), Some(3));
+}
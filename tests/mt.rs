@@ -0,0 +1,146 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use fringe::rt::mt::{self, Executor};
+
+#[test]
+fn spawn_join() {
+  let executor = Executor::new(2);
+  let handle = unsafe { executor.spawn(|| 20) };
+  let other = unsafe { executor.spawn(|| mt::spawn(|| 22).join().unwrap()) };
+  assert_eq!(handle.join().unwrap() + other.join().unwrap(), 42);
+}
+
+#[test]
+fn join_panicked() {
+  let executor = Executor::new(2);
+  let payload = unsafe { executor.spawn(|| -> () { panic!("foo") }) }.join().unwrap_err();
+  assert_eq!(payload.downcast_ref::<&str>(), Some(&"foo"));
+  // The worker survives the panic.
+  assert_eq!(unsafe { executor.spawn(|| 1) }.join().unwrap(), 1);
+}
+
+#[test]
+fn many_tasks() {
+  let executor = Executor::new(4);
+  let handles = (0..1000usize).map(|i| {
+    unsafe {
+      executor.spawn(move || {
+        for _ in 0..10 { mt::yield_now() }
+        i
+      })
+    }
+  }).collect::<Vec<_>>();
+  let sum: usize = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+  assert_eq!(sum, 999 * 1000 / 2);
+}
+
+#[test]
+fn tasks_migrate() {
+  // A task spawned from a task goes to the queue of its worker; the other workers
+  // have to steal, and yielding tasks end up running on several threads.
+  let executor = Executor::new(4);
+  let threads = Arc::new(Mutex::new(HashSet::new()));
+  let handle = {
+    let threads = threads.clone();
+    unsafe {
+      executor.spawn(move || {
+        let handles = (0..64).map(|_| {
+          let threads = threads.clone();
+          mt::spawn(move || {
+            for _ in 0..100 {
+              threads.lock().unwrap().insert(thread::current().id());
+              thread::sleep(Duration::from_micros(10));
+              mt::yield_now()
+            }
+          })
+        }).collect::<Vec<_>>();
+        for handle in handles { handle.join().unwrap() }
+      })
+    }
+  };
+  handle.join().unwrap();
+  assert!(threads.lock().unwrap().len() > 1);
+}
+
+#[test]
+fn park_unpark() {
+  let executor = Executor::new(2);
+  let flag = Arc::new(AtomicBool::new(false));
+  let parked = {
+    let flag = flag.clone();
+    unsafe {
+      executor.spawn(move || {
+        while !flag.load(Ordering::SeqCst) { mt::park() }
+      })
+    }
+  };
+  let task = parked.task().clone();
+  thread::sleep(Duration::from_millis(10));
+  flag.store(true, Ordering::SeqCst);
+  task.unpark();
+  parked.join().unwrap();
+}
+
+#[test]
+fn unpark_races_park() {
+  // Tasks ping-pong, unparking each other right as they park.
+  let executor = Executor::new(4);
+  let counter = Arc::new(AtomicUsize::new(0));
+  let handles = (0..8).map(|_| {
+    let counter = counter.clone();
+    unsafe {
+      executor.spawn(move || {
+        let me = mt::current();
+        for _ in 0..1000 {
+          let other = me.clone();
+          let counter = counter.clone();
+          let done = Arc::new(AtomicBool::new(false));
+          let flag = done.clone();
+          mt::spawn(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            flag.store(true, Ordering::SeqCst);
+            other.unpark()
+          });
+          while !done.load(Ordering::SeqCst) { mt::park() }
+        }
+      })
+    }
+  }).collect::<Vec<_>>();
+  for handle in handles { handle.join().unwrap() }
+  assert_eq!(counter.load(Ordering::SeqCst), 8000);
+}
+
+#[test]
+fn shutdown_waits() {
+  let executor = Executor::new(2);
+  let finished = Arc::new(AtomicUsize::new(0));
+  for _ in 0..16 {
+    let finished = finished.clone();
+    unsafe {
+      executor.spawn(move || {
+        for _ in 0..10 { mt::yield_now() }
+        finished.fetch_add(1, Ordering::SeqCst);
+      });
+    }
+  }
+  executor.shutdown();
+  assert_eq!(finished.load(Ordering::SeqCst), 16);
+}
+
+#[test]
+#[should_panic(expected = "not running inside of a task")]
+fn spawn_outside() {
+  unsafe { mt::spawn(|| ()); }
+}
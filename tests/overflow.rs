@@ -0,0 +1,150 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+use std::{env, ptr};
+use std::process::Command;
+use fringe::{OsStack, GuardedOwnedStack, StackArena, Generator};
+use fringe::generator::{GeneratorState, State, OverflowPolicy, StackOverflow};
+
+const CHILD: &'static str = "FRINGE_OVERFLOW_CHILD";
+
+fn recurse(n: usize) -> usize {
+  if n == usize::max_value() { return 0 }
+  let mut buf = [0u8; 256];
+  unsafe { ptr::write_volatile(&mut buf[0], n as u8) }
+  recurse(n + 1) + unsafe { ptr::read_volatile(&buf[0]) } as usize
+}
+
+/// Runs `test` in a child process, which is expected to abort, and returns its stderr.
+fn run_child(test: &str) -> String {
+  let output = Command::new(env::current_exe().unwrap())
+    .args(&["--exact", test, "--nocapture", "--test-threads=1"])
+    .env(CHILD, "1")
+    .output()
+    .unwrap();
+  assert!(!output.status.success());
+  String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn overflow_diagnosed() {
+  if env::var_os(CHILD).is_none() {
+    let stderr = run_child("overflow_diagnosed");
+    assert!(stderr.contains("generator stack overflow"), "stderr: {}", stderr);
+    assert!(stderr.contains("generator: runaway"), "stderr: {}", stderr);
+    return
+  }
+
+  fringe::install_overflow_handler().unwrap();
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut gen = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+  gen.set_name("runaway");
+  gen.resume(());
+}
+
+#[test]
+fn overflow_diagnosed_stack_before_handler() {
+  if env::var_os(CHILD).is_none() {
+    let stderr = run_child("overflow_diagnosed_stack_before_handler");
+    assert!(stderr.contains("generator stack overflow"), "stderr: {}", stderr);
+    return
+  }
+
+  // The stack is created before the handler is installed.
+  let stack = OsStack::new(1 << 16).unwrap();
+  fringe::install_overflow_handler().unwrap();
+  let mut gen = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+  gen.resume(());
+}
+
+#[test]
+fn unrelated_fault_not_diagnosed() {
+  if env::var_os(CHILD).is_none() {
+    let stderr = run_child("unrelated_fault_not_diagnosed");
+    assert!(!stderr.contains("generator stack overflow"), "stderr: {}", stderr);
+    return
+  }
+
+  fringe::install_overflow_handler().unwrap();
+  unsafe { ptr::write_volatile(8 as *mut u8, 0) }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn overflow_poisons() {
+  fringe::install_overflow_handler().unwrap();
+  // Overflow more than once, to make sure that the signal mask is restored.
+  for _ in 0..2 {
+    let stack = OsStack::new(1 << 16).unwrap();
+    let mut gen = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+    gen.set_overflow_policy(OverflowPolicy::Poison);
+    let payload = gen.try_resume(()).unwrap_err();
+    assert!(payload.is::<StackOverflow>());
+    assert_eq!(gen.state(), State::Poisoned);
+    assert!(gen.try_resume(()).unwrap().is_none());
+  }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn overflow_poisons_guarded_owned_stack() {
+  fringe::install_overflow_handler().unwrap();
+  let stack = GuardedOwnedStack::new(1 << 16).unwrap();
+  let mut gen = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+  gen.set_overflow_policy(OverflowPolicy::Poison);
+  let payload = gen.try_resume(()).unwrap_err();
+  assert!(payload.is::<StackOverflow>());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn overflow_poisons_arena_stack() {
+  fringe::install_overflow_handler().unwrap();
+  let arena = StackArena::new(1 << 16, 2).unwrap();
+  let _neighbour = arena.get().unwrap().unwrap();
+  let stack = arena.get().unwrap().unwrap();
+  let mut gen = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+  gen.set_overflow_policy(OverflowPolicy::Poison);
+  let payload = gen.try_resume(()).unwrap_err();
+  assert!(payload.is::<StackOverflow>());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn overflow_poisons_nested() {
+  fringe::install_overflow_handler().unwrap();
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut outer = Generator::<(), bool, _>::new(stack, |yielder, ()| {
+    let stack = OsStack::new(1 << 16).unwrap();
+    let mut inner = Generator::<(), (), _, usize>::new(stack, |_, ()| recurse(0));
+    inner.set_overflow_policy(OverflowPolicy::Poison);
+    let overflowed = inner.try_resume(()).is_err();
+    yielder.suspend(overflowed && inner.state() == State::Poisoned);
+  });
+  assert_eq!(outer.resume(()), Some(GeneratorState::Yielded(true)));
+  assert_eq!(outer.resume(()), Some(GeneratorState::Complete(())));
+}
+
+#[test]
+fn overflow_large_frame_diagnosed() {
+  if env::var_os(CHILD).is_none() {
+    let stderr = run_child("overflow_large_frame_diagnosed");
+    assert!(stderr.contains("generator stack overflow"), "stderr: {}", stderr);
+    return
+  }
+
+  // The generator function is larger than the stack and its guard page together,
+  // so the frame holding it has to be probed to fault in the guard page.
+  fringe::install_overflow_handler().unwrap();
+  let stack = OsStack::new(16 << 10).unwrap();
+  let buf = [1u8; 64 << 10];
+  Generator::<(), (), _, usize>::new(stack, move |_, ()| {
+    buf.iter().map(|&x| x as usize).sum()
+  });
+}
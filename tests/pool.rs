@@ -0,0 +1,108 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use std::thread;
+use fringe::{Stack, StackPool, StackPoolConfig, Generator};
+
+fn no_thread_cache() -> StackPoolConfig {
+  StackPoolConfig { thread_cache_size: 0, ..StackPoolConfig::default() }
+}
+
+#[test]
+fn reuse() {
+  let pool = StackPool::new(1 << 16);
+  let base = {
+    let stack = pool.get().unwrap();
+    assert!(stack.base() as usize - stack.limit() as usize >= 1 << 16);
+    stack.base()
+  };
+  assert_eq!(pool.get().unwrap().base(), base);
+}
+
+#[test]
+fn shared_idle() {
+  let pool = StackPool::with_config(0, no_thread_cache());
+  let stacks = (0..3).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+  assert_eq!(pool.idle_stacks(), 0);
+  drop(stacks);
+  assert_eq!(pool.idle_stacks(), 3);
+  let _stack = pool.get().unwrap();
+  assert_eq!(pool.idle_stacks(), 2);
+}
+
+#[test]
+fn max_idle_stacks() {
+  let pool = StackPool::with_config(0, StackPoolConfig {
+    max_idle_stacks: 2,
+    ..no_thread_cache()
+  });
+  drop((0..3).map(|_| pool.get().unwrap()).collect::<Vec<_>>());
+  assert_eq!(pool.idle_stacks(), 2);
+}
+
+#[test]
+fn max_idle_bytes() {
+  let pool = StackPool::with_config(1 << 16, StackPoolConfig {
+    max_idle_bytes: 3 << 16,
+    ..no_thread_cache()
+  });
+  drop((0..3).map(|_| pool.get().unwrap()).collect::<Vec<_>>());
+  // Each stack also has a guard page.
+  assert_eq!(pool.idle_stacks(), 2);
+  assert!(pool.idle_bytes() <= 3 << 16);
+}
+
+#[test]
+fn thread_cache() {
+  let pool = StackPool::new(0);
+  drop(pool.get().unwrap());
+  assert_eq!(pool.idle_stacks(), 0);
+
+  // The cache of a thread is returned to the pool when the thread exits.
+  let thread_pool = pool.clone();
+  thread::spawn(move || drop(thread_pool.get().unwrap())).join().unwrap();
+  assert_eq!(pool.idle_stacks(), 1);
+}
+
+#[test]
+fn release_memory() {
+  let pool = StackPool::with_config(0, StackPoolConfig {
+    release_memory: true,
+    ..no_thread_cache()
+  });
+  let stack = pool.get().unwrap();
+  let base = stack.base();
+  unsafe { *base.offset(-1) = 1 }
+  drop(stack);
+
+  let stack = pool.get().unwrap();
+  assert_eq!(stack.base(), base);
+  // The memory has been released, so it reads back as zero.
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  assert_eq!(unsafe { *base.offset(-1) }, 0);
+  // The stack is still accessible.
+  unsafe { *base.offset(-1) = 2 }
+}
+
+#[test]
+fn generators_across_threads() {
+  let pool = StackPool::new(1 << 16);
+  let threads = (0..4).map(|i| {
+    let pool = pool.clone();
+    thread::spawn(move || {
+      for j in 0..100 {
+        let mut gen = Generator::new(pool.get().unwrap(), move |yielder, ()| {
+          yielder.suspend(i * j)
+        });
+        assert_eq!(gen.next(), Some(i * j));
+      }
+    })
+  }).collect::<Vec<_>>();
+  for thread in threads { thread.join().unwrap() }
+  assert!(pool.idle_stacks() > 0);
+}
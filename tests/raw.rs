@@ -0,0 +1,64 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use fringe::{Stack, OsStack};
+use fringe::raw::{self, Context};
+
+#[test]
+fn ping_pong() {
+  unsafe extern "C" fn counter(mut arg: usize, mut parent: Context) -> ! {
+    loop {
+      let (next, context) = raw::switch(arg * 2, parent, None);
+      arg = next;
+      parent = context
+    }
+  }
+
+  let stack = OsStack::new(1 << 16).unwrap();
+  unsafe {
+    let mut context = raw::init(&stack, counter);
+    for i in 0..10 {
+      let (result, next) = raw::switch(i, context, Some(&stack));
+      assert_eq!(result, i * 2);
+      assert!(next.stack_ptr() > stack.limit() && next.stack_ptr() < stack.base());
+      context = next
+    }
+  }
+}
+
+#[test]
+fn payload_pointer() {
+  unsafe extern "C" fn reverse(arg: usize, parent: Context) -> ! {
+    let words = &mut *(arg as *mut Vec<&'static str>);
+    words.reverse();
+    raw::switch(0, parent, None);
+    unreachable!()
+  }
+
+  let stack = OsStack::new(1 << 16).unwrap();
+  let mut words = vec!["a", "b", "c"];
+  unsafe {
+    let context = raw::init(&stack, reverse);
+    raw::switch(&mut words as *mut Vec<&str> as usize, context, Some(&stack));
+  }
+  assert_eq!(words, ["c", "b", "a"]);
+}
+
+#[test]
+#[should_panic(expected = "linked")]
+fn panic_unwinds_into_parent() {
+  unsafe extern "C" fn panicker(_arg: usize, _parent: Context) -> ! {
+    panic!("linked")
+  }
+
+  let stack = OsStack::new(1 << 16).unwrap();
+  unsafe {
+    let context = raw::init(&stack, panicker);
+    raw::switch(0, context, Some(&stack));
+  }
+}
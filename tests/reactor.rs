@@ -0,0 +1,160 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(target_os = "linux")]
+extern crate libc;
+extern crate fringe;
+
+use std::{env, fs, process, thread};
+use std::io::{Read, Write, ErrorKind};
+use std::net::Shutdown;
+use fringe::rt;
+use fringe::rt::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+fn pipe() -> (libc::c_int, libc::c_int) {
+  let mut fds = [0; 2];
+  assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) }, 0);
+  (fds[0], fds[1])
+}
+
+#[test]
+fn pipe_read_waits() {
+  rt::run(|| {
+    let (reader, writer) = pipe();
+    let handle = rt::spawn(rt::stack(), move || {
+      let mut buf = [0; 3];
+      let len = rt::io::read(reader, &mut buf).unwrap();
+      buf[..len].to_vec()
+    });
+    // Let the reader start waiting first.
+    rt::yield_now();
+    assert!(!handle.is_finished());
+    rt::io::write(writer, b"abc").unwrap();
+    assert_eq!(handle.join().unwrap(), b"abc");
+    rt::io::forget(reader);
+    unsafe { libc::close(reader); libc::close(writer) };
+  })
+}
+
+#[test]
+fn pipe_write_waits() {
+  rt::run(|| {
+    let (reader, writer) = pipe();
+    let data = vec![0x5a; 1 << 20];
+    let handle = rt::spawn(rt::stack(), move || {
+      let mut written = 0;
+      while written < data.len() {
+        written += rt::io::write(writer, &data[written..]).unwrap()
+      }
+      unsafe { libc::close(writer) };
+    });
+    let mut total = 0;
+    let mut buf = [0; 4096];
+    loop {
+      match rt::io::read(reader, &mut buf).unwrap() {
+        0 => break,
+        len => total += len
+      }
+    }
+    assert_eq!(total, 1 << 20);
+    handle.join().unwrap();
+    unsafe { libc::close(reader) };
+  })
+}
+
+#[test]
+fn tcp_echo() {
+  rt::run(|| {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = rt::spawn(rt::stack(), move || {
+      for _ in 0..4 {
+        let (mut stream, _) = listener.accept().unwrap();
+        rt::spawn(rt::stack(), move || {
+          let mut buf = Vec::new();
+          stream.read_to_end(&mut buf).unwrap();
+          stream.write_all(&buf).unwrap();
+        });
+      }
+    });
+    let clients = (0..4u8).map(|i| {
+      rt::spawn(rt::stack(), move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        stream.write_all(&[i; 100]).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, &[i; 100][..]);
+      })
+    }).collect::<Vec<_>>();
+    for client in clients { client.join().unwrap() }
+    server.join().unwrap()
+  })
+}
+
+#[test]
+fn tcp_connect_refused() {
+  rt::run(|| {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let err = TcpStream::connect(addr).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+  })
+}
+
+#[test]
+fn unix_stream() {
+  let path = env::temp_dir().join(format!("fringe-reactor-{}.sock", process::id()));
+  let _ = fs::remove_file(&path);
+  {
+    let path = path.clone();
+    rt::run(move || {
+      let listener = UnixListener::bind(&path).unwrap();
+      let client = rt::spawn(rt::stack(), move || {
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"hello").unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"olleh");
+      });
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut buf = [0; 5];
+      stream.read_exact(&mut buf).unwrap();
+      buf.reverse();
+      stream.write_all(&buf).unwrap();
+      client.join().unwrap();
+    });
+  }
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn woken_from_thread() {
+  let (mut theirs, ours) = ::std::os::unix::net::UnixStream::pair().unwrap();
+  let thread = thread::spawn(move || {
+    thread::sleep(::std::time::Duration::from_millis(10));
+    theirs.write_all(b"x").unwrap();
+  });
+  rt::run(move || {
+    let mut ours = UnixStream::from_std(ours).unwrap();
+    let mut buf = [0; 1];
+    ours.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"x");
+  });
+  thread.join().unwrap()
+}
+
+#[test]
+fn outside_runtime() {
+  let (a, b) = UnixStream::pair().unwrap();
+  let thread = thread::spawn(move || {
+    let mut buf = [0; 2];
+    (&b).read_exact(&mut buf).unwrap();
+    buf
+  });
+  (&a).write_all(b"hi").unwrap();
+  assert_eq!(&thread.join().unwrap(), b"hi");
+}
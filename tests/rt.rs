@@ -0,0 +1,116 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use fringe::{rt, OsStack};
+
+#[test]
+fn spawn_join() {
+  let value = rt::run(|| {
+    let handle = rt::spawn(rt::stack(), || 20);
+    let other = rt::spawn(OsStack::new(1 << 16).unwrap(), || 22);
+    handle.join().unwrap() + other.join().unwrap()
+  });
+  assert_eq!(value, 42);
+}
+
+#[test]
+fn yield_interleaves() {
+  let log = Rc::new(RefCell::new(Vec::new()));
+  {
+    let log = log.clone();
+    rt::run(move || {
+      for name in &["a", "b"] {
+        let log = log.clone();
+        rt::spawn(rt::stack(), move || {
+          for i in 0..3 {
+            log.borrow_mut().push(format!("{}{}", name, i));
+            rt::yield_now()
+          }
+        });
+      }
+    });
+  }
+  assert_eq!(*log.borrow(), ["a0", "b0", "a1", "b1", "a2", "b2"]);
+}
+
+#[test]
+fn join_panicked() {
+  rt::run(|| {
+    let handle = rt::spawn(rt::stack(), || -> () { panic!("foo") });
+    let payload = handle.join().unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"foo"));
+  })
+}
+
+#[test]
+#[should_panic(expected = "foo")]
+fn main_panic_propagates() {
+  rt::run(|| panic!("foo"))
+}
+
+#[test]
+fn runs_detached_tasks() {
+  let done = Rc::new(Cell::new(false));
+  {
+    let done = done.clone();
+    rt::run(move || {
+      rt::spawn(rt::stack(), move || {
+        rt::yield_now();
+        done.set(true)
+      });
+    });
+  }
+  assert!(done.get());
+}
+
+#[test]
+fn park_unpark() {
+  rt::run(|| {
+    let flag = Rc::new(Cell::new(false));
+    let parked = rt::current();
+    {
+      let flag = flag.clone();
+      rt::spawn(rt::stack(), move || {
+        flag.set(true);
+        parked.unpark()
+      });
+    }
+    while !flag.get() { rt::park() }
+  })
+}
+
+#[test]
+fn unpark_before_park() {
+  rt::run(|| {
+    rt::current().unpark();
+    rt::park()
+  })
+}
+
+#[test]
+fn unpark_from_thread() {
+  rt::run(|| {
+    let task = rt::current();
+    let thread = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(10));
+      task.unpark()
+    });
+    rt::park();
+    thread.join().unwrap()
+  })
+}
+
+#[test]
+#[should_panic(expected = "not running inside of fringe::rt::run()")]
+fn spawn_outside() {
+  rt::spawn(OsStack::new(0).unwrap(), || ());
+}
@@ -0,0 +1,158 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use std::cell::{Cell, RefCell};
+use std::panic;
+use std::time::Duration;
+use fringe::rt;
+
+#[test]
+fn borrows() {
+  rt::run(|| {
+    let mut values = vec![1, 2, 3];
+    let log = RefCell::new(Vec::new());
+    let sum = rt::scope(|s| {
+      let handles = values.iter_mut().map(|value| {
+        let log = &log;
+        s.spawn(rt::stack(), move |_| {
+          rt::yield_now();
+          *value += 1;
+          log.borrow_mut().push(*value);
+          *value
+        })
+      }).collect::<Vec<_>>();
+      handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u32>()
+    });
+    assert_eq!(sum, 9);
+    assert_eq!(values, [2, 3, 4]);
+    assert_eq!(*log.borrow(), [2, 3, 4]);
+  })
+}
+
+#[test]
+fn waits_for_unjoined() {
+  rt::run(|| {
+    let done = Cell::new(0);
+    rt::scope(|s| {
+      s.spawn(rt::stack(), |s| {
+        rt::sleep(Duration::from_millis(5));
+        // Tasks can spawn more tasks into the scope.
+        s.spawn(rt::stack(), |_| {
+          rt::yield_now();
+          done.set(done.get() + 1)
+        });
+        done.set(done.get() + 1)
+      });
+    });
+    assert_eq!(done.get(), 2);
+  })
+}
+
+#[test]
+fn panic_propagates() {
+  rt::run(|| {
+    let cancelled = Cell::new(false);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+      rt::scope(|s| {
+        s.spawn(rt::stack(), |_| -> () { rt::yield_now(); panic!("foo") });
+        s.spawn(rt::stack(), |_| {
+          // The panic of the other task cancels this one.
+          let _guard = SetOnDrop(&cancelled);
+          loop { rt::yield_now() }
+        });
+      })
+    }));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"foo"));
+    assert!(cancelled.get());
+  })
+}
+
+#[test]
+fn joined_panic_does_not_propagate() {
+  rt::run(|| {
+    let result = rt::scope(|s| {
+      let handle = s.spawn(rt::stack(), |_| -> () { panic!("foo") });
+      handle.join().is_err()
+    });
+    assert!(result);
+  })
+}
+
+#[test]
+fn panic_of_unrelated_task() {
+  struct SleepOnDrop;
+  impl Drop for SleepOnDrop {
+    fn drop(&mut self) { rt::sleep(Duration::from_millis(10)) }
+  }
+
+  rt::run(|| {
+    // The task is suspended while it unwinds.
+    let unwinding = rt::spawn(rt::stack(), || {
+      let _ = panic::catch_unwind(|| { let _guard = SleepOnDrop; panic!("foo") });
+    });
+    rt::yield_now();
+    rt::scope(|s| {
+      s.spawn(rt::stack(), |_| ()).join().unwrap();
+      assert!(!s.token().is_cancelled());
+    });
+    unwinding.join().unwrap();
+  })
+}
+
+#[test]
+fn cancel() {
+  rt::run(|| {
+    let finished = Cell::new(false);
+    rt::scope(|s| {
+      let parked = s.spawn(rt::stack(), |_| loop { rt::park() });
+      let nested = s.spawn(rt::stack(), |_| {
+        rt::scope(|s| {
+          s.spawn(rt::stack(), |_| {
+            let _guard = SetOnDrop(&finished);
+            rt::sleep(Duration::from_secs(3600))
+          });
+        })
+      });
+      // Lets the innermost task start.
+      rt::yield_now();
+      rt::yield_now();
+      s.token().cancel();
+      assert!(parked.join().unwrap_err().is::<rt::Cancelled>());
+      // Nested scopes are cancelled along with the one they are in.
+      assert!(nested.join().unwrap_err().is::<rt::Cancelled>());
+      assert!(finished.get());
+      // Tasks spawned after cancelling never start.
+      let late = s.spawn(rt::stack(), |_| panic!("started"));
+      assert!(late.join().unwrap_err().is::<rt::Cancelled>());
+    })
+  })
+}
+
+#[test]
+fn timeout_cancels() {
+  rt::run(|| {
+    let finished = Cell::new(false);
+    let result = rt::timeout(Duration::from_millis(5), || {
+      rt::scope(|s| {
+        s.spawn(rt::stack(), |_| {
+          let _guard = SetOnDrop(&finished);
+          rt::sleep(Duration::from_secs(3600))
+        });
+      })
+    });
+    assert_eq!(result, Err(rt::Elapsed));
+    assert!(finished.get());
+  })
+}
+
+struct SetOnDrop<'a>(&'a Cell<bool>);
+
+impl<'a> Drop for SetOnDrop<'a> {
+  fn drop(&mut self) { self.0.set(true) }
+}
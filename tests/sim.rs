@@ -0,0 +1,214 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use fringe::rt::sim::{self, Simulation};
+
+/// Runs three tasks that log their steps, and returns the log.
+fn interleaving() -> Vec<(usize, usize)> {
+  let log = Rc::new(RefCell::new(Vec::new()));
+  let handles = (0..3).map(|task| {
+    let log = log.clone();
+    sim::spawn(move || {
+      for step in 0..4 {
+        log.borrow_mut().push((task, step));
+        sim::yield_now()
+      }
+    })
+  }).collect::<Vec<_>>();
+  for handle in handles { handle.join() }
+  let log = log.borrow().clone();
+  log
+}
+
+#[test]
+fn same_seed_same_order() {
+  for seed in 0..10 {
+    let first = Simulation::new(seed).run(interleaving).unwrap();
+    let second = Simulation::new(seed).run(interleaving).unwrap();
+    assert_eq!(first, second);
+  }
+}
+
+#[test]
+fn seeds_differ() {
+  let first = Simulation::new(0).run(interleaving).unwrap();
+  assert!((1..10).any(|seed| Simulation::new(seed).run(interleaving).unwrap() != first));
+}
+
+#[test]
+fn replay_seed() {
+  // A task that observes an unlucky order fails, and the seed reproduces it.
+  let racy = || {
+    let flag = Rc::new(Cell::new(false));
+    let setter = {
+      let flag = flag.clone();
+      sim::spawn(move || { sim::yield_now(); flag.set(true) })
+    };
+    let checker = {
+      let flag = flag.clone();
+      sim::spawn(move || { sim::yield_now(); assert!(flag.get(), "flag not set") })
+    };
+    setter.join();
+    checker.join();
+  };
+  let failure = (0..100).filter_map(|seed| Simulation::new(seed).run(racy).err()).next().unwrap();
+  assert!(failure.message().contains("flag not set"));
+  let seed = failure.seed().unwrap();
+  let replayed = Simulation::new(seed).run(racy).unwrap_err();
+  assert_eq!(replayed.schedule(), failure.schedule());
+  assert_eq!(replayed.steps(), failure.steps());
+  let scheduled = Simulation::with_schedule(failure.schedule()).run(racy).unwrap_err();
+  assert_eq!(scheduled.schedule(), failure.schedule());
+}
+
+#[test]
+#[should_panic(expected = "replay using Simulation::new(")]
+fn check_reports_seed() {
+  sim::check(100, || {
+    let counter = Rc::new(Cell::new(0));
+    let handles = (0..2).map(|_| {
+      let counter = counter.clone();
+      sim::spawn(move || {
+        let value = counter.get();
+        sim::yield_now();
+        counter.set(value + 1)
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles { handle.join() }
+    assert_eq!(counter.get(), 2)
+  })
+}
+
+#[test]
+fn explore_exhausts() {
+  // Two tasks with two steps each have six interleavings, and the main task
+  // never competes with them, since it is parked.
+  let orders = Rc::new(RefCell::new(Vec::new()));
+  let count = {
+    let orders = orders.clone();
+    sim::explore(1000, move || {
+      let log = Rc::new(RefCell::new(String::new()));
+      let handles = ["a", "b"].iter().map(|name| {
+        let log = log.clone();
+        sim::spawn(move || {
+          for _ in 0..2 {
+            log.borrow_mut().push_str(name);
+            sim::yield_now()
+          }
+        })
+      }).collect::<Vec<_>>();
+      for handle in handles { handle.join() }
+      orders.borrow_mut().push(log.borrow().clone());
+    })
+  };
+  let mut orders = orders.borrow().clone();
+  assert_eq!(orders.len(), count);
+  orders.sort();
+  orders.dedup();
+  assert_eq!(orders, ["aabb", "abab", "abba", "baab", "baba", "bbaa"]);
+}
+
+#[test]
+fn explore_bound() {
+  assert_eq!(sim::explore(5, interleaving), 5);
+}
+
+#[test]
+fn virtual_time() {
+  let (elapsed, order) = Simulation::new(7).run(|| {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let handles = [30, 10, 20].iter().map(|&millis| {
+      let order = order.clone();
+      sim::spawn(move || {
+        sim::sleep(Duration::from_millis(millis));
+        order.borrow_mut().push((millis, sim::now()))
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles { handle.join() }
+    let order = order.borrow().clone();
+    (sim::now(), order)
+  }).unwrap();
+  assert_eq!(elapsed, Duration::from_millis(30));
+  assert_eq!(order, [(10, Duration::from_millis(10)),
+                     (20, Duration::from_millis(20)),
+                     (30, Duration::from_millis(30))]);
+}
+
+#[test]
+fn long_sleep() {
+  let elapsed = Simulation::new(0).run(|| {
+    sim::sleep(Duration::from_secs(3600));
+    sim::now()
+  }).unwrap();
+  assert_eq!(elapsed, Duration::from_secs(3600));
+}
+
+#[test]
+fn deadlock() {
+  let failure = Simulation::new(0).run(|| {
+    sim::spawn(|| sim::park());
+    sim::park()
+  }).unwrap_err();
+  assert!(failure.message().contains("deadlock"));
+}
+
+#[test]
+fn step_limit() {
+  let mut simulation = Simulation::new(0);
+  simulation.set_max_steps(100);
+  let failure = simulation.run(|| loop { sim::yield_now() }).unwrap_err();
+  assert_eq!(failure.steps(), 100);
+  assert!(failure.message().contains("limit"));
+}
+
+#[test]
+fn failure_unwinds_tasks() {
+  struct Dropped(Rc<Cell<usize>>);
+  impl Drop for Dropped {
+    fn drop(&mut self) {
+      // Suspending while the simulation unwinds returns immediately.
+      sim::yield_now();
+      self.0.set(self.0.get() + 1)
+    }
+  }
+
+  let dropped = Rc::new(Cell::new(0));
+  let failure = {
+    let dropped = dropped.clone();
+    Simulation::new(0).run(move || {
+      for _ in 0..3 {
+        let guard = Dropped(dropped.clone());
+        sim::spawn(move || { let _guard = guard; sim::park() });
+      }
+      sim::yield_now();
+      panic!("foo")
+    }).unwrap_err()
+  };
+  assert_eq!(dropped.get(), 3);
+  assert_eq!(failure.into_payload().unwrap().downcast_ref::<&str>(), Some(&"foo"));
+}
+
+#[test]
+fn unpark_from_another_thread() {
+  Simulation::new(0).run(|| {
+    let task = sim::current();
+    assert!(thread::spawn(move || task.unpark()).join().is_err());
+  }).unwrap();
+}
+
+#[test]
+fn unpark_from_another_simulation() {
+  let task = Simulation::new(0).run(|| sim::current()).unwrap();
+  let failure = Simulation::new(0).run(move || task.unpark()).unwrap_err();
+  assert!(failure.message().contains("outside of its simulation"));
+}
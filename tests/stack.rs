@@ -15,7 +15,9 @@ use core::alloc::Layout;
 
 use alloc::boxed::Box;
 use std::slice;
-use fringe::{STACK_ALIGNMENT, Stack, SliceStack, OwnedStack, OsStack};
+use fringe::{STACK_ALIGNMENT, Stack, GuardedStack, SliceStack, OwnedStack, OsStack, PaintedStack, CheckedStack, Generator};
+use fringe::{GuardedSliceStack, GuardedOwnedStack};
+use fringe::generator::State;
 
 unsafe fn heap_allocate(size: usize, align: usize) -> *mut u8 {
   alloc(Layout::from_size_align_unchecked(size, align))
@@ -102,3 +104,168 @@ fn one_page_os_stack() {
   // Make sure the topmost page of the stack, at least, is accessible.
   unsafe { *(stack.base().offset(-1)) = 0; }
 }
+
+#[test]
+fn guarded_os_stack() {
+  let stack = OsStack::new(4096).unwrap();
+  assert_eq!(stack.guard_size(), 4096);
+  assert_eq!(stack.upper_guard_size(), 0);
+
+  let stack = OsStack::with_guard(4096, 5000).unwrap();
+  assert_eq!(stack.guard_size(), 8192);
+  assert_eq!(stack.base() as usize - stack.limit() as usize, 4096);
+
+  let stack = OsStack::with_guards(4096, 0, true).unwrap();
+  assert_eq!(stack.guard_size(), 4096);
+  assert_eq!(stack.upper_guard_size(), 4096);
+  assert_eq!(stack.base() as usize & (STACK_ALIGNMENT - 1), 0);
+  unsafe { *(stack.base().offset(-1)) = 0; }
+}
+
+#[repr(align(65536))]
+struct Memory([u8; 1 << 16]);
+
+#[test]
+fn guarded_slice_stack() {
+  let mut memory = Box::new(Memory([0; 1 << 16]));
+  let addr = memory.0.as_ptr() as usize;
+  {
+    let stack = unsafe { GuardedSliceStack::with_guard(&mut memory.0, 8192).unwrap() };
+    assert_eq!(stack.guard_size(), 8192);
+    assert_eq!(stack.limit() as usize, addr + 8192);
+    assert_eq!(stack.base() as usize & (STACK_ALIGNMENT - 1), 0);
+    let mut gen = unsafe {
+      Generator::unsafe_new(stack, |yielder, ()| yielder.suspend(1))
+    };
+    assert_eq!(gen.next(), Some(1));
+    assert_eq!(gen.next(), None);
+  }
+  // The guard area is accessible again.
+  memory.0[0] = 1;
+}
+
+#[test]
+#[should_panic(expected = "page boundary")]
+fn guarded_slice_stack_unaligned() {
+  let mut memory = Box::new(Memory([0; 1 << 16]));
+  let _ = unsafe { GuardedSliceStack::new(&mut memory.0[1..]) };
+}
+
+#[test]
+fn guarded_owned_stack() {
+  let stack = GuardedOwnedStack::new(1 << 16).unwrap();
+  assert_eq!(stack.guard_size(), 4096);
+  assert_eq!(stack.base() as usize - stack.limit() as usize, 1 << 16);
+  let mut gen = Generator::new(stack, |yielder, ()| yielder.suspend(1));
+  assert_eq!(gen.next(), Some(1));
+  assert_eq!(gen.next(), None);
+}
+
+#[test]
+fn painted_stack() {
+  let stack = PaintedStack::new(OsStack::new(1 << 16).unwrap());
+  assert_eq!(stack.high_water_mark(), Some(0));
+  unsafe { *stack.base().offset(-100) = 0 }
+  assert!(stack.high_water_mark().unwrap() >= 100);
+}
+
+#[test]
+fn painted_slice_stack() {
+  let mut memory = [0; 1025];
+  let mut stack = PaintedStack::new(SliceStack::new(&mut memory[1..]));
+  assert_eq!(stack.high_water_mark(), Some(0));
+  unsafe { *stack.limit() = 0 }
+  assert_eq!(stack.high_water_mark(), Some(stack.base() as usize - stack.limit() as usize));
+  stack.repaint();
+  assert_eq!(stack.high_water_mark(), Some(0));
+}
+
+#[test]
+fn generator_stack_usage() {
+  fn recurse(depth: usize) -> usize {
+    let buffer = [depth as u8; 256];
+    if depth == 0 { 0 } else { buffer.iter().map(|&x| x as usize).sum::<usize>() + recurse(depth - 1) }
+  }
+
+  let stack = PaintedStack::new(OsStack::new(1 << 20).unwrap());
+  let mut gen = Generator::new(stack, |yielder, ()| {
+    yielder.suspend(recurse(10));
+    yielder.suspend(recurse(100));
+  });
+  gen.next();
+  let shallow = gen.stack_usage().high_water_mark.unwrap();
+  gen.next();
+  let deep = gen.stack_usage().high_water_mark.unwrap();
+  assert!(shallow >= 10 * 256);
+  assert!(deep >= 100 * 256);
+  assert!(deep > shallow);
+  // Painting makes every page resident.
+  assert_eq!(gen.stack_usage().resident, Some(1 << 20));
+}
+
+#[test]
+fn generator_resident() {
+  let stack = OsStack::new(1 << 20).unwrap();
+  let mut gen = Generator::new(stack, |yielder, ()| {
+    yielder.suspend(());
+  });
+  gen.next();
+  let usage = gen.stack_usage();
+  assert_eq!(usage.high_water_mark, None);
+  let resident = usage.resident.unwrap();
+  assert!(resident > 0 && resident < 1 << 20);
+}
+
+/// Uses roughly `depth` kilobytes of stack, and calls `f` at the deepest point.
+fn use_stack<F: FnOnce()>(depth: usize, f: F) {
+  let mut buf = [0u8; 1024];
+  for byte in buf.iter_mut() { unsafe { std::ptr::write_volatile(byte, 1) } }
+  if depth == 0 { f() } else { use_stack(depth - 1, f) }
+  unsafe { std::ptr::read_volatile(&buf[0]); }
+}
+
+#[test]
+fn checked_stack() {
+  let mut memory = [0u8; 64 << 10];
+  let stack = CheckedStack::new(SliceStack::new(&mut memory[..]));
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, |yielder, ()| {
+      use_stack(16, || ());
+      yielder.suspend(());
+    })
+  };
+  assert_eq!(gen.next(), Some(()));
+  assert_eq!(gen.next(), None);
+}
+
+#[test]
+fn checked_stack_canary() {
+  // The lower half of the memory is there for the generator to overflow into.
+  let mut memory = [0u8; 128 << 10];
+  let stack = CheckedStack::new(SliceStack::new(&mut memory[64 << 10..]));
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, |yielder, ()| {
+      use_stack(80, || ());
+      yielder.suspend(());
+    })
+  };
+  let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| gen.next()));
+  let payload = result.unwrap_err();
+  assert!(payload.downcast_ref::<String>().unwrap().contains("generator stack overflow"));
+  assert_eq!(gen.state(), State::Poisoned);
+}
+
+#[test]
+fn checked_stack_stack_pointer() {
+  let mut memory = [0u8; 128 << 10];
+  let stack = CheckedStack::new(SliceStack::new(&mut memory[64 << 10..]));
+  let mut gen = unsafe {
+    Generator::unsafe_new(stack, |yielder, ()| {
+      use_stack(80, || yielder.suspend(()));
+    })
+  };
+  let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| gen.next()));
+  let payload = result.unwrap_err();
+  assert!(payload.downcast_ref::<String>().unwrap().contains("stack pointer"));
+  assert_eq!(gen.state(), State::Poisoned);
+}
@@ -0,0 +1,295 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+#![cfg(unix)]
+extern crate fringe;
+
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use fringe::{Generator, OsStack};
+use fringe::rt::{self, mt, sim};
+use fringe::sync::{Mutex, Condvar, RwLock, Semaphore, Barrier, OnceCell, Park};
+use common::RoundRobin;
+
+#[test]
+fn mutex_excludes() {
+  rt::run(|| {
+    let mutex = Rc::new(Mutex::new(0));
+    let inside = Rc::new(Cell::new(false));
+    let handles = (0..5).map(|_| {
+      let (mutex, inside) = (mutex.clone(), inside.clone());
+      rt::spawn(rt::stack(), move || {
+        for _ in 0..10 {
+          let mut guard = mutex.lock();
+          assert!(!inside.replace(true));
+          rt::yield_now();
+          *guard += 1;
+          inside.set(false)
+        }
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles { handle.join().unwrap() }
+    assert_eq!(*mutex.lock(), 50);
+  })
+}
+
+#[test]
+fn mutex_executor() {
+  let executor = mt::Executor::new(4);
+  let mutex = Arc::new(Mutex::new(0));
+  let handles = (0..8).map(|_| {
+    let mutex = mutex.clone();
+    unsafe {
+      executor.spawn(move || {
+        for _ in 0..200 {
+          let mut guard = mutex.lock();
+          let value = *guard;
+          mt::yield_now();
+          *guard = value + 1
+        }
+      })
+    }
+  }).collect::<Vec<_>>();
+  for handle in handles { handle.join().unwrap() }
+  assert_eq!(*mutex.lock(), 1600);
+}
+
+#[test]
+fn mutex_threads() {
+  let mutex = Arc::new(Mutex::new(0));
+  let threads = (0..4).map(|_| {
+    let mutex = mutex.clone();
+    thread::spawn(move || {
+      for _ in 0..1000 { *mutex.lock() += 1 }
+    })
+  }).collect::<Vec<_>>();
+  for thread in threads { thread.join().unwrap() }
+  assert_eq!(*mutex.lock(), 4000);
+}
+
+#[test]
+fn mutex_custom_park() {
+  let log = Mutex::<_, RoundRobin>::with_park(Vec::new());
+  let mut generators = (0..3).map(|i| {
+    let log = &log;
+    Generator::new(OsStack::new(1 << 16).unwrap(), move |yielder, ()| {
+      common::enter(yielder);
+      let mut guard = log.lock();
+      guard.push(i);
+      RoundRobin::park();
+      guard.push(i);
+    })
+  }).collect::<Vec<Generator<(), (), OsStack>>>();
+  common::run(&mut generators);
+  drop(generators);
+  assert_eq!(log.into_inner(), [0, 0, 1, 1, 2, 2]);
+}
+
+#[test]
+fn mutex_waiter_times_out() {
+  rt::run(|| {
+    let mutex = Rc::new(Mutex::new(0));
+    let holder = {
+      let mutex = mutex.clone();
+      rt::spawn(rt::stack(), move || {
+        let mut guard = mutex.lock();
+        rt::sleep(Duration::from_millis(50));
+        *guard += 1
+      })
+    };
+    rt::yield_now();
+    let result = rt::timeout(Duration::from_millis(5), || *mutex.lock() += 1);
+    assert_eq!(result, Err(rt::Elapsed));
+    holder.join().unwrap();
+    // The waiter that gave up is not handed the lock.
+    assert_eq!(*mutex.try_lock().unwrap(), 1);
+  })
+}
+
+#[test]
+fn mutex_explore() {
+  sim::explore(10000, || {
+    let mutex = Rc::new(Mutex::new(0));
+    let handles = (0..3).map(|_| {
+      let mutex = mutex.clone();
+      sim::spawn(move || {
+        let mut guard = mutex.lock();
+        let value = *guard;
+        sim::yield_now();
+        *guard = value + 1
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles { handle.join() }
+    assert_eq!(*mutex.lock(), 3);
+  });
+}
+
+#[test]
+fn condvar_queue() {
+  rt::run(|| {
+    let queue = Rc::new((Mutex::new(Vec::new()), Condvar::new()));
+    let consumer = {
+      let queue = queue.clone();
+      rt::spawn(rt::stack(), move || {
+        let mut received = Vec::new();
+        while received.len() < 10 {
+          let mut guard = queue.1.wait_while(queue.0.lock(), |items| items.is_empty());
+          received.extend(guard.drain(..));
+        }
+        received
+      })
+    };
+    for i in 0..10 {
+      queue.0.lock().push(i);
+      queue.1.notify_one();
+      if i % 3 == 0 { rt::yield_now() }
+    }
+    assert_eq!(consumer.join().unwrap(), (0..10).collect::<Vec<_>>());
+  })
+}
+
+#[test]
+fn condvar_notify_all() {
+  let executor = mt::Executor::new(2);
+  let state = Arc::new((Mutex::new(false), Condvar::new()));
+  let handles = (0..5).map(|_| {
+    let state = state.clone();
+    unsafe {
+      executor.spawn(move || {
+        let guard = state.1.wait_while(state.0.lock(), |ready| !*ready);
+        assert!(*guard)
+      })
+    }
+  }).collect::<Vec<_>>();
+  thread::sleep(Duration::from_millis(10));
+  *state.0.lock() = true;
+  state.1.notify_all();
+  for handle in handles { handle.join().unwrap() }
+}
+
+#[test]
+fn rwlock_readers_share() {
+  rt::run(|| {
+    let lock = Rc::new(RwLock::new(0));
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let handles = ["r1", "r2", "w", "r3"].iter().map(|&name| {
+      let (lock, log) = (lock.clone(), log.clone());
+      rt::spawn(rt::stack(), move || {
+        if name == "w" {
+          let mut guard = lock.write();
+          log.borrow_mut().push("w+");
+          rt::yield_now();
+          *guard += 1;
+          log.borrow_mut().push("w-");
+        } else {
+          let guard = lock.read();
+          log.borrow_mut().push(name);
+          rt::yield_now();
+          rt::yield_now();
+          drop(guard)
+        }
+      })
+    }).collect::<Vec<_>>();
+    for handle in handles { handle.join().unwrap() }
+    // Both readers get in together, the writer waits for them, and the reader
+    // that arrives after the writer waits for the writer.
+    assert_eq!(*log.borrow(), ["r1", "r2", "w+", "w-", "r3"]);
+    assert_eq!(*lock.read(), 1);
+  })
+}
+
+#[test]
+fn semaphore_limits() {
+  let executor = mt::Executor::new(4);
+  let semaphore = Arc::new(Semaphore::new(2));
+  let current = Arc::new(AtomicUsize::new(0));
+  let handles = (0..8).map(|_| {
+    let (semaphore, current) = (semaphore.clone(), current.clone());
+    unsafe {
+      executor.spawn(move || {
+        for _ in 0..20 {
+          let _permit = semaphore.acquire();
+          assert!(current.fetch_add(1, Ordering::SeqCst) < 2);
+          mt::yield_now();
+          current.fetch_sub(1, Ordering::SeqCst);
+        }
+      })
+    }
+  }).collect::<Vec<_>>();
+  for handle in handles { handle.join().unwrap() }
+  assert_eq!(semaphore.available_permits(), 2);
+}
+
+#[test]
+fn barrier_reuse() {
+  rt::run(|| {
+    let barrier = Rc::new(Barrier::new(4));
+    let arrived = Rc::new(Cell::new(0));
+    let handles = (0..4).map(|_| {
+      let (barrier, arrived) = (barrier.clone(), arrived.clone());
+      rt::spawn(rt::stack(), move || {
+        let mut leaders = 0;
+        for round in 1..4 {
+          arrived.set(arrived.get() + 1);
+          if barrier.wait().is_leader() { leaders += 1 }
+          // Nobody passes until everybody has arrived.
+          assert!(arrived.get() >= round * 4);
+          rt::yield_now()
+        }
+        leaders
+      })
+    }).collect::<Vec<_>>();
+    let leaders: usize = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+    assert_eq!(leaders, 3);
+  })
+}
+
+#[test]
+fn once_cell_initializes_once() {
+  rt::run(|| {
+    let cell = Rc::new(OnceCell::new());
+    let calls = Rc::new(Cell::new(0));
+    let handles = (0..4).map(|i| {
+      let (cell, calls) = (cell.clone(), calls.clone());
+      rt::spawn(rt::stack(), move || {
+        *cell.get_or_init(|| {
+          calls.set(calls.get() + 1);
+          rt::yield_now();
+          i
+        })
+      })
+    }).collect::<Vec<_>>();
+    let values = handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+    assert_eq!(values, [0, 0, 0, 0]);
+    assert_eq!(calls.get(), 1);
+    assert_eq!(cell.set(5), Err(5));
+  })
+}
+
+#[test]
+fn once_cell_retries_after_panic() {
+  rt::run(|| {
+    let cell = Rc::new(OnceCell::new());
+    let failing = {
+      let cell = cell.clone();
+      rt::spawn(rt::stack(), move || {
+        cell.get_or_init(|| -> u32 { rt::yield_now(); panic!("foo") });
+      })
+    };
+    let waiting = {
+      let cell = cell.clone();
+      rt::spawn(rt::stack(), move || *cell.get_or_init(|| 7))
+    };
+    assert!(failing.join().is_err());
+    assert_eq!(waiting.join().unwrap(), 7);
+  })
+}
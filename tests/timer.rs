@@ -0,0 +1,141 @@
+// This file is part of libfringe, a low-level green threading library.
+// Copyright (c) edef <edef@edef.eu>
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+extern crate fringe;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use fringe::rt;
+
+fn ms(millis: u64) -> Duration {
+  Duration::from_millis(millis)
+}
+
+#[test]
+fn sleep() {
+  rt::run(|| {
+    let start = Instant::now();
+    rt::sleep(ms(20));
+    assert!(start.elapsed() >= ms(20));
+  })
+}
+
+#[test]
+fn sleep_until_past() {
+  rt::run(|| {
+    let deadline = Instant::now();
+    rt::sleep(ms(5));
+    rt::sleep_until(deadline);
+  })
+}
+
+#[test]
+fn sleep_order() {
+  let order = Rc::new(RefCell::new(Vec::new()));
+  {
+    let order = order.clone();
+    rt::run(move || {
+      // 150ms and 300ms are past the first level of the wheel.
+      for &delay in &[300, 30, 150, 10, 20] {
+        let order = order.clone();
+        rt::spawn(rt::stack(), move || {
+          rt::sleep(ms(delay));
+          order.borrow_mut().push(delay)
+        });
+      }
+    });
+  }
+  assert_eq!(*order.borrow(), [10, 20, 30, 150, 300]);
+}
+
+#[test]
+fn sleep_while_others_run() {
+  rt::run(|| {
+    let sleeper = rt::spawn(rt::stack(), || rt::sleep(ms(10)));
+    let start = Instant::now();
+    while !sleeper.is_finished() {
+      assert!(start.elapsed() < Duration::from_secs(10));
+      rt::yield_now()
+    }
+  })
+}
+
+#[test]
+fn timeout_elapsed() {
+  struct SetOnDrop(Rc<Cell<bool>>);
+  impl Drop for SetOnDrop {
+    fn drop(&mut self) { self.0.set(true) }
+  }
+
+  rt::run(|| {
+    let dropped = Rc::new(Cell::new(false));
+    let start = Instant::now();
+    let guard = SetOnDrop(dropped.clone());
+    let result = rt::timeout(ms(10), move || {
+      let _guard = guard;
+      rt::sleep(Duration::from_secs(3600))
+    });
+    assert_eq!(result, Err(rt::Elapsed));
+    assert!(dropped.get());
+    assert!(start.elapsed() < Duration::from_secs(3600));
+  })
+}
+
+#[test]
+fn timeout_completes() {
+  rt::run(|| {
+    assert_eq!(rt::timeout(ms(10), || 42), Ok(42));
+    // The cancelled deadline must not affect the task afterwards.
+    rt::sleep(ms(20));
+    rt::yield_now();
+  })
+}
+
+#[test]
+fn timeout_nested() {
+  rt::run(|| {
+    let result = rt::timeout(ms(10), || {
+      rt::timeout(Duration::from_secs(3600), || rt::sleep(Duration::from_secs(3600)))
+    });
+    assert_eq!(result, Err(rt::Elapsed));
+
+    let result = rt::timeout(Duration::from_secs(3600), || {
+      rt::timeout(ms(10), || rt::sleep(Duration::from_secs(3600)))
+    });
+    assert_eq!(result, Ok(Err(rt::Elapsed)));
+  })
+}
+
+#[test]
+fn timeout_busy() {
+  rt::run(|| {
+    let result = rt::timeout(ms(10), || loop { rt::yield_now() });
+    assert_eq!(result, Err::<(), _>(rt::Elapsed));
+  })
+}
+
+#[test]
+#[should_panic(expected = "foo")]
+fn timeout_propagates_panic() {
+  rt::run(|| {
+    let _ = rt::timeout(ms(10), || -> () { panic!("foo") });
+  })
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn timeout_io() {
+  use std::io::Read;
+  use fringe::rt::net::UnixStream;
+
+  rt::run(|| {
+    let (mut a, _b) = UnixStream::pair().unwrap();
+    let mut buf = [0; 1];
+    let result = rt::timeout(ms(10), || a.read(&mut buf).unwrap());
+    assert_eq!(result, Err(rt::Elapsed));
+  })
+}